@@ -0,0 +1,2 @@
+ALTER TABLE `users` ADD COLUMN `suspended` BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE `users` ADD COLUMN `follow_quota` INT NOT NULL DEFAULT 0 /* 0 不限制 */;
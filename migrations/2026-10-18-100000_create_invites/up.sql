@@ -0,0 +1,9 @@
+CREATE TABLE `invites` (
+  `code` VARCHAR(32) NOT NULL PRIMARY KEY /* 邀请码 */,
+  `label` VARCHAR(8) NOT NULL /* 默认备注名 */,
+  `max_uses` INT NOT NULL /* 可使用次数 */,
+  `used_count` INT NOT NULL DEFAULT 0 /* 已使用次数 */,
+  `expires_at` DATETIME NOT NULL /* 过期时间 */,
+  `created_by` BIGINT UNSIGNED NOT NULL /* 创建者(telegram)ID */,
+  `created_at` DATETIME NOT NULL /* 创建时间 */
+);
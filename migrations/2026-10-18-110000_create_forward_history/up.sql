@@ -0,0 +1,4 @@
+CREATE TABLE `forward_history` (
+  `cache_key` VARCHAR(32) NOT NULL PRIMARY KEY /* md5(telegram ID-推文链接) */,
+  `expires_at` DATETIME NOT NULL /* 过期时间 */
+);
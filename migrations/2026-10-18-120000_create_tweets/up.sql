@@ -0,0 +1,23 @@
+CREATE TABLE `tweets` (
+  `id` INTEGER NOT NULL PRIMARY KEY /* 推文 ID，转推记录原推文 */,
+  `twitter_user_id` BIGINT UNSIGNED NOT NULL /* 作者 ID */,
+  `screen_name` VARCHAR(64) NOT NULL /* 作者用户名 */,
+  `text` TEXT NOT NULL /* 推文内容 */,
+  `media_urls` TEXT NOT NULL /* 媒体链接，换行分隔 */,
+  `url` VARCHAR(128) NOT NULL /* 推文链接 */,
+  `created_at` DATETIME NOT NULL /* 发推时间 */
+);
+CREATE INDEX `tweets_created_at` ON `tweets` (`created_at`);
+CREATE TABLE `tweet_recipients` (
+  `tweet_id` BIGINT UNSIGNED NOT NULL /* 推文 ID */,
+  `user_id` BIGINT UNSIGNED NOT NULL /* 收到推送的(telegram)ID */,
+  PRIMARY KEY (`tweet_id`, `user_id`)
+);
+CREATE INDEX `tweet_recipients_user_id` ON `tweet_recipients` (`user_id`);
+CREATE VIRTUAL TABLE `tweets_fts` USING fts5(`text`, content = 'tweets', content_rowid = 'id');
+CREATE TRIGGER `tweets_fts_insert` AFTER INSERT ON `tweets` BEGIN
+  INSERT INTO `tweets_fts` (rowid, `text`) VALUES (new.`id`, new.`text`);
+END;
+CREATE TRIGGER `tweets_fts_delete` AFTER DELETE ON `tweets` BEGIN
+  INSERT INTO `tweets_fts` (`tweets_fts`, rowid, `text`) VALUES ('delete', old.`id`, old.`text`);
+END;
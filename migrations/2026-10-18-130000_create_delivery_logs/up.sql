@@ -0,0 +1,11 @@
+CREATE TABLE `delivery_logs` (
+  `id` INTEGER PRIMARY KEY AUTOINCREMENT,
+  `user_id` BIGINT UNSIGNED NOT NULL /* 用户(telegram)ID */,
+  `tweet_id` BIGINT UNSIGNED NOT NULL /* 推文 ID，转推时为原推文 */,
+  `status_id` BIGINT UNSIGNED NOT NULL /* stream 收到的推文 ID，转推时为转推本身 */,
+  `screen_name` VARCHAR(64) NOT NULL /* 发推或转推的 Twitter 用户名 */,
+  `decision` INT NOT NULL /* 推送结果 */,
+  `detail` TEXT NOT NULL /* 附加说明，如发送失败的错误 */,
+  `created_at` DATETIME NOT NULL /* 记录时间 */
+);
+CREATE INDEX `delivery_logs_user_id` ON `delivery_logs` (`user_id`, `tweet_id`);
@@ -0,0 +1 @@
+ALTER TABLE `users` ADD COLUMN `dry_run` BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1 @@
+ALTER TABLE `users` ADD COLUMN `remove_deleted` BOOLEAN NOT NULL DEFAULT false /* 推文删除后删除消息，否则标记 */;
@@ -0,0 +1,11 @@
+DROP TRIGGER `tweets_fts_delete`;
+DROP TRIGGER `tweets_fts_insert`;
+DROP TABLE `tweets_fts`;
+CREATE VIRTUAL TABLE `tweets_fts` USING fts5(`text`, content = 'tweets', content_rowid = 'id');
+CREATE TRIGGER `tweets_fts_insert` AFTER INSERT ON `tweets` BEGIN
+  INSERT INTO `tweets_fts` (rowid, `text`) VALUES (new.`id`, new.`text`);
+END;
+CREATE TRIGGER `tweets_fts_delete` AFTER DELETE ON `tweets` BEGIN
+  INSERT INTO `tweets_fts` (`tweets_fts`, rowid, `text`) VALUES ('delete', old.`id`, old.`text`);
+END;
+INSERT INTO `tweets_fts` (`tweets_fts`) VALUES ('rebuild');
@@ -0,0 +1,12 @@
+-- 默认的 unicode61 分词不切分中日韩文字，改用 trigram 按任意三个字符匹配
+DROP TRIGGER `tweets_fts_delete`;
+DROP TRIGGER `tweets_fts_insert`;
+DROP TABLE `tweets_fts`;
+CREATE VIRTUAL TABLE `tweets_fts` USING fts5(`text`, content = 'tweets', content_rowid = 'id', tokenize = 'trigram');
+CREATE TRIGGER `tweets_fts_insert` AFTER INSERT ON `tweets` BEGIN
+  INSERT INTO `tweets_fts` (rowid, `text`) VALUES (new.`id`, new.`text`);
+END;
+CREATE TRIGGER `tweets_fts_delete` AFTER DELETE ON `tweets` BEGIN
+  INSERT INTO `tweets_fts` (`tweets_fts`, rowid, `text`) VALUES ('delete', old.`id`, old.`text`);
+END;
+INSERT INTO `tweets_fts` (`tweets_fts`) VALUES ('rebuild');
@@ -0,0 +1,7 @@
+CREATE TABLE users (
+  id BIGINT NOT NULL PRIMARY KEY /* TG用户ID */,
+  label TEXT NOT NULL /* 备注名 */,
+  twitter_access_token TEXT,
+  twitter_status BOOLEAN NOT NULL,
+  created_at TIMESTAMP NOT NULL /* 创建时间 */
+);
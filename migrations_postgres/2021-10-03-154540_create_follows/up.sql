@@ -0,0 +1,8 @@
+CREATE TABLE follows (
+  id SERIAL PRIMARY KEY,
+  user_id BIGINT NOT NULL /* 用户(telegram)ID */,
+  twitter_user_id BIGINT NOT NULL /* Twitter 用户ID */,
+  twitter_username TEXT NOT NULL /* Twitter 用户名 */,
+  created_at TIMESTAMP NOT NULL /* 创建时间 */,
+  UNIQUE (user_id, twitter_user_id)
+);
@@ -0,0 +1,10 @@
+DROP TABLE blacklists;
+CREATE TABLE blacklists (
+  id SERIAL PRIMARY KEY,
+  user_id BIGINT NOT NULL /* 用户(telegram)ID */,
+  twitter_user_id BIGINT NOT NULL /* Twitter 用户ID */,
+  twitter_username TEXT NOT NULL /* Twitter 用户名 */,
+  created_at TIMESTAMP NOT NULL /* 创建时间 */,
+  type INT NOT NULL /* 1 禁止此人转发 2 屏蔽此人转发 */
+);
+CREATE UNIQUE INDEX idx_block ON blacklists (type, user_id, twitter_user_id);
@@ -0,0 +1,2 @@
+ALTER TABLE follows DROP COLUMN follow_rt_count;
+ALTER TABLE follows DROP COLUMN block_rt_count;
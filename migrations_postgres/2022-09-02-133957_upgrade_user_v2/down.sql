@@ -0,0 +1,2 @@
+ALTER TABLE users DROP COLUMN disable_retweet;
+ALTER TABLE users DROP COLUMN disable_text_msg;
@@ -0,0 +1,5 @@
+CREATE TABLE roles (
+  user_id BIGINT NOT NULL PRIMARY KEY /* 用户(telegram)ID */,
+  role INT NOT NULL /* 1 只读 2 用户 3 管理员 4 所有者 */,
+  created_at TIMESTAMP NOT NULL /* 创建时间 */
+);
@@ -0,0 +1,4 @@
+CREATE TABLE forward_history (
+  cache_key TEXT NOT NULL PRIMARY KEY /* md5(telegram ID-推文链接) */,
+  expires_at TIMESTAMP NOT NULL /* 过期时间 */
+);
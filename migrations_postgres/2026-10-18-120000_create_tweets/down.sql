@@ -0,0 +1,2 @@
+DROP TABLE tweet_recipients;
+DROP TABLE tweets;
@@ -0,0 +1,17 @@
+CREATE TABLE tweets (
+  id BIGINT NOT NULL PRIMARY KEY /* 推文 ID，转推记录原推文 */,
+  twitter_user_id BIGINT NOT NULL /* 作者 ID */,
+  screen_name TEXT NOT NULL /* 作者用户名 */,
+  text TEXT NOT NULL /* 推文内容 */,
+  media_urls TEXT NOT NULL /* 媒体链接，换行分隔 */,
+  url TEXT NOT NULL /* 推文链接 */,
+  created_at TIMESTAMP NOT NULL /* 发推时间 */
+);
+CREATE INDEX tweets_created_at ON tweets (created_at);
+CREATE INDEX tweets_text_search ON tweets USING GIN (to_tsvector('simple', text));
+CREATE TABLE tweet_recipients (
+  tweet_id BIGINT NOT NULL /* 推文 ID */,
+  user_id BIGINT NOT NULL /* 收到推送的(telegram)ID */,
+  PRIMARY KEY (tweet_id, user_id)
+);
+CREATE INDEX tweet_recipients_user_id ON tweet_recipients (user_id);
@@ -0,0 +1,13 @@
+CREATE TABLE sent_messages (
+  id SERIAL PRIMARY KEY,
+  user_id BIGINT NOT NULL /* 用户(telegram)ID */,
+  tweet_id BIGINT NOT NULL /* 推文 ID，转推时为原推文 */,
+  status_id BIGINT NOT NULL /* stream 收到的推文 ID，转推时为转推本身 */,
+  message_id INT NOT NULL /* telegram 消息 ID */,
+  media_message_ids TEXT NOT NULL /* 媒体组的 telegram 消息 ID，逗号分隔 */,
+  text TEXT NOT NULL /* 发送的消息内容，标记删除时重新编辑 */,
+  created_at TIMESTAMP NOT NULL /* 发送时间 */
+);
+CREATE INDEX sent_messages_tweet_id ON sent_messages (tweet_id);
+CREATE INDEX sent_messages_status_id ON sent_messages (status_id);
+CREATE INDEX sent_messages_created_at ON sent_messages (created_at);
@@ -0,0 +1,2 @@
+DROP INDEX tweets_text_trgm;
+CREATE INDEX tweets_text_search ON tweets USING GIN (to_tsvector('simple', text));
@@ -0,0 +1,4 @@
+-- simple 分词不切分中日韩文字，改用 pg_trgm 索引加速 ILIKE 子串匹配
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+DROP INDEX tweets_text_search;
+CREATE INDEX tweets_text_trgm ON tweets USING GIN (text gin_trgm_ops);
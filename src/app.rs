@@ -0,0 +1,354 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+    sync::Arc,
+    time::Duration,
+};
+
+use egg_mode::stream::StreamMessage;
+use futures::Future;
+use log::{error, info, warn};
+use r_cache::cache::Cache;
+use teloxide::{
+    adaptors::{AutoSend, DefaultParseMode},
+    prelude::{Requester, RequesterExt},
+    types::{ParseMode, UserId},
+    utils::markdown::escape,
+    Bot,
+};
+use tokio::sync::{mpsc, watch};
+
+use crate::{
+    backup,
+    config::Config,
+    http_server::{self, HttpState},
+    models::{
+        blacklist_model, establish_connection, follow_model, forward_history_model, run_migrations,
+        user_model::{self, User},
+        Repository,
+    },
+    recorder, telegram_bot, token_cipher,
+    twitter_subscriber::{ForwardHistoryCache, SubscriberHandle, TwitterSubscriber},
+};
+
+// 启动所有组件，shutdown 完成后按顺序退出
+pub async fn run(config: Arc<Config>, shutdown: impl Future<Output = ()>) {
+    let db = Repository::new(establish_connection(&config.database));
+
+    // auto migration
+    info!("migration {:?}", db.run(run_migrations).await);
+
+    // 加载 token 密钥，并加密之前以明文保存的 token
+    if let Err(e) = token_cipher::init(&config.database) {
+        error!("{:?}", e);
+        return;
+    }
+    if !token_cipher::enabled() {
+        warn!("database.token_key is not configured, twitter tokens are stored in plain text");
+    }
+    if let Err(e) = recorder::init(&config.recorder) {
+        error!("open recorder {} {:?}", config.recorder.file, e);
+    }
+    match db.run(user_model::encrypt_plaintext_tokens).await {
+        Ok(0) => {}
+        Ok(count) => info!("encrypted {} plain text twitter tokens", count),
+        Err(e) => error!("encrypt twitter tokens {:?}", e),
+    }
+
+    let cache_instance: Arc<Cache<i64, egg_mode::KeyPair>> =
+        Arc::new(Cache::new(Some(config.twitter.request_token_ttl())));
+    tokio::spawn({
+        let cache = Arc::clone(&cache_instance);
+        let interval = config.twitter.request_token_cleanup();
+        async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                cache.remove_expired().await;
+            }
+        }
+    });
+
+    let bot = new_bot(&config);
+
+    let mut tg_ctx = telegram_bot::TelegramContext::new(
+        "T2TBot".to_string(),
+        cache_instance,
+        db.clone(),
+        config.clone(),
+    );
+
+    let (tx, rx) = mpsc::channel::<StreamMessage>(config.forwarder.channel_capacity);
+
+    // 加载黑名单列表
+    let blacklist_map = load_blacklist_map(&db).await;
+
+    // 取到所有 twitter token 有效的用户
+    let user_vec = match db.run(user_model::get_twitter_users).await {
+        Ok(user_vec) => user_vec,
+        Err(e) => {
+            error!("load twitter users {:?}", e);
+            return;
+        }
+    };
+
+    let ts = TwitterSubscriber::spawn(tx, bot.clone(), blacklist_map, &user_vec, config.clone());
+    tg_ctx.set_twitter_subscriber(Some(ts.clone()));
+
+    let ts_clone = ts.clone();
+    let bot_clone = bot.clone();
+    let db_clone = db.clone();
+    tokio::spawn(async {
+        run_twitter_subscriber(bot_clone, ts_clone, db_clone, user_vec).await;
+    });
+
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+
+    // 读回上次退出时保存的推送记录
+    let now = chrono::Utc::now().naive_utc();
+    let history = match db
+        .run(move |conn| forward_history_model::get_forward_history(conn, now))
+        .await
+    {
+        Ok(records) => records,
+        Err(e) => {
+            error!("load forward history {:?}", e);
+            Vec::new()
+        }
+    };
+    let forward_history = ForwardHistoryCache::new(config.forwarder.history_ttl(), history);
+    let forwarder = tokio::spawn(TwitterSubscriber::forward_tweet(
+        forward_history,
+        ts.clone(),
+        db.clone(),
+        bot.clone(),
+        rx,
+        shutdown_rx.clone(),
+    ));
+
+    if config.backup.enabled() {
+        tokio::spawn(backup::run_scheduled(db.clone(), config.backup.clone()));
+    }
+
+    if let Some(listen) = config.http.listen_addr() {
+        let state = Arc::new(HttpState {
+            db: db.clone(),
+            twitter_subscriber: ts.clone(),
+        });
+        tokio::spawn(http_server::run(listen, state));
+    }
+
+    let mut dispatcher = tokio::spawn(telegram_bot::run(
+        bot.clone(),
+        Arc::new(tg_ctx),
+        shutdown_rx,
+    ));
+
+    tokio::select! {
+        _ = shutdown => info!("shutdown signal received"),
+        _ = &mut dispatcher => error!("telegram dispatcher exited"),
+    }
+
+    // 先关闭 stream，再让转发队列在期限内处理完剩余消息
+    if let Err(e) = ts.stop_streams().await {
+        error!("stop streams {:?}", e);
+    }
+    let _ = shutdown_tx.send(true);
+    let shutdown_timeout = config.forwarder.shutdown_timeout();
+    match tokio::time::timeout(shutdown_timeout + Duration::from_secs(5), forwarder).await {
+        Ok(Ok(forward_history)) => {
+            let records = forward_history.into_records();
+            match db
+                .run(move |conn| forward_history_model::replace_forward_history(conn, records))
+                .await
+            {
+                Ok(count) => info!("flushed {} forward history records", count),
+                Err(e) => error!("flush forward history {:?}", e),
+            }
+        }
+        Ok(Err(e)) => error!("forward_tweet {:?}", e),
+        Err(_) => error!("forward_tweet did not stop in time"),
+    }
+    if !dispatcher.is_finished()
+        && tokio::time::timeout(shutdown_timeout, dispatcher)
+            .await
+            .is_err()
+    {
+        error!("telegram dispatcher did not stop in time");
+    }
+    info!("shutdown complete");
+}
+
+fn new_bot(config: &Config) -> AutoSend<DefaultParseMode<Bot>> {
+    teloxide::Bot::new(&config.telegram.bot_token)
+        .set_api_url(url::Url::parse(&config.telegram.api_url).unwrap())
+        .parse_mode(ParseMode::MarkdownV2)
+        .auto_send()
+}
+
+async fn load_blacklist_map(db: &Repository) -> HashMap<i64, HashSet<(i64, i32)>> {
+    let mut blacklist_map: HashMap<i64, HashSet<(i64, i32)>> = HashMap::new();
+    let res = db.run(blacklist_model::get_all_blacklist).await;
+    if let Ok(list) = res {
+        for item in list {
+            let inner_list = blacklist_map.get_mut(&item.user_id);
+            if let Some(inner_list) = inner_list {
+                inner_list.insert((item.twitter_user_id, item.type_));
+            } else {
+                blacklist_map.insert(
+                    item.user_id,
+                    HashSet::from([(item.twitter_user_id, item.type_)]),
+                );
+            }
+        }
+    }
+    blacklist_map
+}
+
+// 回放录制的推文时不按发推时间过滤
+const REPLAY_MAX_TWEET_AGE_DAYS: i64 = 365 * 100;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReplayOptions {
+    // 按录制时推文之间的间隔除以 speed 等待，0 为不等待
+    pub speed: f64,
+    // 默认试运行，同 forwarder.dry_run，为 true 时才推送给用户
+    pub live: bool,
+}
+
+// 把录制的 stream 消息逐行送入转发队列，返回送入的消息数
+// 不连接 twitter stream，也不处理 telegram 命令
+pub async fn replay(
+    config: Arc<Config>,
+    file: &Path,
+    options: ReplayOptions,
+) -> Result<usize, anyhow::Error> {
+    let content = tokio::fs::read_to_string(file).await?;
+    let mut config = (*config).clone();
+    config.forwarder.max_tweet_age_days = REPLAY_MAX_TWEET_AGE_DAYS;
+    config.forwarder.dry_run |= !options.live;
+    let config = Arc::new(config);
+    let db = Repository::new(establish_connection(&config.database));
+    token_cipher::init(&config.database)?;
+    let bot = new_bot(&config);
+
+    let (tx, rx) = mpsc::channel::<StreamMessage>(config.forwarder.channel_capacity);
+    let blacklist_map = load_blacklist_map(&db).await;
+    let user_vec = db.run(user_model::get_twitter_users).await?;
+    let ts = TwitterSubscriber::spawn(
+        tx.clone(),
+        bot.clone(),
+        blacklist_map,
+        &user_vec,
+        config.clone(),
+    );
+    // 先停掉 stream，之后加入的 token 不会连接 twitter
+    ts.stop_streams().await?;
+    for u in &user_vec {
+        ts.load_token(u.id, u.twitter_access_token.as_ref().unwrap())
+            .await?;
+    }
+    let user_ids = user_vec.iter().map(|u| u.id).collect::<Vec<_>>();
+    for f in db
+        .run(move |conn| follow_model::get_follows_by_user_ids(conn, &user_ids))
+        .await?
+    {
+        ts.load_follow(f).await;
+    }
+    ts.flush().await?;
+
+    // 使用空的推送记录，同一个文件可以反复回放
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    let forwarder = tokio::spawn(TwitterSubscriber::forward_tweet(
+        ForwardHistoryCache::new(config.forwarder.history_ttl(), Vec::new()),
+        ts,
+        db,
+        bot,
+        rx,
+        shutdown_rx,
+    ));
+    let mut count = 0;
+    let mut last_tweet_at: Option<chrono::DateTime<chrono::Utc>> = None;
+    for (i, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let m = match line.parse::<StreamMessage>() {
+            Ok(m) => m,
+            Err(e) => {
+                warn!("{}:{} {:?}", file.display(), i + 1, e);
+                continue;
+            }
+        };
+        if let StreamMessage::Tweet(t) = &m {
+            if let Some(last) = last_tweet_at.filter(|_| options.speed > 0.0) {
+                if let Ok(gap) = (t.created_at - last).to_std() {
+                    tokio::time::sleep(gap.div_f64(options.speed)).await;
+                }
+            }
+            last_tweet_at = Some(t.created_at);
+        }
+        tx.send(m).await?;
+        count += 1;
+    }
+
+    // 队列清空后再通知退出，正在处理的消息会先处理完
+    while tx.capacity() < tx.max_capacity() {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    let _ = shutdown_tx.send(true);
+    forwarder.await?;
+    Ok(count)
+}
+
+async fn run_twitter_subscriber(
+    tg_bot: AutoSend<DefaultParseMode<Bot>>,
+    ts: SubscriberHandle,
+    db: Repository,
+    user_vec: Vec<User>,
+) {
+    let mut valid_user_id_vec: Vec<i64> = Vec::new();
+    for u in &user_vec {
+        if let Err(e) = ts
+            .add_token(u.id, u.twitter_access_token.as_ref().unwrap())
+            .await
+        {
+            error!("add twitter token: {:?}", e);
+            if e.to_string().contains("expired") {
+                let uid = u.id;
+                if let Err(e) = db
+                    .run(move |conn| {
+                        user_model::update_twitter_token(conn, uid, "".to_string(), false)
+                    })
+                    .await
+                {
+                    error!("clear twitter token@{} {:?}", uid, e);
+                }
+                let res = tg_bot
+                    .send_message(UserId(u.id as u64), escape(&e.to_string()))
+                    .await;
+                if let Err(err) = res {
+                    error!("telegram@{} {:?}", &u.id, &err);
+                }
+            }
+        } else {
+            valid_user_id_vec.push(u.id);
+        }
+    }
+
+    // 取到所有有效用户的 follow
+    let follow_vec = match db
+        .run(move |conn| follow_model::get_follows_by_user_ids(conn, &valid_user_id_vec))
+        .await
+    {
+        Ok(vec) => vec,
+        Err(e) => {
+            error!("load follows {:?}", e);
+            return;
+        }
+    };
+
+    // 加入监听，stream 在 follow 加载完成后统一连接
+    for f in follow_vec {
+        ts.load_follow(f).await;
+    }
+}
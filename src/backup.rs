@@ -0,0 +1,178 @@
+use std::{
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+};
+
+use anyhow::anyhow;
+use diesel::{sql_types::Text, Connection, RunQueryDsl};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use log::{error, info};
+
+use crate::config::{BackupConfig, DatabaseConfig};
+use crate::models::{known_migrations, DbConnection, DbError, Repository};
+
+// 备份文件名 t2t-20261018-120000.db.gz，按文件名排序即按时间排序
+const PREFIX: &str = "t2t-";
+const SUFFIX: &str = ".db.gz";
+
+// VACUUM INTO 在读事务中生成一致的快照，不影响正在运行的 bot
+fn snapshot(conn: &DbConnection, path: &Path) -> Result<(), DbError> {
+    diesel::sql_query("VACUUM INTO ?")
+        .bind::<Text, _>(path.to_string_lossy().to_string())
+        .execute(conn)?;
+    Ok(())
+}
+
+fn compress(src: &Path, dst: &Path) -> io::Result<()> {
+    let mut input = File::open(src)?;
+    let mut encoder = GzEncoder::new(File::create(dst)?, Compression::default());
+    io::copy(&mut input, &mut encoder)?;
+    encoder.finish()?;
+    Ok(())
+}
+
+fn decompress(src: &Path, dst: &Path) -> io::Result<()> {
+    let mut decoder = GzDecoder::new(File::open(src)?);
+    let mut output = File::create(dst)?;
+    io::copy(&mut decoder, &mut output)?;
+    Ok(())
+}
+
+// 生成压缩后的快照，返回备份文件路径
+pub async fn create(db: &Repository, dir: &Path) -> Result<PathBuf, anyhow::Error> {
+    if cfg!(feature = "postgres") {
+        return Err(anyhow!(
+            "backup only works with SQLite, use pg_dump to back up PostgreSQL"
+        ));
+    }
+    fs::create_dir_all(dir)?;
+    let name = format!("{}{}", PREFIX, chrono::Utc::now().format("%Y%m%d-%H%M%S"));
+    let raw = dir.join(format!("{}.db", name));
+    let gz = dir.join(format!("{}{}", name, SUFFIX));
+    // VACUUM INTO 要求目标文件不存在
+    let _ = fs::remove_file(&raw);
+    let target = raw.clone();
+    db.run(move |conn| snapshot(conn, &target)).await?;
+    let res = {
+        let gz = gz.clone();
+        let raw = raw.clone();
+        tokio::task::spawn_blocking(move || compress(&raw, &gz)).await?
+    };
+    let _ = fs::remove_file(&raw);
+    res?;
+    Ok(gz)
+}
+
+// 只保留最近 keep 个备份，返回删除的数量
+pub fn prune(dir: &Path, keep: usize) -> Result<usize, anyhow::Error> {
+    let mut backups = fs::read_dir(dir)?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| {
+            p.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.starts_with(PREFIX) && n.ends_with(SUFFIX))
+        })
+        .collect::<Vec<_>>();
+    backups.sort();
+    let expired = backups.len().saturating_sub(keep);
+    for p in &backups[..expired] {
+        fs::remove_file(p)?;
+    }
+    Ok(expired)
+}
+
+pub async fn run_scheduled(db: Repository, config: BackupConfig) {
+    let dir = PathBuf::from(&config.dir);
+    let mut interval = tokio::time::interval_at(
+        tokio::time::Instant::now() + config.interval(),
+        config.interval(),
+    );
+    loop {
+        interval.tick().await;
+        match create(&db, &dir).await {
+            Ok(path) => info!("backup created {}", path.display()),
+            Err(e) => {
+                error!("backup {:?}", e);
+                continue;
+            }
+        }
+        if let Err(e) = prune(&dir, config.keep) {
+            error!("prune backups {:?}", e);
+        }
+    }
+}
+
+// 检查备份完整，并且不是更新版本的程序生成的
+fn check_schema(path: &Path) -> Result<(), anyhow::Error> {
+    #[derive(QueryableByName)]
+    struct IntegrityCheck {
+        #[sql_type = "Text"]
+        integrity_check: String,
+    }
+
+    #[derive(QueryableByName)]
+    struct SchemaVersion {
+        #[sql_type = "Text"]
+        version: String,
+    }
+
+    let conn = DbConnection::establish(&path.to_string_lossy())?;
+    let check = diesel::sql_query("PRAGMA integrity_check").load::<IntegrityCheck>(&conn)?;
+    if check.first().map(|c| c.integrity_check.as_str()) != Some("ok") {
+        return Err(anyhow!("backup is corrupted"));
+    }
+    let versions = diesel::sql_query("SELECT version FROM __diesel_schema_migrations")
+        .load::<SchemaVersion>(&conn)
+        .map_err(|_| anyhow!("backup is not a twitter2telegram database"))?;
+    let known = known_migrations();
+    let unknown = versions
+        .iter()
+        .map(|v| v.version.as_str())
+        .filter(|v| !known.iter().any(|k| k == v))
+        .collect::<Vec<_>>();
+    if !unknown.is_empty() {
+        return Err(anyhow!(
+            "backup was created by a newer version, unknown migrations: {}",
+            unknown.join(", ")
+        ));
+    }
+    Ok(())
+}
+
+// 解压并校验备份，通过后把当前数据库改名保留，再换成备份，返回保留的旧数据库路径
+// 恢复前需要先停止 bot
+pub fn restore(config: &DatabaseConfig, backup: &Path) -> Result<Option<PathBuf>, anyhow::Error> {
+    if cfg!(feature = "postgres") {
+        return Err(anyhow!(
+            "restore only works with SQLite, use pg_restore to restore PostgreSQL"
+        ));
+    }
+    let target = Path::new(&config.url);
+    let restoring = target.with_extension("restore");
+    let res = match backup.extension().and_then(|e| e.to_str()) {
+        Some("gz") => decompress(backup, &restoring),
+        _ => fs::copy(backup, &restoring).map(|_| ()),
+    }
+    .map_err(anyhow::Error::from)
+    .and_then(|_| check_schema(&restoring));
+    if let Err(e) = res {
+        let _ = fs::remove_file(&restoring);
+        return Err(e);
+    }
+
+    let mut old = None;
+    if target.exists() {
+        let name = format!(
+            "{}.{}",
+            target.file_name().unwrap().to_string_lossy(),
+            chrono::Utc::now().format("%Y%m%d-%H%M%S")
+        );
+        let path = target.with_file_name(name);
+        fs::rename(target, &path)?;
+        old = Some(path);
+    }
+    fs::rename(&restoring, target)?;
+    Ok(old)
+}
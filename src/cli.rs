@@ -0,0 +1,164 @@
+use std::{fmt, fs, path::Path};
+
+use anyhow::anyhow;
+use diesel::Connection;
+
+use crate::config::Config;
+use crate::models::{
+    export_model::{self, Export},
+    known_migrations, pending_migrations,
+    role_model::{self, Role, UserRole},
+    run_migrations, schema_version,
+    user_model::{self, User},
+    DbConnection,
+};
+use crate::token_cipher;
+use crate::twitter_subscriber::TwitterSubscriber;
+
+// 运维用的子命令，不启动 bot，直接操作数据库
+
+fn connect(config: &Config) -> Result<DbConnection, anyhow::Error> {
+    DbConnection::establish(&config.database.url)
+        .map_err(|e| anyhow!("connect {}: {}", config.database.url, e))
+}
+
+// 返回待执行的迁移，dry_run 时只查看不执行
+pub fn migrate(config: &Config, dry_run: bool) -> Result<Vec<String>, anyhow::Error> {
+    let conn = connect(config)?;
+    let pending = pending_migrations(&conn)?;
+    if !dry_run && !pending.is_empty() {
+        run_migrations(&conn)?;
+    }
+    Ok(pending)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TokenStatus {
+    Valid,
+    Expired,
+    Error(String),
+}
+
+impl fmt::Display for TokenStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenStatus::Valid => write!(f, "valid"),
+            TokenStatus::Expired => write!(f, "expired"),
+            TokenStatus::Error(e) => write!(f, "error: {}", e),
+        }
+    }
+}
+
+pub struct TokenCheck {
+    pub user_id: i64,
+    pub label: String,
+    pub status: TokenStatus,
+}
+
+// 逐个校验保存的 twitter token，只报告不修改数据库
+pub async fn check_tokens(config: &Config) -> Result<Vec<TokenCheck>, anyhow::Error> {
+    token_cipher::init(&config.database)?;
+    let conn = connect(config)?;
+    let mut checks = Vec::new();
+    for u in user_model::get_all_users(&conn)? {
+        // 单个 token 解密失败只影响这个用户
+        let status = match user_model::get_user_by_id(&conn, u.id) {
+            Ok(user) => match user.twitter_access_token.filter(|t| !t.is_empty()) {
+                Some(token) => {
+                    match TwitterSubscriber::check_token_valid(&config.twitter, &token).await {
+                        Ok(true) => TokenStatus::Valid,
+                        Ok(false) => TokenStatus::Expired,
+                        Err(e) => TokenStatus::Error(e.to_string()),
+                    }
+                }
+                None => continue,
+            },
+            Err(e) => TokenStatus::Error(e.to_string()),
+        };
+        checks.push(TokenCheck {
+            user_id: u.id,
+            label: u.label,
+            status,
+        });
+    }
+    Ok(checks)
+}
+
+// 导出全部数据为 JSON，返回导出的行数
+pub fn export(config: &Config, file: &Path) -> Result<usize, anyhow::Error> {
+    let conn = connect(config)?;
+    let data = export_model::export_all(&conn, schema_version(&conn)?)?;
+    fs::write(file, serde_json::to_vec_pretty(&data)?)?;
+    Ok(data.len())
+}
+
+// 导入到空数据库，返回导入的行数
+pub fn import(config: &Config, file: &Path) -> Result<usize, anyhow::Error> {
+    let data: Export = serde_json::from_slice(&fs::read(file)?)
+        .map_err(|e| anyhow!("read {}: {}", file.display(), e))?;
+    if !data.schema_version.is_empty() && !known_migrations().contains(&data.schema_version) {
+        return Err(anyhow!(
+            "{} was exported by a newer version, unknown migration {}",
+            file.display(),
+            data.schema_version
+        ));
+    }
+    let conn = connect(config)?;
+    run_migrations(&conn)?;
+    if !export_model::is_empty(&conn)? {
+        return Err(anyhow!(
+            "database {} is not empty, import only works on a new database",
+            config.database.url
+        ));
+    }
+    Ok(export_model::import_all(&conn, data)?)
+}
+
+// 角色名不区分大小写，和 /ListRoles 显示的一致
+pub fn parse_role(name: &str) -> Option<Role> {
+    [Role::ReadOnly, Role::User, Role::Admin, Role::Owner]
+        .into_iter()
+        .find(|r| format!("{:?}", r).eq_ignore_ascii_case(name))
+}
+
+// 不通过 telegram 直接添加用户，用于初次部署
+pub fn add_user(
+    config: &Config,
+    telegram_id: i64,
+    label: &str,
+    role: Option<Role>,
+) -> Result<(), anyhow::Error> {
+    if telegram_id.le(&0) {
+        return Err(anyhow!("incorrect telegram id {}", telegram_id));
+    }
+    let conn = connect(config)?;
+    run_migrations(&conn)?;
+    let now = chrono::Utc::now().naive_utc();
+    user_model::create_user(
+        &conn,
+        User {
+            id: telegram_id,
+            label: label.to_string(),
+            twitter_access_token: None,
+            twitter_status: false,
+            created_at: now,
+            disable_retweet: false,
+            disable_text_msg: false,
+            suspended: false,
+            follow_quota: 0,
+            dry_run: false,
+            remove_deleted: false,
+        },
+    )?;
+    if let Some(role) = role {
+        role_model::grant_role(
+            &conn,
+            UserRole {
+                user_id: telegram_id,
+                role: role.toi32(),
+                created_at: now,
+            },
+        )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,740 @@
+use std::{env, fmt, fs, net::SocketAddr, str::FromStr, time::Duration};
+
+use serde::Deserialize;
+
+use crate::token_cipher;
+
+const DEFAULT_CONFIG_FILE: &str = "data/config.toml";
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub database: DatabaseConfig,
+    pub telegram: TelegramConfig,
+    pub twitter: TwitterConfig,
+    pub forwarder: ForwarderConfig,
+    pub http: HttpConfig,
+    pub backup: BackupConfig,
+    pub recorder: RecorderConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub url: String,
+    pub pool_size: u32,
+    // 等待空闲连接的最长时间，超时后提示用户稍后重试
+    pub pool_timeout_secs: u64,
+    // base64 编码的 32 字节密钥，用于加密保存 twitter token，为空则明文保存
+    pub token_key: String,
+    // 轮换前的旧密钥，只用于解密，执行 /RotateTokenKey 后即可移除
+    pub old_token_keys: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TelegramConfig {
+    pub bot_token: String,
+    pub admin_id: i64,
+    pub api_url: String,
+    // /Broadcast 预览等待确认的时间
+    pub broadcast_ttl_secs: u64,
+    pub webhook: WebhookConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebhookConfig {
+    // 为空则使用 long polling
+    pub url: String,
+    pub listen: String,
+    // 为空则启动时随机生成
+    pub secret: String,
+    // 同时配置证书和私钥时直接提供 HTTPS，否则需要前置反向代理
+    pub tls_cert: String,
+    pub tls_key: String,
+    // 自签名证书需要上传给 Telegram
+    pub self_signed: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TwitterConfig {
+    pub key: String,
+    pub secret: String,
+    pub api_url: String,
+    pub stream_url: String,
+    pub request_token_ttl_secs: u64,
+    pub request_token_cleanup_secs: u64,
+    // 出错后第一次重连的等待时间，之后每次翻倍直到 stream_retry_max_secs
+    pub stream_retry_secs: u64,
+    pub stream_retry_max_secs: u64,
+    // follow 变化后等待这么久没有新的变化再重连
+    pub stream_restart_delay_ms: u64,
+    // 单个 stream 连接最多 follow 的账号数
+    pub max_follows_per_token: usize,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ForwarderConfig {
+    pub max_tweet_age_days: i64,
+    pub history_ttl_secs: u64,
+    pub history_cleanup_secs: u64,
+    pub channel_capacity: usize,
+    pub shutdown_timeout_secs: u64,
+    // 每个用户保留的推送记录条数，供 /WhyNot 查询
+    pub delivery_log_size: i64,
+    // 保留推文和 telegram 消息对应关系的天数，推文在此期间被删除时标记或删除消息，0 为不处理
+    pub sent_message_days: i64,
+    // 试运行，照常过滤和排版但不推送给用户，/SetDryRun 可以只对单个用户开启
+    pub dry_run: bool,
+    // 试运行的消息发给管理员，否则只写日志
+    pub dry_run_to_admin: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpConfig {
+    // 为空则不启动 HTTP 服务
+    pub listen: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BackupConfig {
+    // /Backup 和定时备份的保存目录
+    pub dir: String,
+    // 定时备份间隔，0 为不启用
+    pub interval_hours: u64,
+    // 保留最近的备份数量
+    pub keep: usize,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RecorderConfig {
+    // 把 stream 收到的原始消息逐行写入这个文件，供 replay 子命令回放，为空则不录制
+    pub file: String,
+    // 文件超过这个大小后轮转为 file.1、file.2 ...
+    pub max_size_mb: u64,
+    // 保留的轮转文件数量
+    pub keep: usize,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        DatabaseConfig {
+            url: "data/main.db".to_string(),
+            pool_size: 5,
+            pool_timeout_secs: 5,
+            token_key: "".to_string(),
+            old_token_keys: Vec::new(),
+        }
+    }
+}
+
+impl Default for TelegramConfig {
+    fn default() -> Self {
+        TelegramConfig {
+            bot_token: "".to_string(),
+            admin_id: 0,
+            api_url: "https://api.telegram.org".to_string(),
+            broadcast_ttl_secs: 10 * 60,
+            webhook: WebhookConfig::default(),
+        }
+    }
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        WebhookConfig {
+            url: "".to_string(),
+            listen: "0.0.0.0:8443".to_string(),
+            secret: "".to_string(),
+            tls_cert: "".to_string(),
+            tls_key: "".to_string(),
+            self_signed: false,
+        }
+    }
+}
+
+impl Default for TwitterConfig {
+    fn default() -> Self {
+        TwitterConfig {
+            key: "".to_string(),
+            secret: "".to_string(),
+            api_url: "https://api.twitter.com".to_string(),
+            stream_url: "https://stream.twitter.com".to_string(),
+            request_token_ttl_secs: 10 * 60,
+            request_token_cleanup_secs: 10 * 60,
+            stream_retry_secs: 3,
+            stream_retry_max_secs: 320,
+            stream_restart_delay_ms: 2000,
+            max_follows_per_token: 5000,
+        }
+    }
+}
+
+impl Default for ForwarderConfig {
+    fn default() -> Self {
+        ForwarderConfig {
+            max_tweet_age_days: 3,
+            history_ttl_secs: 60 * 60 * 24 * 3,
+            history_cleanup_secs: 60 * 60,
+            channel_capacity: 100,
+            shutdown_timeout_secs: 10,
+            delivery_log_size: 1000,
+            sent_message_days: 7,
+            dry_run: false,
+            dry_run_to_admin: false,
+        }
+    }
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        BackupConfig {
+            dir: "data/backup".to_string(),
+            interval_hours: 0,
+            keep: 7,
+        }
+    }
+}
+
+impl Default for RecorderConfig {
+    fn default() -> Self {
+        RecorderConfig {
+            file: "".to_string(),
+            max_size_mb: 100,
+            keep: 3,
+        }
+    }
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        HttpConfig {
+            listen: "0.0.0.0:9090".to_string(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ConfigError(pub Vec<String>);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "invalid configuration:")?;
+        for e in &self.0 {
+            writeln!(f, "  - {}", e)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    // 读取配置文件（CONFIG_FILE，默认 data/config.toml），再用环境变量覆盖
+    pub fn load() -> Result<Config, ConfigError> {
+        Self::read(Self::validate)
+    }
+
+    // 只检查 [database]，供不连接 Telegram 和 Twitter 的子命令使用
+    pub fn load_database() -> Result<Config, ConfigError> {
+        Self::read(Self::validate_database)
+    }
+
+    fn read(validate: fn(&Config) -> Vec<String>) -> Result<Config, ConfigError> {
+        let (path, required) = match env::var("CONFIG_FILE") {
+            Ok(path) => (path, true),
+            Err(_) => (DEFAULT_CONFIG_FILE.to_string(), false),
+        };
+        let mut config = match fs::read_to_string(&path) {
+            Ok(content) => Self::from_toml(&content)
+                .map_err(|e| ConfigError(vec![format!("{}: {}", path, e)]))?,
+            Err(e) if required || e.kind() != std::io::ErrorKind::NotFound => {
+                return Err(ConfigError(vec![format!("{}: {}", path, e)]));
+            }
+            Err(_) => Config::default(),
+        };
+        let mut errors = config.apply_env();
+        errors.extend(validate(&config));
+        if errors.is_empty() {
+            Ok(config)
+        } else {
+            Err(ConfigError(errors))
+        }
+    }
+
+    pub fn from_toml(content: &str) -> Result<Config, toml::de::Error> {
+        toml::from_str(content)
+    }
+
+    fn apply_env(&mut self) -> Vec<String> {
+        let mut errors = Vec::new();
+        override_env(&mut errors, "DATABASE_URL", &mut self.database.url);
+        override_env(
+            &mut errors,
+            "DATABASE_POOL_SIZE",
+            &mut self.database.pool_size,
+        );
+        override_env(
+            &mut errors,
+            "DATABASE_POOL_TIMEOUT_SECS",
+            &mut self.database.pool_timeout_secs,
+        );
+        override_env(
+            &mut errors,
+            "DATABASE_TOKEN_KEY",
+            &mut self.database.token_key,
+        );
+        // 多个旧密钥用逗号分隔
+        if let Ok(value) = env::var("DATABASE_OLD_TOKEN_KEYS") {
+            self.database.old_token_keys = value
+                .split(',')
+                .map(|k| k.trim().to_string())
+                .filter(|k| !k.is_empty())
+                .collect();
+        }
+        override_env(
+            &mut errors,
+            "TELEGRAM_BOT_TOKEN",
+            &mut self.telegram.bot_token,
+        );
+        override_env(
+            &mut errors,
+            "TELEGRAM_ADMIN_ID",
+            &mut self.telegram.admin_id,
+        );
+        override_env(&mut errors, "TELEGRAM_API_URL", &mut self.telegram.api_url);
+        override_env(
+            &mut errors,
+            "TELEGRAM_BROADCAST_TTL_SECS",
+            &mut self.telegram.broadcast_ttl_secs,
+        );
+        override_env(
+            &mut errors,
+            "TELEGRAM_WEBHOOK_URL",
+            &mut self.telegram.webhook.url,
+        );
+        override_env(
+            &mut errors,
+            "TELEGRAM_WEBHOOK_LISTEN",
+            &mut self.telegram.webhook.listen,
+        );
+        override_env(
+            &mut errors,
+            "TELEGRAM_WEBHOOK_SECRET",
+            &mut self.telegram.webhook.secret,
+        );
+        override_env(
+            &mut errors,
+            "TELEGRAM_WEBHOOK_TLS_CERT",
+            &mut self.telegram.webhook.tls_cert,
+        );
+        override_env(
+            &mut errors,
+            "TELEGRAM_WEBHOOK_TLS_KEY",
+            &mut self.telegram.webhook.tls_key,
+        );
+        override_env(
+            &mut errors,
+            "TELEGRAM_WEBHOOK_SELF_SIGNED",
+            &mut self.telegram.webhook.self_signed,
+        );
+        override_env(&mut errors, "TWITTER_KEY", &mut self.twitter.key);
+        override_env(&mut errors, "TWITTER_SECRET", &mut self.twitter.secret);
+        override_env(&mut errors, "TWITTER_API_URL", &mut self.twitter.api_url);
+        override_env(
+            &mut errors,
+            "TWITTER_STREAM_URL",
+            &mut self.twitter.stream_url,
+        );
+        override_env(
+            &mut errors,
+            "TWITTER_REQUEST_TOKEN_TTL_SECS",
+            &mut self.twitter.request_token_ttl_secs,
+        );
+        override_env(
+            &mut errors,
+            "TWITTER_REQUEST_TOKEN_CLEANUP_SECS",
+            &mut self.twitter.request_token_cleanup_secs,
+        );
+        override_env(
+            &mut errors,
+            "TWITTER_STREAM_RETRY_SECS",
+            &mut self.twitter.stream_retry_secs,
+        );
+        override_env(
+            &mut errors,
+            "TWITTER_STREAM_RETRY_MAX_SECS",
+            &mut self.twitter.stream_retry_max_secs,
+        );
+        override_env(
+            &mut errors,
+            "TWITTER_STREAM_RESTART_DELAY_MS",
+            &mut self.twitter.stream_restart_delay_ms,
+        );
+        override_env(
+            &mut errors,
+            "TWITTER_MAX_FOLLOWS_PER_TOKEN",
+            &mut self.twitter.max_follows_per_token,
+        );
+        override_env(
+            &mut errors,
+            "FORWARDER_MAX_TWEET_AGE_DAYS",
+            &mut self.forwarder.max_tweet_age_days,
+        );
+        override_env(
+            &mut errors,
+            "FORWARDER_HISTORY_TTL_SECS",
+            &mut self.forwarder.history_ttl_secs,
+        );
+        override_env(
+            &mut errors,
+            "FORWARDER_HISTORY_CLEANUP_SECS",
+            &mut self.forwarder.history_cleanup_secs,
+        );
+        override_env(
+            &mut errors,
+            "FORWARDER_CHANNEL_CAPACITY",
+            &mut self.forwarder.channel_capacity,
+        );
+        override_env(
+            &mut errors,
+            "FORWARDER_SHUTDOWN_TIMEOUT_SECS",
+            &mut self.forwarder.shutdown_timeout_secs,
+        );
+        override_env(
+            &mut errors,
+            "FORWARDER_DELIVERY_LOG_SIZE",
+            &mut self.forwarder.delivery_log_size,
+        );
+        override_env(
+            &mut errors,
+            "FORWARDER_SENT_MESSAGE_DAYS",
+            &mut self.forwarder.sent_message_days,
+        );
+        override_env(
+            &mut errors,
+            "FORWARDER_DRY_RUN",
+            &mut self.forwarder.dry_run,
+        );
+        override_env(
+            &mut errors,
+            "FORWARDER_DRY_RUN_TO_ADMIN",
+            &mut self.forwarder.dry_run_to_admin,
+        );
+        override_env(&mut errors, "HTTP_LISTEN", &mut self.http.listen);
+        override_env(&mut errors, "BACKUP_DIR", &mut self.backup.dir);
+        override_env(
+            &mut errors,
+            "BACKUP_INTERVAL_HOURS",
+            &mut self.backup.interval_hours,
+        );
+        override_env(&mut errors, "BACKUP_KEEP", &mut self.backup.keep);
+        override_env(&mut errors, "RECORDER_FILE", &mut self.recorder.file);
+        override_env(
+            &mut errors,
+            "RECORDER_MAX_SIZE_MB",
+            &mut self.recorder.max_size_mb,
+        );
+        override_env(&mut errors, "RECORDER_KEEP", &mut self.recorder.keep);
+        errors
+    }
+
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = self.validate_database();
+        if !self.telegram.bot_token.contains(':') {
+            errors.push(
+                "telegram.bot_token (TELEGRAM_BOT_TOKEN) is required, like 123456:abcdef"
+                    .to_string(),
+            );
+        }
+        if self.telegram.admin_id <= 0 {
+            errors.push(
+                "telegram.admin_id (TELEGRAM_ADMIN_ID) must be a telegram user id".to_string(),
+            );
+        }
+        if self.telegram.broadcast_ttl_secs == 0 {
+            errors.push("telegram.broadcast_ttl_secs must be greater than 0".to_string());
+        }
+        for (name, value) in [
+            (
+                "telegram.api_url (TELEGRAM_API_URL)",
+                &self.telegram.api_url,
+            ),
+            ("twitter.api_url (TWITTER_API_URL)", &self.twitter.api_url),
+            (
+                "twitter.stream_url (TWITTER_STREAM_URL)",
+                &self.twitter.stream_url,
+            ),
+        ] {
+            if url::Url::parse(value).is_err() {
+                errors.push(format!("{} {:?} is not a valid url", name, value));
+            }
+        }
+        errors.extend(self.telegram.webhook.validate());
+        if self.twitter.key.trim().is_empty() {
+            errors.push("twitter.key (TWITTER_KEY) is required".to_string());
+        }
+        if self.twitter.secret.trim().is_empty() {
+            errors.push("twitter.secret (TWITTER_SECRET) is required".to_string());
+        }
+        if self.twitter.request_token_ttl_secs == 0 {
+            errors.push("twitter.request_token_ttl_secs must be greater than 0".to_string());
+        }
+        if self.twitter.request_token_cleanup_secs == 0 {
+            errors.push("twitter.request_token_cleanup_secs must be greater than 0".to_string());
+        }
+        if self.twitter.stream_retry_secs == 0 {
+            errors.push("twitter.stream_retry_secs must be greater than 0".to_string());
+        }
+        if self.twitter.stream_retry_max_secs < self.twitter.stream_retry_secs {
+            errors.push(
+                "twitter.stream_retry_max_secs must not be less than twitter.stream_retry_secs"
+                    .to_string(),
+            );
+        }
+        if self.twitter.max_follows_per_token == 0 {
+            errors.push("twitter.max_follows_per_token must be greater than 0".to_string());
+        }
+        if self.forwarder.max_tweet_age_days <= 0 {
+            errors.push("forwarder.max_tweet_age_days must be greater than 0".to_string());
+        }
+        if self.forwarder.history_ttl_secs == 0 {
+            errors.push("forwarder.history_ttl_secs must be greater than 0".to_string());
+        }
+        if self.forwarder.history_cleanup_secs == 0 {
+            errors.push("forwarder.history_cleanup_secs must be greater than 0".to_string());
+        }
+        if self.forwarder.channel_capacity == 0 {
+            errors.push("forwarder.channel_capacity must be greater than 0".to_string());
+        }
+        if self.forwarder.delivery_log_size <= 0 {
+            errors.push("forwarder.delivery_log_size must be greater than 0".to_string());
+        }
+        if self.forwarder.sent_message_days < 0 {
+            errors.push("forwarder.sent_message_days must not be negative".to_string());
+        }
+        if !self.http.listen.is_empty() && self.http.listen.parse::<SocketAddr>().is_err() {
+            errors.push(format!(
+                "http.listen (HTTP_LISTEN) {:?} is not a socket address like 0.0.0.0:9090",
+                self.http.listen
+            ));
+        }
+        if self.backup.dir.trim().is_empty() {
+            errors.push("backup.dir (BACKUP_DIR) is required".to_string());
+        }
+        if self.backup.keep == 0 {
+            errors.push("backup.keep must be greater than 0".to_string());
+        }
+        if cfg!(feature = "postgres") && self.backup.enabled() {
+            errors.push(
+                "backup.interval_hours only works with SQLite, use pg_dump to back up PostgreSQL"
+                    .to_string(),
+            );
+        }
+        if self.recorder.max_size_mb == 0 {
+            errors.push("recorder.max_size_mb must be greater than 0".to_string());
+        }
+        errors
+    }
+
+    // [database] 中的连接和 token 加密配置
+    pub fn validate_database(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if self.database.url.trim().is_empty() {
+            errors.push("database.url (DATABASE_URL) is required".to_string());
+        }
+        if self.database.pool_size == 0 {
+            errors.push("database.pool_size must be greater than 0".to_string());
+        }
+        if self.database.pool_timeout_secs == 0 {
+            errors.push("database.pool_timeout_secs must be greater than 0".to_string());
+        }
+        if !self.database.token_key.is_empty() {
+            if let Err(e) = token_cipher::parse_key(&self.database.token_key) {
+                errors.push(format!(
+                    "database.token_key (DATABASE_TOKEN_KEY) must be 32 bytes encoded in base64: {}",
+                    e
+                ));
+            }
+        } else if !self.database.old_token_keys.is_empty() {
+            errors.push(
+                "database.old_token_keys (DATABASE_OLD_TOKEN_KEYS) requires database.token_key"
+                    .to_string(),
+            );
+        }
+        for key in &self.database.old_token_keys {
+            if let Err(e) = token_cipher::parse_key(key) {
+                errors.push(format!(
+                    "database.old_token_keys (DATABASE_OLD_TOKEN_KEYS) must be 32 bytes encoded in base64: {}",
+                    e
+                ));
+            }
+        }
+        errors
+    }
+}
+
+impl BackupConfig {
+    pub fn enabled(&self) -> bool {
+        self.interval_hours > 0
+    }
+
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_hours * 60 * 60)
+    }
+}
+
+impl RecorderConfig {
+    pub fn enabled(&self) -> bool {
+        !self.file.trim().is_empty()
+    }
+
+    pub fn max_size(&self) -> u64 {
+        self.max_size_mb * 1024 * 1024
+    }
+}
+
+impl DatabaseConfig {
+    pub fn pool_timeout(&self) -> Duration {
+        Duration::from_secs(self.pool_timeout_secs)
+    }
+}
+
+impl TelegramConfig {
+    pub fn broadcast_ttl(&self) -> Duration {
+        Duration::from_secs(self.broadcast_ttl_secs)
+    }
+}
+
+impl TwitterConfig {
+    // 应用的 consumer key，用于 OAuth 授权
+    pub fn consumer_token(&self) -> egg_mode::KeyPair {
+        egg_mode::KeyPair::new(self.key.clone(), self.secret.clone())
+    }
+
+    pub fn request_token_ttl(&self) -> Duration {
+        Duration::from_secs(self.request_token_ttl_secs)
+    }
+
+    pub fn request_token_cleanup(&self) -> Duration {
+        Duration::from_secs(self.request_token_cleanup_secs)
+    }
+
+    pub fn stream_retry(&self) -> Duration {
+        Duration::from_secs(self.stream_retry_secs)
+    }
+
+    pub fn stream_retry_max(&self) -> Duration {
+        Duration::from_secs(self.stream_retry_max_secs)
+    }
+
+    pub fn stream_restart_delay(&self) -> Duration {
+        Duration::from_millis(self.stream_restart_delay_ms)
+    }
+}
+
+impl WebhookConfig {
+    pub fn enabled(&self) -> bool {
+        !self.url.is_empty()
+    }
+
+    pub fn listen_addr(&self) -> Option<SocketAddr> {
+        self.listen.parse().ok()
+    }
+
+    pub fn tls_enabled(&self) -> bool {
+        !self.tls_cert.is_empty() && !self.tls_key.is_empty()
+    }
+
+    fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if !self.enabled() {
+            return errors;
+        }
+        match url::Url::parse(&self.url) {
+            Ok(u) if u.scheme() == "https" => {}
+            _ => errors.push(format!(
+                "telegram.webhook.url (TELEGRAM_WEBHOOK_URL) {:?} must be an https url",
+                self.url
+            )),
+        }
+        if self.listen_addr().is_none() {
+            errors.push(format!(
+                "telegram.webhook.listen (TELEGRAM_WEBHOOK_LISTEN) {:?} is not a socket address like 0.0.0.0:8443",
+                self.listen
+            ));
+        }
+        // Telegram 只接受 1-256 位的 A-Z a-z 0-9 _ -
+        if !self.secret.is_empty()
+            && (self.secret.len() > 256
+                || !self
+                    .secret
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+        {
+            errors.push(
+                "telegram.webhook.secret (TELEGRAM_WEBHOOK_SECRET) must be 1-256 characters of A-Z, a-z, 0-9, _ and -"
+                    .to_string(),
+            );
+        }
+        if self.tls_cert.is_empty() != self.tls_key.is_empty() {
+            errors.push(
+                "telegram.webhook.tls_cert and telegram.webhook.tls_key must be set together"
+                    .to_string(),
+            );
+        }
+        if self.self_signed && self.tls_cert.is_empty() {
+            errors.push(
+                "telegram.webhook.self_signed requires telegram.webhook.tls_cert".to_string(),
+            );
+        }
+        errors
+    }
+}
+
+impl HttpConfig {
+    pub fn listen_addr(&self) -> Option<SocketAddr> {
+        self.listen.parse().ok()
+    }
+}
+
+impl ForwarderConfig {
+    pub fn max_tweet_age(&self) -> chrono::Duration {
+        chrono::Duration::days(self.max_tweet_age_days)
+    }
+
+    pub fn history_ttl(&self) -> Duration {
+        Duration::from_secs(self.history_ttl_secs)
+    }
+
+    pub fn history_cleanup(&self) -> Duration {
+        Duration::from_secs(self.history_cleanup_secs)
+    }
+
+    pub fn shutdown_timeout(&self) -> Duration {
+        Duration::from_secs(self.shutdown_timeout_secs)
+    }
+
+    pub fn track_sent_messages(&self) -> bool {
+        self.sent_message_days > 0
+    }
+}
+
+fn override_env<T>(errors: &mut Vec<String>, name: &str, field: &mut T)
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    if let Ok(value) = env::var(name) {
+        match value.trim().parse::<T>() {
+            Ok(v) => *field = v,
+            Err(e) => errors.push(format!("{}={:?}: {}", name, value, e)),
+        }
+    }
+}
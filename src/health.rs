@@ -0,0 +1,163 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+
+// 超过这个时间没有收到任何消息（包括 30 秒一次的 Ping）视为连接僵死
+pub const STREAM_STALE_SECS: i64 = 90;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamStatus {
+    Connecting,
+    Connected,
+    Retrying,
+    Stopped,
+}
+
+impl StreamStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StreamStatus::Connecting => "connecting",
+            StreamStatus::Connected => "connected",
+            StreamStatus::Retrying => "retrying",
+            StreamStatus::Stopped => "stopped",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StreamState {
+    pub status: StreamStatus,
+    pub follows: usize,
+    pub last_message_at: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    pub last_error_at: Option<DateTime<Utc>>,
+    // 连接次数，包括 follow 变化引起的重连
+    pub connects: u64,
+    // 连续失败次数
+    pub failures: u32,
+    pub next_retry_at: Option<DateTime<Utc>>,
+    // 按类型统计的控制消息，如 limit、disconnect、warning
+    pub control_messages: HashMap<&'static str, u64>,
+    pub last_control_message: Option<String>,
+    pub last_control_message_at: Option<DateTime<Utc>>,
+}
+
+impl StreamState {
+    // 有 follow 的 stream 必须处于连接状态且近期收到过消息
+    pub fn is_healthy(&self, now: DateTime<Utc>) -> bool {
+        match self.status {
+            StreamStatus::Stopped => self.follows == 0,
+            StreamStatus::Connecting => true,
+            StreamStatus::Retrying => false,
+            StreamStatus::Connected => self
+                .last_message_at
+                .is_some_and(|t| now - t < chrono::Duration::seconds(STREAM_STALE_SECS)),
+        }
+    }
+}
+
+lazy_static! {
+    // key 为 token hash
+    static ref STREAMS: Mutex<HashMap<String, StreamState>> = Mutex::new(HashMap::new());
+    // key 为后台任务名，value 为是否在运行
+    static ref TASKS: Mutex<HashMap<&'static str, bool>> = Mutex::new(HashMap::new());
+}
+
+pub fn stream_connecting(hash: &str, follows: usize) {
+    let mut streams = STREAMS.lock().unwrap();
+    let state = streams.entry(hash.to_string()).or_insert(StreamState {
+        status: StreamStatus::Connecting,
+        follows,
+        last_message_at: None,
+        last_error: None,
+        last_error_at: None,
+        connects: 0,
+        failures: 0,
+        next_retry_at: None,
+        control_messages: HashMap::new(),
+        last_control_message: None,
+        last_control_message_at: None,
+    });
+    state.status = StreamStatus::Connecting;
+    state.follows = follows;
+    state.connects += 1;
+    state.next_retry_at = None;
+}
+
+pub fn stream_message(hash: &str) {
+    if let Some(state) = STREAMS.lock().unwrap().get_mut(hash) {
+        state.status = StreamStatus::Connected;
+        state.last_message_at = Some(Utc::now());
+    }
+}
+
+pub fn stream_control_message(hash: &str, kind: &'static str, detail: &str) {
+    if let Some(state) = STREAMS.lock().unwrap().get_mut(hash) {
+        *state.control_messages.entry(kind).or_default() += 1;
+        state.last_control_message = Some(format!("{}: {}", kind, detail));
+        state.last_control_message_at = Some(Utc::now());
+    }
+}
+
+pub fn stream_error(hash: &str, error: String, failures: u32, next_retry_at: DateTime<Utc>) {
+    if let Some(state) = STREAMS.lock().unwrap().get_mut(hash) {
+        state.status = StreamStatus::Retrying;
+        state.last_error = Some(error);
+        state.last_error_at = Some(Utc::now());
+        state.failures = failures;
+        state.next_retry_at = Some(next_retry_at);
+    }
+}
+
+pub fn stream_stopped(hash: &str, follows: usize) {
+    if let Some(state) = STREAMS.lock().unwrap().get_mut(hash) {
+        state.status = StreamStatus::Stopped;
+        state.follows = follows;
+        state.next_retry_at = None;
+    }
+}
+
+pub fn remove_stream(hash: &str) {
+    STREAMS.lock().unwrap().remove(hash);
+}
+
+pub fn streams() -> Vec<(String, StreamState)> {
+    let mut list: Vec<(String, StreamState)> = STREAMS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect();
+    list.sort_by(|a, b| a.0.cmp(&b.0));
+    list
+}
+
+pub fn tasks() -> Vec<(&'static str, bool)> {
+    let mut list: Vec<(&'static str, bool)> = TASKS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(k, v)| (*k, *v))
+        .collect();
+    list.sort();
+    list
+}
+
+// 后台任务存活标记，任务退出或 panic 时随 drop 标记为停止
+pub struct TaskGuard(&'static str);
+
+impl TaskGuard {
+    pub fn new(name: &'static str) -> Self {
+        TASKS.lock().unwrap().insert(name, true);
+        TaskGuard(name)
+    }
+}
+
+impl Drop for TaskGuard {
+    fn drop(&mut self) {
+        if let Ok(mut tasks) = TASKS.lock() {
+            tasks.insert(self.0, false);
+        }
+    }
+}
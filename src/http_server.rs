@@ -0,0 +1,170 @@
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use axum::{extract::Extension, http::StatusCode, routing::get, Json, Router};
+use diesel::RunQueryDsl;
+use log::{error, info};
+use serde_json::{json, Value};
+
+use crate::health;
+use crate::metrics;
+use crate::models::{DbPool, Repository};
+use crate::twitter_subscriber::SubscriberHandle;
+
+pub struct HttpState {
+    pub db: Repository,
+    pub twitter_subscriber: SubscriberHandle,
+}
+
+pub async fn run(listen: SocketAddr, state: Arc<HttpState>) {
+    let app = Router::new()
+        .route("/metrics", get(metrics_handler))
+        .route("/healthz", get(healthz_handler))
+        .route("/readyz", get(readyz_handler))
+        .layer(Extension(state));
+    info!("HTTP server listening on {}", listen);
+    if let Err(e) = axum::Server::bind(&listen)
+        .serve(app.into_make_service())
+        .await
+    {
+        error!("HTTP server {:?}", e);
+    }
+}
+
+async fn metrics_handler(Extension(state): Extension<Arc<HttpState>>) -> String {
+    // 抓取时刷新按 token 统计的 follow 数与连接池状态
+    let follow_counts = state.twitter_subscriber.follow_counts();
+    metrics::FOLLOWS_UNASSIGNED.set(state.twitter_subscriber.snapshot().unassigned_follows as i64);
+    metrics::FOLLOWS_PER_TOKEN.reset();
+    for (hash, count) in follow_counts {
+        metrics::FOLLOWS_PER_TOKEN
+            .with_label_values(&[metrics::token_label(&hash)])
+            .set(count as i64);
+    }
+    let pool_state = state.db.pool().state();
+    metrics::DB_POOL_CONNECTIONS
+        .with_label_values(&["idle"])
+        .set(pool_state.idle_connections as i64);
+    metrics::DB_POOL_CONNECTIONS
+        .with_label_values(&["in_use"])
+        .set((pool_state.connections - pool_state.idle_connections) as i64);
+    metrics::DB_POOL_CONNECTIONS
+        .with_label_values(&["max"])
+        .set(state.db.pool().max_size() as i64);
+
+    metrics::encode()
+}
+
+// 必须一直运行的后台任务
+const REQUIRED_TASKS: [&str; 3] = ["telegram_dispatcher", "forward_tweet", "twitter_subscriber"];
+
+struct HealthReport {
+    alive: bool,
+    ready: bool,
+    body: Value,
+}
+
+async fn check_database(db_pool: DbPool) -> Result<(), String> {
+    tokio::task::spawn_blocking(move || {
+        let conn = db_pool
+            .get_timeout(Duration::from_secs(2))
+            .map_err(|e| e.to_string())?;
+        diesel::sql_query("SELECT 1")
+            .execute(&conn)
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+async fn health_report(state: &HttpState) -> HealthReport {
+    let now = chrono::Utc::now();
+
+    let running = health::tasks();
+    let mut tasks = serde_json::Map::new();
+    let mut alive = true;
+    for name in REQUIRED_TASKS {
+        let ok = running.iter().any(|(n, r)| n.eq(&name) && *r);
+        alive &= ok;
+        tasks.insert(
+            name.to_string(),
+            json!(if ok { "running" } else { "stopped" }),
+        );
+    }
+
+    let mut ready = alive;
+    let mut streams = serde_json::Map::new();
+    for (hash, s) in health::streams() {
+        let healthy = s.is_healthy(now);
+        ready &= healthy;
+        streams.insert(
+            metrics::token_label(&hash).to_string(),
+            json!({
+                "status": s.status.as_str(),
+                "healthy": healthy,
+                "follows": s.follows,
+                "last_message_at": s.last_message_at.map(|t| t.to_rfc3339()),
+                "last_error": s.last_error,
+                "last_error_at": s.last_error_at.map(|t| t.to_rfc3339()),
+                "connects": s.connects,
+                "failures": s.failures,
+                "next_retry_at": s.next_retry_at.map(|t| t.to_rfc3339()),
+                "control_messages": s.control_messages,
+                "last_control_message": s.last_control_message,
+                "last_control_message_at": s.last_control_message_at.map(|t| t.to_rfc3339()),
+            }),
+        );
+    }
+
+    let mut queues = serde_json::Map::new();
+    for (name, depth, capacity) in state.twitter_subscriber.queue_depths() {
+        // 队列满说明消费端跟不上或已经卡死
+        ready &= depth < capacity;
+        queues.insert(
+            name.to_string(),
+            json!({"depth": depth, "capacity": capacity}),
+        );
+    }
+
+    let database = match check_database(state.db.pool().clone()).await {
+        Ok(_) => json!({"ok": true}),
+        Err(e) => {
+            ready = false;
+            json!({"ok": false, "error": e})
+        }
+    };
+
+    HealthReport {
+        alive,
+        ready,
+        body: json!({
+            "alive": alive,
+            "ready": ready,
+            "tasks": tasks,
+            "streams": streams,
+            "queues": queues,
+            "database": database,
+            "unassigned_follows": state.twitter_subscriber.snapshot().unassigned_follows,
+        }),
+    }
+}
+
+fn status_code(ok: bool) -> StatusCode {
+    if ok {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    }
+}
+
+// 存活检查：核心后台任务退出时返回 503，需要重启进程
+async fn healthz_handler(Extension(state): Extension<Arc<HttpState>>) -> (StatusCode, Json<Value>) {
+    let report = health_report(&state).await;
+    (status_code(report.alive), Json(report.body))
+}
+
+// 就绪检查：任意 stream、队列或数据库异常时返回 503
+async fn readyz_handler(Extension(state): Extension<Arc<HttpState>>) -> (StatusCode, Json<Value>) {
+    let report = health_report(&state).await;
+    (status_code(report.ready), Json(report.body))
+}
@@ -1,8 +1,22 @@
+pub mod app;
+pub mod backup;
+pub mod cli;
+pub mod config;
+pub mod health;
+pub mod http_server;
+pub mod metrics;
 pub mod models;
+pub mod recorder;
+pub mod stream_supervisor;
 pub mod telegram_bot;
+pub mod token_cipher;
+pub mod twitter_api;
 pub mod twitter_subscriber;
+pub mod webhook;
 
 pub const GIT_HASH: &'static str = env!("GIT_HASH");
 
 #[macro_use]
 extern crate diesel;
+#[macro_use]
+extern crate diesel_migrations;
@@ -1,238 +1,167 @@
-use std::{
-    collections::{HashMap, HashSet},
-    env,
-    sync::Arc,
-    time::Duration,
-};
+use std::{path::Path, sync::Arc};
 
-use diesel::{ExpressionMethods, GroupByDsl, QueryDsl, RunQueryDsl};
 use dotenv::dotenv;
-use egg_mode::stream::StreamMessage;
-use log::{error, info};
-use r_cache::cache::Cache;
-use teloxide::{
-    adaptors::{AutoSend, DefaultParseMode},
-    prelude::{Requester, RequesterExt},
-    types::{ParseMode, UserId},
-    utils::markdown::escape,
-    Bot,
-};
-use tokio::sync::{
-    mpsc::{self, Sender},
-    RwLock,
-};
-
-use twitter2telegram::{
-    models::{
-        blacklist_model, establish_connection,
-        follow_model::Follow,
-        schema::follows::dsl::*,
-        schema::users::dsl::*,
-        user_model::{self, User},
-        DbPool,
-    },
-    telegram_bot,
-    twitter_subscriber::TwitterSubscriber,
-};
-
-#[macro_use]
-extern crate diesel_migrations;
-embed_migrations!("./migrations");
+use log::error;
+use tokio::signal::unix::{signal, SignalKind};
+
+use twitter2telegram::{app, backup, cli, config::Config};
+
+const USAGE: &str = "usage: twitter2telegram [command]
+
+commands:
+    (none)                          run the bot
+    migrate [--dry-run]             run pending database migrations, or only list them
+    check-tokens                    check every stored Twitter token
+    export <file>                   export all data as JSON
+    import <file>                   import an export into a new database
+    add-user <telegram id> <label> [readonly|user|admin|owner]
+                                    add a user without Telegram
+    replay <file> [--speed <n>] [--live]
+                                    forward recorded stream messages, one JSON per line,
+                                    --speed 1 keeps the recorded pace, 0 (default) does not wait,
+                                    dry run unless --live, logs or sends to the admin instead of users
+    restore <backup file>           replace the database with a backup, stop the bot first";
 
 #[tokio::main]
 async fn main() {
     dotenv().ok();
     pretty_env_logger::init_timed();
 
-    let db_pool: DbPool = establish_connection(&env::var("DATABASE_URL").unwrap());
-
-    // auto migration
-    info!(
-        "migration {:?}",
-        diesel_migrations::run_pending_migrations(&db_pool.get().unwrap())
-    );
-
-    let cache_instance: Arc<Cache<i64, egg_mode::KeyPair>> =
-        Arc::new(Cache::new(Some(Duration::from_secs(5 * 60))));
-    tokio::spawn({
-        let cache = Arc::clone(&cache_instance);
-        async move {
-            loop {
-                tokio::time::sleep(Duration::from_secs(10 * 60)).await;
-                cache.remove_expired().await;
-            }
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+    // 只操作数据库的子命令不需要 Telegram 和 Twitter 的配置
+    let loaded = match args[..] {
+        ["migrate", ..] | ["export", ..] | ["import", ..] | ["add-user", ..] | ["restore", ..] => {
+            Config::load_database()
         }
-    });
-
-    let telegram_admin_id: i64 = env::var("TELEGRAM_ADMIN_ID")
-        .unwrap()
-        .parse::<i64>()
-        .unwrap();
-    let twitter_app_token: egg_mode::KeyPair = egg_mode::KeyPair::new(
-        env::var("TWITTER_KEY").unwrap(),
-        env::var("TWITTER_SECRET").unwrap(),
-    );
-
-    let bot = teloxide::Bot::new(env::var("TELEGRAM_BOT_TOKEN").unwrap())
-        .parse_mode(ParseMode::MarkdownV2)
-        .auto_send();
-
-    let mut tg_ctx = telegram_bot::TelegramContext::new(
-        "T2TBot".to_string(),
-        cache_instance,
-        db_pool.clone(),
-        telegram_admin_id,
-        twitter_app_token,
-    );
-
-    let (tx, rx) = mpsc::channel::<StreamMessage>(100);
-    let (sub_tx, sub_rx) = mpsc::channel::<String>(100);
-    let sub_tx_clone = sub_tx.clone();
-
-    // 加载黑名单列表
-    let mut blacklist_map: HashMap<i64, HashSet<(i64, i32)>> = HashMap::new();
-    let res = blacklist_model::get_all_blacklist(&db_pool.get().unwrap());
-    if let Ok(list) = res {
-        for item in list {
-            let inner_list = blacklist_map.get_mut(&item.user_id);
-            if let Some(inner_list) = inner_list {
-                inner_list.insert((item.twitter_user_id, item.type_));
-            } else {
-                blacklist_map.insert(
-                    item.user_id,
-                    HashSet::from([(item.twitter_user_id, item.type_)]),
-                );
-            }
+        _ => Config::load(),
+    };
+    let config = match loaded {
+        Ok(config) => Arc::new(config),
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(1);
         }
+    };
+
+    match args[..] {
+        [] => app::run(config, wait_for_signal()).await,
+        ["migrate"] => migrate(&config, false),
+        ["migrate", "--dry-run"] => migrate(&config, true),
+        ["check-tokens"] => check_tokens(&config).await,
+        ["export", file] => match cli::export(&config, Path::new(file)) {
+            Ok(count) => println!("exported {} rows to {}", count, file),
+            Err(e) => fail(&format!("export {}", file), e),
+        },
+        ["import", file] => match cli::import(&config, Path::new(file)) {
+            Ok(count) => println!("imported {} rows from {}", count, file),
+            Err(e) => fail(&format!("import {}", file), e),
+        },
+        ["add-user", id, label] => add_user(&config, id, label, None),
+        ["add-user", id, label, role] => add_user(&config, id, label, Some(role)),
+        ["replay", file, ref flags @ ..] => replay(config, file, flags).await,
+        ["restore", file] => restore(&config, file),
+        _ => usage(),
     }
+}
 
-    // 取到所有 twitter token 有效的用户
-    let user_vec = users
-        .filter(twitter_status.eq(true))
-        .load::<User>(&db_pool.get().unwrap())
-        .unwrap();
-
-    let ts = Arc::new(RwLock::new(TwitterSubscriber::new(
-        tx,
-        sub_tx_clone,
-        bot.clone(),
-        blacklist_map,
-        &user_vec,
-    )));
-
-    let ts_clone = ts.clone();
-    tokio::spawn(async move { TwitterSubscriber::subscribe_worker(ts_clone, sub_rx).await });
-
-    let ts_clone = ts.clone();
-    tg_ctx.set_twitter_subscriber(Some(ts_clone));
-
-    let ts_clone = ts.clone();
-    let sub_tx_clone = sub_tx.clone();
-    let bot_clone = bot.clone();
-    tokio::spawn(async {
-        run_twitter_subscriber(bot_clone, sub_tx_clone, ts_clone, db_pool, user_vec).await;
-    });
-
-    let ts_clone = ts.clone();
-    let forward_history_cache: Arc<Cache<String, ()>> =
-        Arc::new(Cache::new(Some(Duration::from_secs(60 * 60 * 24 * 3))));
-    tokio::spawn({
-        let cache = Arc::clone(&forward_history_cache);
-        async move {
-            loop {
-                tokio::time::sleep(Duration::from_secs(60 * 60)).await;
-                cache.remove_expired().await;
-            }
-        }
-    });
-    tokio::spawn(async move {
-        TwitterSubscriber::forward_tweet(forward_history_cache, ts_clone, rx).await
-    });
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    std::process::exit(2);
+}
 
-    telegram_bot::run(bot.clone(), Arc::new(tg_ctx)).await;
+fn fail(what: &str, e: anyhow::Error) -> ! {
+    error!("{}: {}", what, e);
+    std::process::exit(1);
 }
 
-async fn run_twitter_subscriber(
-    tg_bot: AutoSend<DefaultParseMode<Bot>>,
-    sub_tx: Sender<String>,
-    ts: Arc<RwLock<TwitterSubscriber>>,
-    db_pool: DbPool,
-    user_vec: Vec<User>,
-) {
-    let mut valid_user_id_vec: Vec<i64> = Vec::new();
-    let mut ts_writer = ts.write().await;
-    for u in &user_vec {
-        if let Err(e) = ts_writer
-            .add_token(u.id, u.twitter_access_token.as_ref().unwrap())
-            .await
-        {
-            error!("add twitter token: {:?}", e);
-            if e.to_string().contains("expired") {
-                user_model::update_twitter_token(
-                    &db_pool.get().unwrap(),
-                    u.id,
-                    "".to_string(),
-                    false,
-                )
-                .unwrap();
-                let res = tg_bot
-                    .send_message(UserId(u.id as u64), escape(&e.to_string()))
-                    .await;
-                if let Err(err) = res {
-                    error!("telegram@{} {:?}", &u.id, &err);
-                }
+fn migrate(config: &Config, dry_run: bool) {
+    match cli::migrate(config, dry_run) {
+        Ok(pending) if pending.is_empty() => println!("database is up to date"),
+        Ok(pending) => {
+            println!(
+                "{} {} migrations:",
+                if dry_run { "pending" } else { "ran" },
+                pending.len()
+            );
+            for name in pending {
+                println!("  {}", name);
             }
-        } else {
-            valid_user_id_vec.push(u.id);
         }
+        Err(e) => fail("migrate", e),
     }
+}
 
-    // 取到所有有效用户的 follow 的 twitter id
-    let follow_vec = follows
-        .filter(user_id.eq_any(valid_user_id_vec))
-        .group_by(twitter_user_id)
-        .load::<Follow>(&db_pool.get().unwrap())
-        .unwrap();
-    drop(ts_writer);
+// 有无效 token 时返回非零，便于在脚本中使用
+async fn check_tokens(config: &Config) {
+    let checks = match cli::check_tokens(config).await {
+        Ok(checks) => checks,
+        Err(e) => fail("check tokens", e),
+    };
+    for c in &checks {
+        println!("{}\t{}\t{}", c.user_id, c.label, c.status);
+    }
+    let invalid = checks
+        .iter()
+        .filter(|c| c.status != cli::TokenStatus::Valid)
+        .count();
+    println!("{} tokens checked, {} invalid", checks.len(), invalid);
+    if invalid > 0 {
+        std::process::exit(1);
+    }
+}
 
-    // 加入监听
-    let mut ts_writer2 = ts.write().await;
-    for f in follow_vec {
-        if !ts_writer2.block_rt_count_map.contains_key(&f.user_id) {
-            ts_writer2.block_rt_count_map.insert(
-                f.user_id,
-                HashMap::from([(f.twitter_user_id, f.block_rt_count)]),
-            );
-        } else {
-            ts_writer2
-                .block_rt_count_map
-                .get_mut(&f.user_id)
-                .unwrap()
-                .insert(f.twitter_user_id, f.block_rt_count);
-        }
+fn add_user(config: &Config, id: &str, label: &str, role: Option<&str>) {
+    let id = id.parse::<i64>().unwrap_or_else(|_| usage());
+    let role = role.map(|r| cli::parse_role(r).unwrap_or_else(|| usage()));
+    match cli::add_user(config, id, label, role) {
+        Ok(()) => println!("added user {} {}", id, label),
+        Err(e) => fail(&format!("add user {}", id), e),
+    }
+}
 
-        if !ts_writer2.follow_rt_count_map.contains_key(&f.user_id) {
-            ts_writer2.follow_rt_count_map.insert(
-                f.user_id,
-                HashMap::from([(f.twitter_user_id, f.follow_rt_count)]),
-            );
-        } else {
-            ts_writer2
-                .follow_rt_count_map
-                .get_mut(&f.user_id)
-                .unwrap()
-                .insert(f.twitter_user_id, f.follow_rt_count);
-        }
+async fn replay(config: Arc<Config>, file: &str, mut flags: &[&str]) {
+    let mut options = app::ReplayOptions::default();
+    loop {
+        flags = match flags {
+            [] => break,
+            ["--live", rest @ ..] => {
+                options.live = true;
+                rest
+            }
+            ["--speed", speed, rest @ ..] => {
+                options.speed = match speed.parse::<f64>() {
+                    Ok(speed) if speed >= 0.0 && speed.is_finite() => speed,
+                    _ => usage(),
+                };
+                rest
+            }
+            _ => usage(),
+        };
+    }
+    match app::replay(config, Path::new(file), options).await {
+        Ok(count) => println!("replayed {} messages from {}", count, file),
+        Err(e) => fail(&format!("replay {}", file), e),
+    }
+}
 
-        ts_writer2.add_follow(f, 0).await.unwrap();
+// 用备份替换数据库，需要先停止 bot
+fn restore(config: &Config, file: &str) {
+    match backup::restore(&config.database, Path::new(file)) {
+        Ok(Some(old)) => println!(
+            "restored {}, the previous database is kept at {}",
+            file,
+            old.display()
+        ),
+        Ok(None) => println!("restored {}", file),
+        Err(e) => fail(&format!("restore {}", file), e),
     }
-    drop(ts_writer2);
+}
 
-    // 更新监控
-    for u in &user_vec {
-        sub_tx
-            .send(u.twitter_access_token.as_ref().unwrap().clone())
-            .await
-            .unwrap();
+async fn wait_for_signal() {
+    let mut sigterm = signal(SignalKind::terminate()).unwrap();
+    tokio::select! {
+        _ = sigterm.recv() => {},
+        _ = tokio::signal::ctrl_c() => {},
     }
 }
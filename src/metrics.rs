@@ -0,0 +1,133 @@
+use lazy_static::lazy_static;
+use prometheus::{
+    register_int_counter, register_int_counter_vec, register_int_gauge, register_int_gauge_vec,
+    Encoder, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, TextEncoder,
+};
+use teloxide::RequestError;
+
+lazy_static! {
+    pub static ref TWEETS_RECEIVED: IntCounterVec = register_int_counter_vec!(
+        "t2t_tweets_received_total",
+        "Tweets received from the twitter stream",
+        &["token"]
+    )
+    .unwrap();
+    pub static ref TWEETS_FORWARDED: IntCounter = register_int_counter!(
+        "t2t_tweets_forwarded_total",
+        "Tweets forwarded to telegram users"
+    )
+    .unwrap();
+    pub static ref TWEETS_FILTERED: IntCounterVec = register_int_counter_vec!(
+        "t2t_tweets_filtered_total",
+        "Tweets not forwarded, by reason",
+        &["reason"]
+    )
+    .unwrap();
+    pub static ref TWEETS_DEDUPED: IntCounter = register_int_counter!(
+        "t2t_tweets_deduped_total",
+        "Tweets skipped because they were already forwarded to the user"
+    )
+    .unwrap();
+    pub static ref TWEETS_DELETED: IntCounterVec = register_int_counter_vec!(
+        "t2t_tweets_deleted_total",
+        "Forwarded tweets deleted on twitter, by how the telegram message was handled",
+        &["action"]
+    )
+    .unwrap();
+    pub static ref TELEGRAM_SEND_FAILURES: IntCounterVec = register_int_counter_vec!(
+        "t2t_telegram_send_failures_total",
+        "Failed telegram requests, by error kind",
+        &["kind"]
+    )
+    .unwrap();
+    pub static ref STREAM_CONTROL_MESSAGES: IntCounterVec = register_int_counter_vec!(
+        "t2t_stream_control_messages_total",
+        "Control messages from the twitter stream such as limit, disconnect and warning",
+        &["token", "kind"]
+    )
+    .unwrap();
+    pub static ref STREAM_CONNECTIONS: IntGauge = register_int_gauge!(
+        "t2t_stream_connections",
+        "Active twitter stream connections"
+    )
+    .unwrap();
+    pub static ref STREAM_RECONNECTS: IntCounterVec = register_int_counter_vec!(
+        "t2t_stream_reconnects_total",
+        "Twitter stream reconnects after an error",
+        &["token"]
+    )
+    .unwrap();
+    pub static ref FOLLOWS_PER_TOKEN: IntGaugeVec = register_int_gauge_vec!(
+        "t2t_follows_per_token",
+        "Twitter accounts assigned to each token",
+        &["token"]
+    )
+    .unwrap();
+    pub static ref FOLLOWS_UNASSIGNED: IntGauge = register_int_gauge!(
+        "t2t_follows_unassigned",
+        "Twitter accounts waiting for a token with free capacity"
+    )
+    .unwrap();
+    pub static ref DB_POOL_CONNECTIONS: IntGaugeVec = register_int_gauge_vec!(
+        "t2t_db_pool_connections",
+        "Database pool connections, by state",
+        &["state"]
+    )
+    .unwrap();
+}
+
+// token 的 md5 前 8 位作为标签，避免泄露 token
+pub fn token_label(hash: &str) -> &str {
+    &hash[..hash.len().min(8)]
+}
+
+pub fn telegram_error_kind(e: &RequestError) -> String {
+    match e {
+        RequestError::Api(api) => format!("api_{:?}", api)
+            .split('(')
+            .next()
+            .unwrap()
+            .to_string(),
+        RequestError::MigrateToChatId(_) => "migrate_to_chat".to_string(),
+        RequestError::RetryAfter(_) => "retry_after".to_string(),
+        RequestError::Network(_) => "network".to_string(),
+        RequestError::InvalidJson { .. } => "invalid_json".to_string(),
+        RequestError::Io(_) => "io".to_string(),
+    }
+}
+
+pub fn record_telegram_error(e: &RequestError) {
+    TELEGRAM_SEND_FAILURES
+        .with_label_values(&[&telegram_error_kind(e)])
+        .inc();
+}
+
+// 在线的 stream 连接计数，drop 时自动减一
+pub struct StreamConnectionGuard;
+
+impl StreamConnectionGuard {
+    pub fn new() -> Self {
+        STREAM_CONNECTIONS.inc();
+        StreamConnectionGuard
+    }
+}
+
+impl Default for StreamConnectionGuard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for StreamConnectionGuard {
+    fn drop(&mut self) {
+        STREAM_CONNECTIONS.dec();
+    }
+}
+
+pub fn encode() -> String {
+    let mut buffer = Vec::new();
+    TextEncoder::new()
+        .encode(&prometheus::gather(), &mut buffer)
+        .unwrap();
+    String::from_utf8(buffer).unwrap()
+}
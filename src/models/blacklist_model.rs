@@ -1,8 +1,9 @@
 use crate::models::schema::blacklists::dsl::*;
-use anyhow::anyhow;
+use crate::models::{DbConnection, DbError};
 use chrono::NaiveDateTime;
 use diesel::prelude::*;
-use diesel::{Queryable, RunQueryDsl, SqliteConnection};
+use diesel::{Queryable, RunQueryDsl};
+use serde::{Deserialize, Serialize};
 
 pub enum BlacklistType {
     BlockRT,
@@ -18,7 +19,7 @@ impl BlacklistType {
     }
 }
 
-#[derive(Clone, Queryable)]
+#[derive(Clone, Queryable, Serialize, Deserialize)]
 pub struct Blacklist {
     pub id: Option<i32>,
     pub user_id: i64,
@@ -29,23 +30,19 @@ pub struct Blacklist {
 }
 
 pub fn unblock(
-    conn: &SqliteConnection,
+    conn: &DbConnection,
     x_user_id: i64,
     x_twitter_user_id: i64,
     x_type: i32,
-) -> Result<usize, anyhow::Error> {
-    let res = diesel::delete(blacklists.filter(user_id.eq(x_user_id)))
+) -> Result<usize, DbError> {
+    Ok(diesel::delete(blacklists.filter(user_id.eq(x_user_id)))
         .filter(twitter_user_id.eq(x_twitter_user_id))
         .filter(type_.eq(x_type))
-        .execute(conn);
-    match res {
-        Ok(cound) => Ok(cound),
-        Err(e) => Err(anyhow!("{:?}", e)),
-    }
+        .execute(conn)?)
 }
 
-pub fn block_user(conn: &SqliteConnection, b: Blacklist) -> Result<usize, anyhow::Error> {
-    let res = diesel::insert_into(blacklists)
+pub fn block_user(conn: &DbConnection, b: Blacklist) -> Result<usize, DbError> {
+    Ok(diesel::insert_into(blacklists)
         .values((
             user_id.eq(b.user_id),
             twitter_user_id.eq(b.twitter_user_id),
@@ -53,32 +50,20 @@ pub fn block_user(conn: &SqliteConnection, b: Blacklist) -> Result<usize, anyhow
             created_at.eq(b.created_at),
             type_.eq(b.type_),
         ))
-        .execute(conn);
-    match res {
-        Ok(cound) => Ok(cound),
-        Err(e) => Err(anyhow!("{:?}", e)),
-    }
+        .execute(conn)?)
 }
 
-pub fn get_all_blacklist(conn: &SqliteConnection) -> Result<Vec<Blacklist>, anyhow::Error> {
-    let res = blacklists.load::<Blacklist>(conn);
-    match res {
-        Ok(vec) => Ok(vec),
-        Err(e) => Err(anyhow!("{:?}", e)),
-    }
+pub fn get_all_blacklist(conn: &DbConnection) -> Result<Vec<Blacklist>, DbError> {
+    Ok(blacklists.load::<Blacklist>(conn)?)
 }
 
 pub fn get_blacklist_by_user_id(
-    conn: &SqliteConnection,
+    conn: &DbConnection,
     x_user_id: i64,
     x_type: i32,
-) -> Result<Vec<Blacklist>, anyhow::Error> {
-    let res = blacklists
+) -> Result<Vec<Blacklist>, DbError> {
+    Ok(blacklists
         .filter(user_id.eq(x_user_id))
         .filter(type_.eq(x_type))
-        .load::<Blacklist>(conn);
-    match res {
-        Ok(vec) => Ok(vec),
-        Err(e) => Err(anyhow!("{:?}", e)),
-    }
+        .load::<Blacklist>(conn)?)
 }
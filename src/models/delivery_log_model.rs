@@ -0,0 +1,117 @@
+use crate::models::schema::delivery_logs::dsl::*;
+use crate::models::{DbConnection, DbError};
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use diesel::{QueryDsl, Queryable, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+
+// forward_tweet 对每个用户做出的推送决定
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Decision {
+    Delivered,
+    Suspended,
+    DisableRetweet,
+    DisableTextMsg,
+    Duplicate,
+    BlockTwitter,
+    BlockRT,
+    SendError,
+    DryRun,
+}
+
+impl Decision {
+    pub fn toi32(&self) -> i32 {
+        match self {
+            Decision::Delivered => 1,
+            Decision::Suspended => 2,
+            Decision::DisableRetweet => 3,
+            Decision::DisableTextMsg => 4,
+            Decision::Duplicate => 5,
+            Decision::BlockTwitter => 6,
+            Decision::BlockRT => 7,
+            Decision::SendError => 8,
+            Decision::DryRun => 9,
+        }
+    }
+
+    pub fn from_i32(x_decision: i32) -> Option<Decision> {
+        match x_decision {
+            1 => Some(Decision::Delivered),
+            2 => Some(Decision::Suspended),
+            3 => Some(Decision::DisableRetweet),
+            4 => Some(Decision::DisableTextMsg),
+            5 => Some(Decision::Duplicate),
+            6 => Some(Decision::BlockTwitter),
+            7 => Some(Decision::BlockRT),
+            8 => Some(Decision::SendError),
+            9 => Some(Decision::DryRun),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Queryable, Serialize, Deserialize)]
+pub struct DeliveryLog {
+    pub id: Option<i32>,
+    pub user_id: i64,
+    pub tweet_id: i64,
+    pub status_id: i64,
+    pub screen_name: String,
+    pub decision: i32,
+    pub detail: String,
+    pub created_at: NaiveDateTime,
+}
+
+pub fn insert_logs(conn: &DbConnection, logs: Vec<DeliveryLog>) -> Result<usize, DbError> {
+    conn.transaction::<usize, DbError, _>(|| {
+        let mut count = 0;
+        for l in logs {
+            count += diesel::insert_into(delivery_logs)
+                .values((
+                    user_id.eq(l.user_id),
+                    tweet_id.eq(l.tweet_id),
+                    status_id.eq(l.status_id),
+                    screen_name.eq(l.screen_name),
+                    decision.eq(l.decision),
+                    detail.eq(l.detail),
+                    created_at.eq(l.created_at),
+                ))
+                .execute(conn)?;
+        }
+        Ok(count)
+    })
+}
+
+// 按原推文或转推 ID 查询，最新的在前
+pub fn get_logs_by_tweet(
+    conn: &DbConnection,
+    x_user_id: i64,
+    x_tweet_id: i64,
+) -> Result<Vec<DeliveryLog>, DbError> {
+    Ok(delivery_logs
+        .filter(user_id.eq(x_user_id))
+        .filter(tweet_id.eq(x_tweet_id).or(status_id.eq(x_tweet_id)))
+        .order(id.desc())
+        .load::<DeliveryLog>(conn)?)
+}
+
+// 每个用户只保留最近 keep 条记录
+pub fn prune_logs(conn: &DbConnection, keep: i64) -> Result<usize, DbError> {
+    let users = delivery_logs.select(user_id).distinct().load::<i64>(conn)?;
+    let mut count = 0;
+    for uid in users {
+        let oldest_kept = delivery_logs
+            .select(id)
+            .filter(user_id.eq(uid))
+            .order(id.desc())
+            .offset(keep - 1)
+            .first::<Option<i32>>(conn)
+            .optional()?;
+        if let Some(Some(oldest_kept)) = oldest_kept {
+            count += diesel::delete(delivery_logs.filter(user_id.eq(uid)))
+                .filter(id.lt(oldest_kept))
+                .execute(conn)?;
+        }
+    }
+    Ok(count)
+}
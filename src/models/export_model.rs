@@ -0,0 +1,228 @@
+use crate::models::blacklist_model::Blacklist;
+use crate::models::delivery_log_model::DeliveryLog;
+use crate::models::follow_model::Follow;
+use crate::models::forward_history_model::ForwardHistory;
+use crate::models::invite_model::Invite;
+use crate::models::role_model::UserRole;
+use crate::models::schema::{
+    blacklists, delivery_logs, follows, forward_history, invites, roles, sent_messages,
+    tweet_recipients, tweets, users,
+};
+use crate::models::sent_message_model::SentMessage;
+use crate::models::tweet_model::Tweet;
+use crate::models::user_model::User;
+use crate::models::{DbConnection, DbError};
+use diesel::prelude::*;
+use diesel::{QueryDsl, Queryable, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Queryable, Serialize, Deserialize)]
+pub struct TweetRecipient {
+    pub tweet_id: i64,
+    pub user_id: i64,
+}
+
+// 导出的全部数据，token 保持数据库中的加密形式，导入时需要配置相同的 token_key
+// 旧版本导出的文件缺少的表按空处理
+#[derive(Serialize, Deserialize)]
+pub struct Export {
+    // 导出时数据库最新的迁移版本
+    pub schema_version: String,
+    #[serde(default)]
+    pub users: Vec<User>,
+    #[serde(default)]
+    pub roles: Vec<UserRole>,
+    #[serde(default)]
+    pub follows: Vec<Follow>,
+    #[serde(default)]
+    pub blacklists: Vec<Blacklist>,
+    #[serde(default)]
+    pub invites: Vec<Invite>,
+    #[serde(default)]
+    pub tweets: Vec<Tweet>,
+    #[serde(default)]
+    pub tweet_recipients: Vec<TweetRecipient>,
+    #[serde(default)]
+    pub delivery_logs: Vec<DeliveryLog>,
+    #[serde(default)]
+    pub forward_history: Vec<ForwardHistory>,
+    #[serde(default)]
+    pub sent_messages: Vec<SentMessage>,
+}
+
+impl Export {
+    // 总行数
+    pub fn len(&self) -> usize {
+        self.users.len()
+            + self.roles.len()
+            + self.follows.len()
+            + self.blacklists.len()
+            + self.invites.len()
+            + self.tweets.len()
+            + self.tweet_recipients.len()
+            + self.delivery_logs.len()
+            + self.forward_history.len()
+            + self.sent_messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// 没有任何用户时才允许导入
+pub fn is_empty(conn: &DbConnection) -> Result<bool, DbError> {
+    Ok(users::table.count().get_result::<i64>(conn)? == 0)
+}
+
+// 按主键顺序读出所有表，自增 id 导入时重新生成但保持先后顺序
+pub fn export_all(conn: &DbConnection, schema_version: String) -> Result<Export, DbError> {
+    Ok(Export {
+        schema_version,
+        users: users::table.order(users::id).load::<User>(conn)?,
+        roles: roles::table.order(roles::user_id).load::<UserRole>(conn)?,
+        follows: follows::table.order(follows::id).load::<Follow>(conn)?,
+        blacklists: blacklists::table
+            .order(blacklists::id)
+            .load::<Blacklist>(conn)?,
+        invites: invites::table.order(invites::code).load::<Invite>(conn)?,
+        tweets: tweets::table.order(tweets::id).load::<Tweet>(conn)?,
+        tweet_recipients: tweet_recipients::table
+            .order((tweet_recipients::tweet_id, tweet_recipients::user_id))
+            .load::<TweetRecipient>(conn)?,
+        delivery_logs: delivery_logs::table
+            .order(delivery_logs::id)
+            .load::<DeliveryLog>(conn)?,
+        forward_history: forward_history::table
+            .order(forward_history::cache_key)
+            .load::<ForwardHistory>(conn)?,
+        sent_messages: sent_messages::table
+            .order(sent_messages::id)
+            .load::<SentMessage>(conn)?,
+    })
+}
+
+// 在一个事务中写入所有数据，任何一行失败都不会留下部分数据
+pub fn import_all(conn: &DbConnection, data: Export) -> Result<usize, DbError> {
+    conn.transaction::<usize, DbError, _>(|| {
+        let mut count = 0;
+        for u in data.users {
+            count += diesel::insert_into(users::table)
+                .values((
+                    users::id.eq(u.id),
+                    users::label.eq(u.label),
+                    users::twitter_access_token.eq(u.twitter_access_token),
+                    users::twitter_status.eq(u.twitter_status),
+                    users::created_at.eq(u.created_at),
+                    users::disable_retweet.eq(u.disable_retweet),
+                    users::disable_text_msg.eq(u.disable_text_msg),
+                    users::suspended.eq(u.suspended),
+                    users::follow_quota.eq(u.follow_quota),
+                    users::dry_run.eq(u.dry_run),
+                    users::remove_deleted.eq(u.remove_deleted),
+                ))
+                .execute(conn)?;
+        }
+        for r in data.roles {
+            count += diesel::insert_into(roles::table)
+                .values((
+                    roles::user_id.eq(r.user_id),
+                    roles::role.eq(r.role),
+                    roles::created_at.eq(r.created_at),
+                ))
+                .execute(conn)?;
+        }
+        for f in data.follows {
+            count += diesel::insert_into(follows::table)
+                .values((
+                    follows::user_id.eq(f.user_id),
+                    follows::twitter_user_id.eq(f.twitter_user_id),
+                    follows::twitter_username.eq(f.twitter_username),
+                    follows::created_at.eq(f.created_at),
+                    follows::follow_rt_count.eq(f.follow_rt_count),
+                    follows::block_rt_count.eq(f.block_rt_count),
+                ))
+                .execute(conn)?;
+        }
+        for b in data.blacklists {
+            count += diesel::insert_into(blacklists::table)
+                .values((
+                    blacklists::user_id.eq(b.user_id),
+                    blacklists::twitter_user_id.eq(b.twitter_user_id),
+                    blacklists::twitter_username.eq(b.twitter_username),
+                    blacklists::created_at.eq(b.created_at),
+                    blacklists::type_.eq(b.type_),
+                ))
+                .execute(conn)?;
+        }
+        for i in data.invites {
+            count += diesel::insert_into(invites::table)
+                .values((
+                    invites::code.eq(i.code),
+                    invites::label.eq(i.label),
+                    invites::max_uses.eq(i.max_uses),
+                    invites::used_count.eq(i.used_count),
+                    invites::expires_at.eq(i.expires_at),
+                    invites::created_by.eq(i.created_by),
+                    invites::created_at.eq(i.created_at),
+                ))
+                .execute(conn)?;
+        }
+        for t in data.tweets {
+            count += diesel::insert_into(tweets::table)
+                .values((
+                    tweets::id.eq(t.id),
+                    tweets::twitter_user_id.eq(t.twitter_user_id),
+                    tweets::screen_name.eq(t.screen_name),
+                    tweets::text.eq(t.text),
+                    tweets::media_urls.eq(t.media_urls),
+                    tweets::url.eq(t.url),
+                    tweets::created_at.eq(t.created_at),
+                ))
+                .execute(conn)?;
+        }
+        for r in data.tweet_recipients {
+            count += diesel::insert_into(tweet_recipients::table)
+                .values((
+                    tweet_recipients::tweet_id.eq(r.tweet_id),
+                    tweet_recipients::user_id.eq(r.user_id),
+                ))
+                .execute(conn)?;
+        }
+        for l in data.delivery_logs {
+            count += diesel::insert_into(delivery_logs::table)
+                .values((
+                    delivery_logs::user_id.eq(l.user_id),
+                    delivery_logs::tweet_id.eq(l.tweet_id),
+                    delivery_logs::status_id.eq(l.status_id),
+                    delivery_logs::screen_name.eq(l.screen_name),
+                    delivery_logs::decision.eq(l.decision),
+                    delivery_logs::detail.eq(l.detail),
+                    delivery_logs::created_at.eq(l.created_at),
+                ))
+                .execute(conn)?;
+        }
+        for h in data.forward_history {
+            count += diesel::insert_into(forward_history::table)
+                .values((
+                    forward_history::cache_key.eq(h.cache_key),
+                    forward_history::expires_at.eq(h.expires_at),
+                ))
+                .execute(conn)?;
+        }
+        for m in data.sent_messages {
+            count += diesel::insert_into(sent_messages::table)
+                .values((
+                    sent_messages::user_id.eq(m.user_id),
+                    sent_messages::tweet_id.eq(m.tweet_id),
+                    sent_messages::status_id.eq(m.status_id),
+                    sent_messages::message_id.eq(m.message_id),
+                    sent_messages::media_message_ids.eq(m.media_message_ids),
+                    sent_messages::text.eq(m.text),
+                    sent_messages::created_at.eq(m.created_at),
+                ))
+                .execute(conn)?;
+        }
+        Ok(count)
+    })
+}
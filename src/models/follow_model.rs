@@ -1,10 +1,11 @@
 use crate::models::schema::follows::dsl::*;
-use anyhow::anyhow;
+use crate::models::{DbConnection, DbError};
 use chrono::NaiveDateTime;
 use diesel::prelude::*;
-use diesel::{QueryDsl, Queryable, RunQueryDsl, SqliteConnection};
+use diesel::{QueryDsl, Queryable, RunQueryDsl};
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Queryable)]
+#[derive(Clone, Queryable, Serialize, Deserialize)]
 pub struct Follow {
     pub id: Option<i32>,
     pub user_id: i64,
@@ -15,59 +16,56 @@ pub struct Follow {
     pub block_rt_count: i64,
 }
 
-pub fn create_follow(conn: &SqliteConnection, f: Follow) -> Result<usize, anyhow::Error> {
-    let res = diesel::insert_into(follows)
+pub fn create_follow(conn: &DbConnection, f: Follow) -> Result<usize, DbError> {
+    Ok(diesel::insert_into(follows)
         .values((
             user_id.eq(f.user_id),
             twitter_user_id.eq(f.twitter_user_id),
             twitter_username.eq(f.twitter_username),
             created_at.eq(f.created_at),
         ))
-        .execute(conn);
-    match res {
-        Ok(cound) => Ok(cound),
-        Err(e) => Err(anyhow!("{:?}", e)),
-    }
+        .execute(conn)?)
 }
 
 pub fn unfollow(
-    conn: &SqliteConnection,
+    conn: &DbConnection,
     x_user_id: i64,
     x_twitter_user_id: i64,
-) -> Result<usize, anyhow::Error> {
-    let res = diesel::delete(follows.filter(user_id.eq(x_user_id)))
+) -> Result<usize, DbError> {
+    Ok(diesel::delete(follows.filter(user_id.eq(x_user_id)))
         .filter(twitter_user_id.eq(x_twitter_user_id))
-        .execute(conn);
-    match res {
-        Ok(cound) => Ok(cound),
-        Err(e) => Err(anyhow!("{:?}", e)),
-    }
+        .execute(conn)?)
 }
 
-pub fn get_follows_by_user_id(
-    conn: &SqliteConnection,
-    x_user_id: i64,
-) -> Result<Vec<Follow>, anyhow::Error> {
-    let res = follows.filter(user_id.eq(x_user_id)).load::<Follow>(conn);
-    match res {
-        Ok(vec) => Ok(vec),
-        Err(e) => Err(anyhow!("{:?}", e)),
-    }
+pub fn get_follows_by_user_id(conn: &DbConnection, x_user_id: i64) -> Result<Vec<Follow>, DbError> {
+    Ok(follows.filter(user_id.eq(x_user_id)).load::<Follow>(conn)?)
+}
+
+pub fn get_follows_by_user_ids(
+    conn: &DbConnection,
+    x_user_ids: &[i64],
+) -> Result<Vec<Follow>, DbError> {
+    Ok(follows
+        .filter(user_id.eq_any(x_user_ids))
+        .load::<Follow>(conn)?)
+}
+
+pub fn count_follows_by_user_id(conn: &DbConnection, x_user_id: i64) -> Result<i64, DbError> {
+    Ok(follows
+        .filter(user_id.eq(x_user_id))
+        .count()
+        .get_result::<i64>(conn)?)
 }
 
-pub fn get_all_follows(conn: &SqliteConnection) -> Result<Vec<Follow>, anyhow::Error> {
-    let res = follows.load::<Follow>(conn);
-    match res {
-        Ok(vec) => Ok(vec),
-        Err(e) => Err(anyhow!("{:?}", e)),
-    }
+pub fn get_all_follows(conn: &DbConnection) -> Result<Vec<Follow>, DbError> {
+    Ok(follows.load::<Follow>(conn)?)
 }
 
 pub fn increase_block_rt_count(
-    conn: &SqliteConnection,
+    conn: &DbConnection,
     x_user_id: i64,
     x_twitter_user_id: i64,
-) -> Result<usize, anyhow::Error> {
+) -> Result<usize, DbError> {
     let res = diesel::update(
         follows
             .filter(user_id.eq(x_user_id))
@@ -79,10 +77,10 @@ pub fn increase_block_rt_count(
 }
 
 pub fn increase_follow_rt_count(
-    conn: &SqliteConnection,
+    conn: &DbConnection,
     x_user_id: i64,
     x_twitter_user_id: i64,
-) -> Result<usize, anyhow::Error> {
+) -> Result<usize, DbError> {
     let res = diesel::update(
         follows
             .filter(user_id.eq(x_user_id))
@@ -0,0 +1,38 @@
+use crate::models::schema::forward_history::dsl::*;
+use crate::models::{DbConnection, DbError};
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use diesel::{QueryDsl, Queryable, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Queryable, Serialize, Deserialize)]
+pub struct ForwardHistory {
+    pub cache_key: String,
+    pub expires_at: NaiveDateTime,
+}
+
+pub fn get_forward_history(
+    conn: &DbConnection,
+    now: NaiveDateTime,
+) -> Result<Vec<ForwardHistory>, DbError> {
+    Ok(forward_history
+        .filter(expires_at.gt(now))
+        .load::<ForwardHistory>(conn)?)
+}
+
+// 用内存中的推送记录整体替换数据库中的记录
+pub fn replace_forward_history(
+    conn: &DbConnection,
+    records: Vec<ForwardHistory>,
+) -> Result<usize, DbError> {
+    conn.transaction::<usize, DbError, _>(|| {
+        diesel::delete(forward_history).execute(conn)?;
+        let mut count = 0;
+        for r in records {
+            count += diesel::insert_into(forward_history)
+                .values((cache_key.eq(r.cache_key), expires_at.eq(r.expires_at)))
+                .execute(conn)?;
+        }
+        Ok(count)
+    })
+}
@@ -0,0 +1,69 @@
+use crate::models::schema::invites::dsl::*;
+use crate::models::user_model::{self, User};
+use crate::models::{DbConnection, DbError};
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use diesel::{QueryDsl, Queryable, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Queryable, Serialize, Deserialize)]
+pub struct Invite {
+    pub code: String,
+    pub label: String,
+    pub max_uses: i32,
+    pub used_count: i32,
+    pub expires_at: NaiveDateTime,
+    pub created_by: i64,
+    pub created_at: NaiveDateTime,
+}
+
+pub fn create_invite(conn: &DbConnection, i: Invite) -> Result<usize, DbError> {
+    Ok(diesel::insert_into(invites)
+        .values((
+            code.eq(i.code),
+            label.eq(i.label),
+            max_uses.eq(i.max_uses),
+            used_count.eq(i.used_count),
+            expires_at.eq(i.expires_at),
+            created_by.eq(i.created_by),
+            created_at.eq(i.created_at),
+        ))
+        .execute(conn)?)
+}
+
+pub fn get_all_invites(conn: &DbConnection) -> Result<Vec<Invite>, DbError> {
+    Ok(invites.order(created_at.desc()).load::<Invite>(conn)?)
+}
+
+pub fn revoke_invite(conn: &DbConnection, x_code: &str) -> Result<usize, DbError> {
+    Ok(diesel::delete(invites.filter(code.eq(x_code))).execute(conn)?)
+}
+
+// 使用邀请码创建用户，邀请码的默认备注名作为用户备注名，邀请码无效或过期时返回 None
+pub fn redeem_invite(
+    conn: &DbConnection,
+    x_code: &str,
+    mut u: User,
+    now: NaiveDateTime,
+) -> Result<Option<User>, DbError> {
+    conn.transaction::<Option<User>, DbError, _>(|| {
+        // 在数据库中检查并计数，并发使用时不会超过次数
+        let updated = diesel::update(
+            invites
+                .filter(code.eq(x_code))
+                .filter(used_count.lt(max_uses))
+                .filter(expires_at.gt(now)),
+        )
+        .set(used_count.eq(used_count + 1))
+        .execute(conn)?;
+        if updated == 0 {
+            return Ok(None);
+        }
+        u.label = invites
+            .select(label)
+            .filter(code.eq(x_code))
+            .first::<String>(conn)?;
+        user_model::create_user(conn, u.clone())?;
+        Ok(Some(u))
+    })
+}
@@ -1,19 +1,117 @@
 pub mod blacklist_model;
+pub mod delivery_log_model;
+pub mod export_model;
 pub mod follow_model;
+pub mod forward_history_model;
+pub mod invite_model;
+pub mod repository;
+pub mod role_model;
 pub mod schema;
+pub mod sent_message_model;
+pub mod tweet_model;
 pub mod user_model;
 
-use diesel::{
-    r2d2::{ConnectionManager, Pool},
-    sqlite::SqliteConnection,
-};
+pub use repository::{DbError, Repository};
 
-pub type DbPool = Pool<ConnectionManager<SqliteConnection>>;
+use std::io;
 
-pub fn establish_connection(database_url: &str) -> DbPool {
-    let manager = ConnectionManager::<SqliteConnection>::new(database_url);
-    Pool::builder()
-        .max_size(5)
+use diesel::r2d2::{ConnectionManager, Pool};
+
+use crate::config::DatabaseConfig;
+
+// 默认使用 SQLite，开启 postgres feature 后改用 PostgreSQL，两者的迁移脚本分开存放
+#[cfg(not(feature = "postgres"))]
+pub type DbConnection = diesel::sqlite::SqliteConnection;
+#[cfg(feature = "postgres")]
+pub type DbConnection = diesel::pg::PgConnection;
+
+// 迁移脚本编译进程序，不依赖运行时的当前目录
+// 和 embed_migrations! 生成的模块相同，另外导出全部迁移供 dry-run 和版本检查使用
+macro_rules! embedded_migrations {
+    ($path:literal) => {
+        #[allow(dead_code)]
+        mod embedded_migrations {
+            #[derive(EmbedMigrations)]
+            #[embed_migrations_options(migrations_path = $path)]
+            struct _Dummy;
+
+            // 按版本排序
+            pub fn all() -> &'static [&'static dyn Migration] {
+                ALL_MIGRATIONS
+            }
+        }
+    };
+}
+
+#[cfg(not(feature = "postgres"))]
+embedded_migrations!("migrations");
+#[cfg(feature = "postgres")]
+embedded_migrations!("migrations_postgres");
+
+pub type DbPool = Pool<ConnectionManager<DbConnection>>;
+
+// SQLite 写锁被占用时等待一会儿再报 database is locked
+#[cfg(not(feature = "postgres"))]
+const SQLITE_BUSY_TIMEOUT_MS: u64 = 1000;
+
+#[cfg(not(feature = "postgres"))]
+#[derive(Debug)]
+struct SqliteBusyTimeout;
+
+#[cfg(not(feature = "postgres"))]
+impl diesel::r2d2::CustomizeConnection<DbConnection, diesel::r2d2::Error> for SqliteBusyTimeout {
+    fn on_acquire(&self, conn: &mut DbConnection) -> Result<(), diesel::r2d2::Error> {
+        use diesel::connection::SimpleConnection;
+        conn.batch_execute(&format!("PRAGMA busy_timeout = {}", SQLITE_BUSY_TIMEOUT_MS))
+            .map_err(diesel::r2d2::Error::QueryError)
+    }
+}
+
+pub fn establish_connection(config: &DatabaseConfig) -> DbPool {
+    let manager = ConnectionManager::<DbConnection>::new(&config.url);
+    let builder = Pool::builder();
+    #[cfg(not(feature = "postgres"))]
+    let builder = builder.connection_customizer(Box::new(SqliteBusyTimeout));
+    builder
+        .max_size(config.pool_size)
+        .connection_timeout(config.pool_timeout())
         .build(manager)
-        .unwrap_or_else(|_| panic!("Error connecting to {}", database_url))
+        .unwrap_or_else(|_| panic!("Error connecting to {}", config.url))
+}
+
+pub fn run_migrations(conn: &DbConnection) -> Result<(), DbError> {
+    embedded_migrations::run_with_output(conn, &mut io::stdout())
+        .map_err(|e| DbError::Migration(e.to_string()))
+}
+
+// 程序内置的全部迁移版本，按版本排序
+pub fn known_migrations() -> Vec<String> {
+    embedded_migrations::all()
+        .iter()
+        .map(|m| m.version().to_string())
+        .collect()
+}
+
+// 尚未执行的迁移版本，按版本排序
+pub fn pending_migrations(conn: &DbConnection) -> Result<Vec<String>, DbError> {
+    use diesel_migrations::MigrationConnection;
+
+    diesel_migrations::setup_database(conn)?;
+    let applied = conn.previously_run_migration_versions()?;
+    Ok(known_migrations()
+        .into_iter()
+        .filter(|v| !applied.contains(v))
+        .collect())
+}
+
+// 已执行的最新迁移版本，空库返回空字符串
+pub fn schema_version(conn: &DbConnection) -> Result<String, DbError> {
+    use diesel_migrations::MigrationConnection;
+
+    diesel_migrations::setup_database(conn)?;
+    Ok(conn
+        .previously_run_migration_versions()?
+        .into_iter()
+        .max()
+        .unwrap_or_default())
 }
@@ -0,0 +1,90 @@
+use std::fmt;
+
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+
+use crate::models::{DbConnection, DbPool};
+
+#[derive(Debug)]
+pub enum DbError {
+    // 超时没有拿到连接，连接池耗尽或数据库暂时不可用
+    Pool(String),
+    NotFound,
+    Query(DieselError),
+    Migration(String),
+    // token 无法解密，通常是密钥配置有误
+    Token(String),
+    // 执行查询的后台任务异常退出
+    Task(String),
+}
+
+impl DbError {
+    // 稍后原样重试就可能成功的错误
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            DbError::Pool(_) => true,
+            DbError::Query(DieselError::DatabaseError(
+                DatabaseErrorKind::SerializationFailure,
+                _,
+            )) => true,
+            // SQLite 写锁被其他连接占用
+            DbError::Query(DieselError::DatabaseError(_, info)) => {
+                info.message().contains("is locked")
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DbError::Pool(e) => write!(f, "database connection unavailable: {}", e),
+            DbError::NotFound => write!(f, "record not found"),
+            DbError::Query(e) => write!(f, "database query failed: {}", e),
+            DbError::Migration(e) => write!(f, "database migration failed: {}", e),
+            DbError::Token(e) => write!(f, "{}", e),
+            DbError::Task(e) => write!(f, "database task failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for DbError {}
+
+impl From<DieselError> for DbError {
+    fn from(e: DieselError) -> Self {
+        match e {
+            DieselError::NotFound => DbError::NotFound,
+            e => DbError::Query(e),
+        }
+    }
+}
+
+// 在 blocking 线程池里执行同步的 diesel 查询，避免阻塞 tokio worker
+#[derive(Clone)]
+pub struct Repository {
+    pool: DbPool,
+}
+
+impl Repository {
+    pub fn new(pool: DbPool) -> Self {
+        Repository { pool }
+    }
+
+    pub fn pool(&self) -> &DbPool {
+        &self.pool
+    }
+
+    pub async fn run<T, F>(&self, f: F) -> Result<T, DbError>
+    where
+        F: FnOnce(&DbConnection) -> Result<T, DbError> + Send + 'static,
+        T: Send + 'static,
+    {
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let conn = pool.get().map_err(|e| DbError::Pool(e.to_string()))?;
+            f(&conn)
+        })
+        .await
+        .map_err(|e| DbError::Task(e.to_string()))?
+    }
+}
@@ -0,0 +1,74 @@
+use crate::models::schema::roles::dsl::*;
+use crate::models::{DbConnection, DbError};
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use diesel::{QueryDsl, Queryable, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    ReadOnly,
+    User,
+    Admin,
+    Owner,
+}
+
+impl Role {
+    pub fn toi32(&self) -> i32 {
+        match self {
+            Role::ReadOnly => 1,
+            Role::User => 2,
+            Role::Admin => 3,
+            Role::Owner => 4,
+        }
+    }
+
+    pub fn from_i32(x_role: i32) -> Option<Role> {
+        match x_role {
+            1 => Some(Role::ReadOnly),
+            2 => Some(Role::User),
+            3 => Some(Role::Admin),
+            4 => Some(Role::Owner),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Queryable, Serialize, Deserialize)]
+pub struct UserRole {
+    pub user_id: i64,
+    pub role: i32,
+    pub created_at: NaiveDateTime,
+}
+
+pub fn get_role_by_user_id(
+    conn: &DbConnection,
+    x_user_id: i64,
+) -> Result<Option<UserRole>, DbError> {
+    Ok(roles
+        .filter(user_id.eq(x_user_id))
+        .first::<UserRole>(conn)
+        .optional()?)
+}
+
+pub fn get_all_roles(conn: &DbConnection) -> Result<Vec<UserRole>, DbError> {
+    Ok(roles.order(role.desc()).load::<UserRole>(conn)?)
+}
+
+// 先删后插，SQLite 和 PostgreSQL 通用
+pub fn grant_role(conn: &DbConnection, r: UserRole) -> Result<usize, DbError> {
+    Ok(conn.transaction::<usize, diesel::result::Error, _>(|| {
+        diesel::delete(roles.filter(user_id.eq(r.user_id))).execute(conn)?;
+        diesel::insert_into(roles)
+            .values((
+                user_id.eq(r.user_id),
+                role.eq(r.role),
+                created_at.eq(r.created_at),
+            ))
+            .execute(conn)
+    })?)
+}
+
+pub fn revoke_role(conn: &DbConnection, x_user_id: i64) -> Result<usize, DbError> {
+    Ok(diesel::delete(roles.filter(user_id.eq(x_user_id))).execute(conn)?)
+}
@@ -10,6 +10,19 @@ table! {
     }
 }
 
+table! {
+    delivery_logs (id) {
+        id -> Nullable<Integer>,
+        user_id -> BigInt,
+        tweet_id -> BigInt,
+        status_id -> BigInt,
+        screen_name -> Text,
+        decision -> Integer,
+        detail -> Text,
+        created_at -> Timestamp,
+    }
+}
+
 table! {
     follows (id) {
         id -> Nullable<Integer>,
@@ -22,6 +35,65 @@ table! {
     }
 }
 
+table! {
+    forward_history (cache_key) {
+        cache_key -> Text,
+        expires_at -> Timestamp,
+    }
+}
+
+table! {
+    invites (code) {
+        code -> Text,
+        label -> Text,
+        max_uses -> Integer,
+        used_count -> Integer,
+        expires_at -> Timestamp,
+        created_by -> BigInt,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    roles (user_id) {
+        user_id -> BigInt,
+        role -> Integer,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    sent_messages (id) {
+        id -> Nullable<Integer>,
+        user_id -> BigInt,
+        tweet_id -> BigInt,
+        status_id -> BigInt,
+        message_id -> Integer,
+        media_message_ids -> Text,
+        text -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    tweet_recipients (tweet_id, user_id) {
+        tweet_id -> BigInt,
+        user_id -> BigInt,
+    }
+}
+
+table! {
+    tweets (id) {
+        id -> BigInt,
+        twitter_user_id -> BigInt,
+        screen_name -> Text,
+        text -> Text,
+        media_urls -> Text,
+        url -> Text,
+        created_at -> Timestamp,
+    }
+}
+
 table! {
     users (id) {
         id -> BigInt,
@@ -31,11 +103,24 @@ table! {
         created_at -> Timestamp,
         disable_retweet -> Bool,
         disable_text_msg -> Bool,
+        suspended -> Bool,
+        follow_quota -> Integer,
+        dry_run -> Bool,
+        remove_deleted -> Bool,
     }
 }
 
+joinable!(tweet_recipients -> tweets (tweet_id));
+
 allow_tables_to_appear_in_same_query!(
     blacklists,
+    delivery_logs,
     follows,
+    forward_history,
+    invites,
+    roles,
+    sent_messages,
+    tweet_recipients,
+    tweets,
     users,
 );
@@ -0,0 +1,71 @@
+use crate::models::schema::sent_messages::dsl::*;
+use crate::models::{DbConnection, DbError};
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use diesel::{QueryDsl, Queryable, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+
+// 推送给用户的 telegram 消息，推文被删除时据此标记或删除
+#[derive(Clone, Queryable, Serialize, Deserialize)]
+pub struct SentMessage {
+    pub id: Option<i32>,
+    pub user_id: i64,
+    pub tweet_id: i64,
+    pub status_id: i64,
+    pub message_id: i32,
+    // 逗号分隔
+    pub media_message_ids: String,
+    pub text: String,
+    pub created_at: NaiveDateTime,
+}
+
+impl SentMessage {
+    pub fn media_message_ids(&self) -> Vec<i32> {
+        self.media_message_ids
+            .split(',')
+            .filter_map(|m| m.parse().ok())
+            .collect()
+    }
+}
+
+pub fn insert_messages(conn: &DbConnection, messages: Vec<SentMessage>) -> Result<usize, DbError> {
+    conn.transaction::<usize, DbError, _>(|| {
+        let mut count = 0;
+        for m in messages {
+            count += diesel::insert_into(sent_messages)
+                .values((
+                    user_id.eq(m.user_id),
+                    tweet_id.eq(m.tweet_id),
+                    status_id.eq(m.status_id),
+                    message_id.eq(m.message_id),
+                    media_message_ids.eq(m.media_message_ids),
+                    text.eq(m.text),
+                    created_at.eq(m.created_at),
+                ))
+                .execute(conn)?;
+        }
+        Ok(count)
+    })
+}
+
+// 取出并删除被删除的推文对应的消息，同一条消息只处理一次
+// 只按 status_id 匹配，取消转推不影响原推文的消息，反之亦然
+pub fn take_messages_by_status(
+    conn: &DbConnection,
+    x_status_id: i64,
+) -> Result<Vec<SentMessage>, DbError> {
+    conn.transaction::<Vec<SentMessage>, DbError, _>(|| {
+        let filter = status_id.eq(x_status_id);
+        let messages = sent_messages
+            .filter(filter)
+            .order(id.asc())
+            .load::<SentMessage>(conn)?;
+        diesel::delete(sent_messages.filter(filter)).execute(conn)?;
+        Ok(messages)
+    })
+}
+
+// 删除早于 before 的记录，之后删除的推文不再处理
+pub fn prune_messages(conn: &DbConnection, before: NaiveDateTime) -> Result<usize, DbError> {
+    Ok(diesel::delete(sent_messages.filter(created_at.lt(before))).execute(conn)?)
+}
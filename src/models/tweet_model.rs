@@ -0,0 +1,169 @@
+use crate::models::schema::{tweet_recipients, tweets};
+use crate::models::{DbConnection, DbError};
+use chrono::NaiveDateTime;
+use diesel::dsl::sql;
+use diesel::prelude::*;
+use diesel::sql_types::{Bool, Text};
+use diesel::{QueryDsl, Queryable, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+
+type Backend = <DbConnection as Connection>::Backend;
+
+#[derive(Clone, Queryable, Serialize, Deserialize)]
+pub struct Tweet {
+    pub id: i64,
+    pub twitter_user_id: i64,
+    pub screen_name: String,
+    pub text: String,
+    // 换行分隔
+    pub media_urls: String,
+    pub url: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Clone, Default)]
+pub struct SearchFilter {
+    pub query: String,
+    pub author: Option<String>,
+    pub since: Option<NaiveDateTime>,
+    pub until: Option<NaiveDateTime>,
+}
+
+// 保存已推送的推文，并记录收到推送的用户
+pub fn archive_tweet(conn: &DbConnection, t: Tweet, user_ids: Vec<i64>) -> Result<usize, DbError> {
+    conn.transaction::<usize, DbError, _>(|| {
+        let exists = tweets::table
+            .find(t.id)
+            .select(tweets::id)
+            .first::<i64>(conn)
+            .optional()?
+            .is_some();
+        if !exists {
+            diesel::insert_into(tweets::table)
+                .values((
+                    tweets::id.eq(t.id),
+                    tweets::twitter_user_id.eq(t.twitter_user_id),
+                    tweets::screen_name.eq(t.screen_name),
+                    tweets::text.eq(t.text),
+                    tweets::media_urls.eq(t.media_urls),
+                    tweets::url.eq(t.url),
+                    tweets::created_at.eq(t.created_at),
+                ))
+                .execute(conn)?;
+        }
+        let received = tweet_recipients::table
+            .select(tweet_recipients::user_id)
+            .filter(tweet_recipients::tweet_id.eq(t.id))
+            .load::<i64>(conn)?;
+        let mut count = 0;
+        for uid in user_ids.into_iter().filter(|u| !received.contains(u)) {
+            count += diesel::insert_into(tweet_recipients::table)
+                .values((
+                    tweet_recipients::tweet_id.eq(t.id),
+                    tweet_recipients::user_id.eq(uid),
+                ))
+                .execute(conn)?;
+        }
+        Ok(count)
+    })
+}
+
+type TextMatch = Box<dyn BoxableExpression<tweets::table, Backend, SqlType = Bool>>;
+
+// 包含该词的 LIKE 模式，转义其中的通配符
+fn like_pattern(word: &str) -> String {
+    format!(
+        "%{}%",
+        word.replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_")
+    )
+}
+
+// trigram 分词按三个字符建索引，更短的词用 LIKE 匹配
+// 其余的词都作为短语，避免 FTS5 语法错误
+#[cfg(not(feature = "postgres"))]
+fn text_match(query: &str) -> Vec<TextMatch> {
+    let (words, short): (Vec<&str>, Vec<&str>) = query
+        .split_whitespace()
+        .partition(|w| w.chars().count() >= 3);
+    let mut matches = short
+        .into_iter()
+        .map(|w| -> TextMatch {
+            Box::new(
+                sql::<Bool>("tweets.text LIKE ")
+                    .bind::<Text, _>(like_pattern(w))
+                    .sql(" ESCAPE '\\'"),
+            )
+        })
+        .collect::<Vec<_>>();
+    if !words.is_empty() {
+        let phrases = words
+            .iter()
+            .map(|w| format!("\"{}\"", w.replace('"', "\"\"")))
+            .collect::<Vec<_>>()
+            .join(" ");
+        matches.push(Box::new(
+            sql::<Bool>("tweets.id IN (SELECT rowid FROM tweets_fts WHERE tweets_fts MATCH ")
+                .bind::<Text, _>(phrases)
+                .sql(")"),
+        ));
+    }
+    matches
+}
+
+// 每个词都要出现，由 pg_trgm 索引加速
+#[cfg(feature = "postgres")]
+fn text_match(query: &str) -> Vec<TextMatch> {
+    query
+        .split_whitespace()
+        .map(|w| -> TextMatch {
+            Box::new(sql::<Bool>("tweets.text ILIKE ").bind::<Text, _>(like_pattern(w)))
+        })
+        .collect()
+}
+
+// 只在该用户收到过的推文中搜索
+fn search_query(uid: i64, filter: &SearchFilter) -> tweets::BoxedQuery<'static, Backend> {
+    let mut query = tweets::table
+        .filter(
+            tweets::id.eq_any(
+                tweet_recipients::table
+                    .select(tweet_recipients::tweet_id)
+                    .filter(tweet_recipients::user_id.eq(uid)),
+            ),
+        )
+        .into_boxed();
+    for m in text_match(&filter.query) {
+        query = query.filter(m);
+    }
+    if let Some(author) = &filter.author {
+        query = query.filter(
+            sql::<Bool>("lower(tweets.screen_name) = ").bind::<Text, _>(author.to_lowercase()),
+        );
+    }
+    if let Some(since) = filter.since {
+        query = query.filter(tweets::created_at.ge(since));
+    }
+    if let Some(until) = filter.until {
+        query = query.filter(tweets::created_at.lt(until));
+    }
+    query
+}
+
+// 返回匹配总数和当前页的推文，按发推时间倒序
+pub fn search_tweets(
+    conn: &DbConnection,
+    uid: i64,
+    filter: &SearchFilter,
+    offset: i64,
+    limit: i64,
+) -> Result<(i64, Vec<Tweet>), DbError> {
+    let total = search_query(uid, filter).count().get_result::<i64>(conn)?;
+    let list = search_query(uid, filter)
+        .order(tweets::created_at.desc())
+        .offset(offset)
+        .limit(limit)
+        .load::<Tweet>(conn)?;
+    Ok((total, list))
+}
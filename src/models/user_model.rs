@@ -1,10 +1,16 @@
 use crate::models::schema::users::dsl::*;
-use anyhow::anyhow;
+use crate::models::schema::{
+    blacklists, delivery_logs, follows, roles, sent_messages, tweet_recipients,
+};
+use crate::models::{DbConnection, DbError};
+use crate::token_cipher::{self, KeyState};
 use chrono::NaiveDateTime;
 use diesel::prelude::*;
-use diesel::{QueryDsl, Queryable, RunQueryDsl, SqliteConnection};
+use diesel::{QueryDsl, Queryable, RunQueryDsl};
+use log::warn;
+use serde::{Deserialize, Serialize};
 
-#[derive(Queryable, Debug, Clone)]
+#[derive(Queryable, Debug, Clone, Serialize, Deserialize)]
 pub struct User {
     pub id: i64,
     pub label: String,
@@ -13,76 +19,211 @@ pub struct User {
     pub created_at: NaiveDateTime,
     pub disable_retweet: bool,
     pub disable_text_msg: bool,
+    pub suspended: bool,
+    pub follow_quota: i32,
+    // 旧版本导出的文件没有这个字段
+    #[serde(default)]
+    pub dry_run: bool,
+    #[serde(default)]
+    pub remove_deleted: bool,
 }
 
-pub fn get_user_by_id(conn: &SqliteConnection, uid: i64) -> Result<User, anyhow::Error> {
-    let res = users.filter(id.eq(uid)).first::<User>(conn);
-    match res {
-        Ok(u) => Ok(u),
-        Err(e) => Err(anyhow!("{:?}", e)),
+// 数据库里的 token 是加密的，读出时解密
+fn decrypt_token(mut u: User) -> Result<User, DbError> {
+    if let Some(token) = u.twitter_access_token.as_ref().filter(|t| !t.is_empty()) {
+        let (plain, _) =
+            token_cipher::decrypt(u.id, token).map_err(|e| DbError::Token(e.to_string()))?;
+        u.twitter_access_token = Some(plain);
     }
+    Ok(u)
 }
 
-pub fn create_user(conn: &SqliteConnection, u: User) -> Result<usize, anyhow::Error> {
-    let res = diesel::insert_into(users)
+fn encrypt_token(uid: i64, plain: &str) -> Result<String, DbError> {
+    token_cipher::encrypt(uid, plain).map_err(|e| DbError::Token(e.to_string()))
+}
+
+pub fn get_user_by_id(conn: &DbConnection, uid: i64) -> Result<User, DbError> {
+    decrypt_token(users.filter(id.eq(uid)).first::<User>(conn)?)
+}
+
+// 不含 token，需要 token 时用 get_user_by_id 逐个读取
+pub fn get_all_users(conn: &DbConnection) -> Result<Vec<User>, DbError> {
+    Ok(users
+        .order(created_at.asc())
+        .load::<User>(conn)?
+        .into_iter()
+        .map(|mut u| {
+            u.twitter_access_token = None;
+            u
+        })
+        .collect())
+}
+
+// twitter token 有效的用户，token 无法解密的用户记录日志后跳过
+pub fn get_twitter_users(conn: &DbConnection) -> Result<Vec<User>, DbError> {
+    Ok(users
+        .filter(twitter_status.eq(true))
+        .load::<User>(conn)?
+        .into_iter()
+        .filter_map(|u| {
+            let uid = u.id;
+            decrypt_token(u)
+                .map_err(|e| warn!("skip user {}, token cannot be decrypted: {}", uid, e))
+                .ok()
+        })
+        .collect())
+}
+
+pub fn create_user(conn: &DbConnection, u: User) -> Result<usize, DbError> {
+    Ok(diesel::insert_into(users)
         .values((
             id.eq(u.id),
             label.eq(u.label),
             created_at.eq(u.created_at),
             twitter_status.eq(u.twitter_status),
         ))
-        .execute(conn);
-    match res {
-        Ok(cound) => Ok(cound),
-        Err(e) => Err(anyhow!("{:?}", e)),
-    }
+        .execute(conn)?)
 }
 
 pub fn update_twitter_token(
-    conn: &SqliteConnection,
+    conn: &DbConnection,
     uid: i64,
     i_twitter_access_token: String,
     i_twitter_status: bool,
-) -> Result<usize, anyhow::Error> {
-    let res = diesel::update(users)
+) -> Result<usize, DbError> {
+    let i_twitter_access_token = if i_twitter_access_token.is_empty() {
+        i_twitter_access_token
+    } else {
+        encrypt_token(uid, &i_twitter_access_token)?
+    };
+    Ok(diesel::update(users)
         .filter(id.eq(uid))
         .set((
             twitter_access_token.eq(i_twitter_access_token),
             twitter_status.eq(i_twitter_status),
         ))
-        .execute(conn);
-    match res {
-        Ok(cound) => Ok(cound),
-        Err(e) => Err(anyhow!("{:?}", e)),
-    }
+        .execute(conn)?)
 }
 
 pub fn update_disable_retweet(
-    conn: &SqliteConnection,
+    conn: &DbConnection,
     uid: i64,
     disable: bool,
-) -> Result<usize, anyhow::Error> {
-    let res = diesel::update(users)
+) -> Result<usize, DbError> {
+    Ok(diesel::update(users)
         .filter(id.eq(uid))
         .set((disable_retweet.eq(disable),))
-        .execute(conn);
-    match res {
-        Ok(cound) => Ok(cound),
-        Err(e) => Err(anyhow!("{:?}", e)),
-    }
+        .execute(conn)?)
 }
 
 pub fn update_disable_text_msg(
-    conn: &SqliteConnection,
+    conn: &DbConnection,
     uid: i64,
     disable: bool,
-) -> Result<usize, anyhow::Error> {
-    let res = diesel::update(users)
+) -> Result<usize, DbError> {
+    Ok(diesel::update(users)
         .filter(id.eq(uid))
         .set((disable_text_msg.eq(disable),))
-        .execute(conn);
-    match res {
-        Ok(cound) => Ok(cound),
-        Err(e) => Err(anyhow!("{:?}", e)),
+        .execute(conn)?)
+}
+
+pub fn update_label(conn: &DbConnection, uid: i64, i_label: String) -> Result<usize, DbError> {
+    Ok(diesel::update(users)
+        .filter(id.eq(uid))
+        .set((label.eq(i_label),))
+        .execute(conn)?)
+}
+
+pub fn update_remove_deleted(
+    conn: &DbConnection,
+    uid: i64,
+    remove: bool,
+) -> Result<usize, DbError> {
+    Ok(diesel::update(users)
+        .filter(id.eq(uid))
+        .set((remove_deleted.eq(remove),))
+        .execute(conn)?)
+}
+
+pub fn update_dry_run(conn: &DbConnection, uid: i64, i_dry_run: bool) -> Result<usize, DbError> {
+    Ok(diesel::update(users)
+        .filter(id.eq(uid))
+        .set((dry_run.eq(i_dry_run),))
+        .execute(conn)?)
+}
+
+pub fn update_suspended(
+    conn: &DbConnection,
+    uid: i64,
+    i_suspended: bool,
+) -> Result<usize, DbError> {
+    Ok(diesel::update(users)
+        .filter(id.eq(uid))
+        .set((suspended.eq(i_suspended),))
+        .execute(conn)?)
+}
+
+pub fn update_follow_quota(conn: &DbConnection, uid: i64, quota: i32) -> Result<usize, DbError> {
+    Ok(diesel::update(users)
+        .filter(id.eq(uid))
+        .set((follow_quota.eq(quota),))
+        .execute(conn)?)
+}
+
+// 删除用户及其订阅、黑名单、角色
+pub fn remove_user(conn: &DbConnection, uid: i64) -> Result<usize, DbError> {
+    Ok(conn.transaction::<usize, diesel::result::Error, _>(|| {
+        diesel::delete(follows::table.filter(follows::user_id.eq(uid))).execute(conn)?;
+        diesel::delete(blacklists::table.filter(blacklists::user_id.eq(uid))).execute(conn)?;
+        diesel::delete(roles::table.filter(roles::user_id.eq(uid))).execute(conn)?;
+        diesel::delete(tweet_recipients::table.filter(tweet_recipients::user_id.eq(uid)))
+            .execute(conn)?;
+        diesel::delete(delivery_logs::table.filter(delivery_logs::user_id.eq(uid)))
+            .execute(conn)?;
+        diesel::delete(sent_messages::table.filter(sent_messages::user_id.eq(uid)))
+            .execute(conn)?;
+        diesel::delete(users.filter(id.eq(uid))).execute(conn)
+    })?)
+}
+
+// 启动时加密上线前以明文保存的 token
+pub fn encrypt_plaintext_tokens(conn: &DbConnection) -> Result<usize, DbError> {
+    if !token_cipher::enabled() {
+        return Ok(0);
     }
+    reencrypt_tokens(conn, &[KeyState::Plaintext])
+}
+
+// 用当前密钥重新加密所有 token，完成后旧密钥可以从配置中移除
+pub fn rotate_token_key(conn: &DbConnection) -> Result<usize, DbError> {
+    if !token_cipher::enabled() {
+        return Err(DbError::Token(
+            "database.token_key is not configured".to_string(),
+        ));
+    }
+    reencrypt_tokens(conn, &[KeyState::Plaintext, KeyState::Old])
+}
+
+fn reencrypt_tokens(conn: &DbConnection, states: &[KeyState]) -> Result<usize, DbError> {
+    conn.transaction::<usize, DbError, _>(|| {
+        let rows = users
+            .select((id, twitter_access_token))
+            .filter(twitter_access_token.is_not_null())
+            .filter(twitter_access_token.ne(""))
+            .load::<(i64, Option<String>)>(conn)?;
+        let mut count = 0;
+        for (uid, token) in rows {
+            let (plain, state) = token_cipher::decrypt(uid, &token.unwrap_or_default())
+                .map_err(|e| DbError::Token(e.to_string()))?;
+            if !states.contains(&state) {
+                continue;
+            }
+            diesel::update(users)
+                .filter(id.eq(uid))
+                .set(twitter_access_token.eq(encrypt_token(uid, &plain)?))
+                .execute(conn)?;
+            count += 1;
+        }
+        Ok(count)
+    })
 }
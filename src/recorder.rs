@@ -0,0 +1,100 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use lazy_static::lazy_static;
+use log::error;
+
+use crate::config::RecorderConfig;
+
+lazy_static! {
+    static ref RECORDER: Mutex<Option<Recorder>> = Mutex::new(None);
+}
+
+// 追加写入 JSON lines，超过大小后轮转，file.1 是最近轮转的
+pub struct Recorder {
+    path: PathBuf,
+    max_size: u64,
+    keep: usize,
+    file: File,
+    size: u64,
+}
+
+impl Recorder {
+    pub fn open(path: &Path, max_size: u64, keep: usize) -> io::Result<Recorder> {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok(Recorder {
+            path: path.to_path_buf(),
+            max_size,
+            keep,
+            file,
+            size,
+        })
+    }
+
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut name = self.path.as_os_str().to_os_string();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        let _ = fs::remove_file(self.rotated(self.keep));
+        for n in (1..self.keep).rev() {
+            let from = self.rotated(n);
+            if from.exists() {
+                fs::rename(&from, self.rotated(n + 1))?;
+            }
+        }
+        if self.keep > 0 {
+            fs::rename(&self.path, self.rotated(1))?;
+        }
+        self.file = File::create(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+
+    pub fn write(&mut self, line: &str) -> io::Result<()> {
+        let line = format!("{}\n", line.trim_end());
+        if self.size > 0 && self.size + line.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+}
+
+// 按配置打开录制文件，未配置时关闭录制
+pub fn init(config: &RecorderConfig) -> io::Result<()> {
+    let recorder = if config.enabled() {
+        Some(Recorder::open(
+            Path::new(&config.file),
+            config.max_size(),
+            config.keep,
+        )?)
+    } else {
+        None
+    };
+    *RECORDER.lock().unwrap() = recorder;
+    Ok(())
+}
+
+// 记录 stream 收到的一行原始消息，跳过保活的空行
+pub fn record(line: &str) {
+    if line.trim().is_empty() {
+        return;
+    }
+    if let Some(recorder) = RECORDER.lock().unwrap().as_mut() {
+        if let Err(e) = recorder.write(line) {
+            error!("record stream message {:?}", e);
+        }
+    }
+}
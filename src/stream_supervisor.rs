@@ -0,0 +1,403 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use egg_mode::stream::StreamMessage;
+use futures::TryStreamExt;
+use log::{error, info, warn};
+use rand::Rng;
+use tokio::{
+    sync::{mpsc::Sender, watch},
+    task::JoinHandle,
+    time::Instant,
+};
+
+use crate::config::{Config, TwitterConfig};
+use crate::health;
+use crate::metrics;
+use crate::recorder;
+use crate::twitter_api;
+
+// 连接保持这么久之后再断开，视为新的一轮失败
+const STABLE_CONNECTION: Duration = Duration::from_secs(60);
+
+// disconnect 消息的 code，2 和 7 为同一 token 的连接过多，6 为 token 被撤销
+const DISCONNECT_DUPLICATE_STREAM: u64 = 2;
+pub const DISCONNECT_TOKEN_REVOKED: u64 = 6;
+const DISCONNECT_ADMIN_LOGOUT: u64 = 7;
+
+// 交给订阅管理处理的事件
+#[derive(Debug)]
+pub enum StreamEvent {
+    // 确认失效的 token
+    Expired(String),
+    // twitter 因为 token 被撤销或连接过多断开了 stream，需要提醒管理员
+    Disconnected {
+        token: String,
+        code: u64,
+        reason: String,
+    },
+}
+
+struct StreamWorker {
+    follows_tx: watch::Sender<Vec<u64>>,
+    task: JoinHandle<()>,
+}
+
+// 每个 token 只有一个常驻的 stream 任务，follow 列表变化时由任务自己重连
+pub struct StreamSupervisor {
+    config: Arc<Config>,
+    tweet_tx: Sender<StreamMessage>,
+    events_tx: Sender<StreamEvent>,
+    workers: HashMap<String, StreamWorker>,
+}
+
+impl StreamSupervisor {
+    pub fn new(
+        config: Arc<Config>,
+        tweet_tx: Sender<StreamMessage>,
+        events_tx: Sender<StreamEvent>,
+    ) -> Self {
+        StreamSupervisor {
+            config,
+            tweet_tx,
+            events_tx,
+            workers: HashMap::new(),
+        }
+    }
+
+    // 更新 token 的 follow 列表，列表不变时什么都不做
+    pub fn update(&mut self, hash: &str, token: &str, follows: &[u64]) {
+        let mut follows = follows.to_vec();
+        follows.sort_unstable();
+        if let Some(worker) = self.workers.get(hash) {
+            if !worker.task.is_finished() {
+                worker.follows_tx.send_if_modified(|current| {
+                    if *current == follows {
+                        return false;
+                    }
+                    *current = follows;
+                    true
+                });
+                return;
+            }
+        }
+        if follows.is_empty() {
+            health::stream_stopped(hash, 0);
+            return;
+        }
+
+        let t: egg_mode::Token = match serde_json::from_str(token) {
+            Ok(t) => t,
+            Err(e) => {
+                error!("Twitter token {:?} {:?}", hash, e);
+                return;
+            }
+        };
+        let (follows_tx, follows_rx) = watch::channel(follows);
+        let task = tokio::spawn(run_stream(
+            StreamContext {
+                config: self.config.clone(),
+                tweet_tx: self.tweet_tx.clone(),
+                events_tx: self.events_tx.clone(),
+                hash: hash.to_string(),
+                token: token.to_string(),
+            },
+            t,
+            follows_rx,
+        ));
+        self.workers
+            .insert(hash.to_string(), StreamWorker { follows_tx, task });
+    }
+
+    pub fn stop(&mut self, hash: &str) {
+        // 丢弃 sender 后任务会在下一个等待点退出
+        if let Some(worker) = self.workers.remove(hash) {
+            drop(worker.follows_tx);
+        }
+    }
+
+    pub fn stop_all(&mut self) {
+        let hashes: Vec<String> = self.workers.keys().cloned().collect();
+        for hash in hashes {
+            self.stop(&hash);
+            health::stream_stopped(&hash, 0);
+        }
+    }
+}
+
+struct StreamContext {
+    config: Arc<Config>,
+    tweet_tx: Sender<StreamMessage>,
+    events_tx: Sender<StreamEvent>,
+    hash: String,
+    token: String,
+}
+
+enum StreamEnd {
+    FollowsChanged,
+    Disconnect(u64, String),
+    Error(String),
+}
+
+async fn run_stream(
+    ctx: StreamContext,
+    t: egg_mode::Token,
+    mut follows_rx: watch::Receiver<Vec<u64>>,
+) {
+    let hash = ctx.hash.as_str();
+    let config = &ctx.config.twitter;
+    let mut failures: u32 = 0;
+    loop {
+        // 出错重连时已经等待过退避时间，只有 follow 变化才需要等列表稳定
+        if failures == 0 && !debounce(&mut follows_rx, config.stream_restart_delay()).await {
+            break;
+        }
+        let follows = follows_rx.borrow_and_update().clone();
+        if follows.is_empty() {
+            info!("Twitter token {:?} no follows, waiting", hash);
+            health::stream_stopped(hash, 0);
+            if follows_rx.changed().await.is_err() {
+                break;
+            }
+            continue;
+        }
+
+        info!("Twitter {:?} subscribe", &follows);
+        health::stream_connecting(hash, follows.len());
+        let mut stream = twitter_api::filter_stream(config, &follows, &t);
+        let connection = metrics::StreamConnectionGuard::new();
+        let connected_at = Instant::now();
+        let end = loop {
+            tokio::select! {
+                res = stream.try_next() => match res {
+                    Ok(Some(line)) => {
+                        // 先录制再解析，无法解析的消息也能留下原文
+                        recorder::record(&line);
+                        let m = match line.parse::<StreamMessage>() {
+                            Ok(m) => m,
+                            Err(e) => break StreamEnd::Error(e.to_string()),
+                        };
+                        health::stream_message(hash);
+                        if let Some((kind, detail)) = control_message(&m) {
+                            match kind {
+                                "disconnect" | "warning" => {
+                                    warn!("Twitter token {:?} {}: {}", hash, kind, detail)
+                                }
+                                _ => info!("Twitter token {:?} {}: {}", hash, kind, detail),
+                            }
+                            metrics::STREAM_CONTROL_MESSAGES
+                                .with_label_values(&[metrics::token_label(hash), kind])
+                                .inc();
+                            health::stream_control_message(hash, kind, &detail);
+                            if let StreamMessage::Disconnect(code, reason) = m {
+                                break StreamEnd::Disconnect(code, reason);
+                            }
+                            continue;
+                        }
+                        if let StreamMessage::Tweet(_) = m {
+                            metrics::TWEETS_RECEIVED
+                                .with_label_values(&[metrics::token_label(hash)])
+                                .inc();
+                        }
+                        if ctx.tweet_tx.send(m).await.is_err() {
+                            // 转发队列已关闭，正在退出
+                            return;
+                        }
+                    }
+                    Ok(None) => break StreamEnd::Error("stream closed".to_string()),
+                    Err(e) => break StreamEnd::Error(e.to_string()),
+                },
+                res = follows_rx.changed() => match res {
+                    Ok(_) => break StreamEnd::FollowsChanged,
+                    Err(_) => {
+                        info!("Twitter token {:?} stream stopped", hash);
+                        return;
+                    }
+                },
+            }
+        };
+        drop(stream);
+        drop(connection);
+
+        let e = match end {
+            StreamEnd::FollowsChanged => {
+                info!("Twitter token {:?} follows changed, restarting", hash);
+                failures = 0;
+                continue;
+            }
+            StreamEnd::Error(e) => e,
+            StreamEnd::Disconnect(code, reason) => {
+                let e = format!("disconnect {}: {}", code, reason);
+                // 连续失败时只提醒一次
+                let first_failure = failures == 0 || connected_at.elapsed() >= STABLE_CONNECTION;
+                let notify = match code {
+                    DISCONNECT_TOKEN_REVOKED => true,
+                    DISCONNECT_DUPLICATE_STREAM | DISCONNECT_ADMIN_LOGOUT => first_failure,
+                    _ => false,
+                };
+                if notify {
+                    let _ = ctx
+                        .events_tx
+                        .send(StreamEvent::Disconnected {
+                            token: ctx.token.clone(),
+                            code,
+                            reason,
+                        })
+                        .await;
+                }
+                // twitter 明确告知 token 已被撤销，不需要再检查
+                if code == DISCONNECT_TOKEN_REVOKED {
+                    warn!("Twitter token {:?} has been revoked", hash);
+                    health::remove_stream(hash);
+                    let _ = ctx
+                        .events_tx
+                        .send(StreamEvent::Expired(ctx.token.clone()))
+                        .await;
+                    return;
+                }
+                e
+            }
+        };
+        // twitter 的 stream 出错退出，先打印错误信息
+        warn!("Twitter {:?} subscribe error {:?}", &follows, e);
+        // 再检查一下 token 有效性，如果确认无效，走删除 token 流程
+        if token_expired(config, &ctx.token).await {
+            warn!("Twitter token {:?} has expired", hash);
+            health::remove_stream(hash);
+            let _ = ctx
+                .events_tx
+                .send(StreamEvent::Expired(ctx.token.clone()))
+                .await;
+            return;
+        }
+
+        if connected_at.elapsed() >= STABLE_CONNECTION {
+            failures = 0;
+        }
+        failures = failures.saturating_add(1);
+        let delay = backoff(config, failures);
+        health::stream_error(
+            hash,
+            e,
+            failures,
+            chrono::Utc::now() + chrono::Duration::from_std(delay).unwrap(),
+        );
+        metrics::STREAM_RECONNECTS
+            .with_label_values(&[metrics::token_label(hash)])
+            .inc();
+        info!(
+            "Twitter token {:?} reconnect in {:?} (failure {})",
+            hash, delay, failures
+        );
+        if !wait_until(&mut follows_rx, Instant::now() + delay).await {
+            break;
+        }
+    }
+    info!("Twitter token {:?} stream stopped", hash);
+}
+
+// stream 的控制消息，返回类型和说明，推文、删除通知和保活消息返回 None
+fn control_message(m: &StreamMessage) -> Option<(&'static str, String)> {
+    match m {
+        StreamMessage::Disconnect(code, reason) => {
+            Some(("disconnect", format!("{} {}", code, reason)))
+        }
+        StreamMessage::ScrubGeo {
+            user_id,
+            up_to_status_id,
+        } => Some((
+            "scrub_geo",
+            format!("user {} up to status {}", user_id, up_to_status_id),
+        )),
+        StreamMessage::StatusWithheld {
+            status_id,
+            withheld_in_countries,
+            ..
+        } => Some((
+            "status_withheld",
+            format!(
+                "status {} in {}",
+                status_id,
+                withheld_in_countries.join(",")
+            ),
+        )),
+        StreamMessage::UserWithheld {
+            user_id,
+            withheld_in_countries,
+        } => Some((
+            "user_withheld",
+            format!("user {} in {}", user_id, withheld_in_countries.join(",")),
+        )),
+        // egg-mode 不解析 limit 和 warning
+        StreamMessage::Unknown(v) => Some(if let Some(limit) = v.get("limit") {
+            ("limit", format!("{} tweets undelivered", limit["track"]))
+        } else if let Some(warning) = v.get("warning") {
+            (
+                "warning",
+                format!(
+                    "{} {}",
+                    warning["code"].as_str().unwrap_or_default(),
+                    warning["message"].as_str().unwrap_or_default()
+                ),
+            )
+        } else {
+            ("unknown", v.to_string())
+        }),
+        _ => None,
+    }
+}
+
+// 等待列表稳定，期间有变化则重新计时，返回 false 表示已被停止
+async fn debounce(follows_rx: &mut watch::Receiver<Vec<u64>>, delay: Duration) -> bool {
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => return true,
+            res = follows_rx.changed() => {
+                if res.is_err() {
+                    return false;
+                }
+            }
+        }
+    }
+}
+
+// 等到 deadline，返回 false 表示已被停止
+async fn wait_until(follows_rx: &mut watch::Receiver<Vec<u64>>, deadline: Instant) -> bool {
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep_until(deadline) => return true,
+            res = follows_rx.changed() => {
+                if res.is_err() {
+                    return false;
+                }
+            }
+        }
+    }
+}
+
+// 指数退避，实际等待时间在 [delay/2, delay] 之间随机，避免多个 token 同时重连
+fn backoff(config: &TwitterConfig, failures: u32) -> Duration {
+    let base = config.stream_retry();
+    let max = config.stream_retry_max().max(base);
+    let delay = base
+        .checked_mul(2u32.saturating_pow(failures.saturating_sub(1)))
+        .unwrap_or(max)
+        .min(max);
+    let half = delay / 2;
+    half + rand::thread_rng().gen_range(Duration::ZERO..=half)
+}
+
+// 只有 twitter 明确拒绝时才认为 token 失效，网络错误继续重试
+async fn token_expired(config: &TwitterConfig, token: &str) -> bool {
+    let t: egg_mode::Token = match serde_json::from_str(token) {
+        Ok(t) => t,
+        Err(_) => return true,
+    };
+    match twitter_api::show_user(config, 783214, &t).await {
+        Ok(user) => !user.screen_name.eq("Twitter"),
+        Err(egg_mode::error::Error::BadStatus(status)) => status.as_u16() == 401,
+        Err(egg_mode::error::Error::TwitterError(_, errors)) => {
+            errors.errors.iter().any(|e| matches!(e.code, 32 | 89))
+        }
+        Err(_) => false,
+    }
+}
@@ -1,26 +1,41 @@
 use std::{
+    collections::HashMap,
     fmt::Debug,
+    path::Path,
     sync::Arc,
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use chrono::NaiveDateTime;
-use egg_mode::KeyPair;
 use r_cache::cache::Cache;
+use rand::{distributions::Alphanumeric, Rng};
 use teloxide::{
     adaptors::DefaultParseMode,
     prelude::*,
-    types::{MessageKind, Update},
-    utils::{command::BotCommands, markdown::escape},
+    types::{InlineKeyboardButton, InlineKeyboardMarkup, InputFile, MessageKind, Update},
+    utils::{
+        command::BotCommands,
+        markdown::{bold, escape, link},
+    },
+    ApiError, RequestError,
 };
-use tokio::sync::RwLock;
+use tokio::sync::watch;
 
+use crate::backup;
+use crate::config::Config;
+use crate::health;
 use crate::models::{
-    blacklist_model, follow_model,
+    blacklist_model,
+    delivery_log_model::{self, Decision},
+    follow_model, invite_model,
+    role_model::{self, Role, UserRole},
+    tweet_model::{self, SearchFilter},
     user_model::{self, User},
-    DbPool,
+    DbError, Repository,
 };
-use crate::twitter_subscriber::TwitterSubscriber;
+use crate::twitter_api;
+use crate::twitter_subscriber::SubscriberHandle;
+use crate::webhook;
 use crate::GIT_HASH;
 
 #[derive(BotCommands, Clone, Debug)]
@@ -29,7 +44,7 @@ use crate::GIT_HASH;
 usage: */command* _param1_ _param2_")]
 enum Command {
     #[command(rename = "lowercase", description = "Menu")]
-    Start,
+    Start(String),
     #[command(description = "Get the authorization URL for twitter")]
     GetTwitterAuthURL,
     #[command(description = "_twitterAuthCode_")]
@@ -59,45 +74,217 @@ enum Command {
     ListBlockedTwitterID(i32),
     #[command(description = "List subscribed Twitter users")]
     ListFollowedTwitterID,
+    #[command(
+        description = "_keywords from:author since:YYYY\\-MM\\-DD until:YYYY\\-MM\\-DD_ Search forwarded tweets"
+    )]
+    Search(String),
+    #[command(description = "_tweetURL_ Explain why a tweet was or was not forwarded to you")]
+    WhyNot(String),
     #[command(description = "Disable retweet forwards")]
     SetDisableRetweet(bool),
     #[command(description = "Disable text\\-only msg forwards")]
     SetDisableTextMsg(bool),
-    #[command(description = "*OnlyOwner* Add a user", parse_with = "split")]
+    #[command(description = "Delete forwards of deleted tweets instead of marking them")]
+    SetRemoveDeleted(bool),
+    #[command(description = "*Admin* Add a user", parse_with = "split")]
     AddUser {
         telegram_id: i64,
         custom_label: String,
     },
+    #[command(description = "*Admin* _telegramID_ Remove a user and all subscriptions")]
+    RemoveUser(i64),
+    #[command(description = "*Admin* List users")]
+    ListUsers,
+    #[command(
+        description = "*Admin* _telegramID label_ Set user label",
+        parse_with = "split"
+    )]
+    SetUserLabel {
+        telegram_id: i64,
+        custom_label: String,
+    },
+    #[command(description = "*Admin* _telegramID_ Suspend a user")]
+    SuspendUser(i64),
+    #[command(description = "*Admin* _telegramID_ Resume a suspended user")]
+    ResumeUser(i64),
+    #[command(
+        description = "*Admin* _telegramID bool_ Send tweets of a user to the log or admin instead",
+        parse_with = "split"
+    )]
+    SetDryRun { telegram_id: i64, dry_run: bool },
+    #[command(
+        description = "*Admin* _telegramID quota_ Limit follows of a user, 0 for unlimited",
+        parse_with = "split"
+    )]
+    SetUserQuota { telegram_id: i64, quota: i32 },
+    #[command(
+        description = "*Owner* _telegramID role_ Grant a role to user",
+        parse_with = "split"
+    )]
+    GrantRole { telegram_id: i64, x_role: i32 },
+    #[command(description = "*Owner* _telegramID_ Revoke the granted role of user")]
+    RevokeRole(i64),
+    #[command(description = "*Owner* List granted roles")]
+    ListRoles,
+    #[command(description = "*Owner* Re\\-encrypt all Twitter tokens with the current key")]
+    RotateTokenKey,
+    #[command(description = "*Owner* Back up the database and send it to you")]
+    Backup,
+    #[command(
+        description = "*Admin* _maxUses expireHours label_ Create an invite link",
+        parse_with = "split"
+    )]
+    CreateInvite {
+        max_uses: i32,
+        expire_hours: i64,
+        custom_label: String,
+    },
+    #[command(description = "*Admin* List invite codes")]
+    ListInvites,
+    #[command(description = "*Admin* _code_ Revoke an invite code")]
+    RevokeInvite(String),
+    #[command(
+        description = "*Admin* _text_ Broadcast a message to all users, or reply to a message to forward it"
+    )]
+    Broadcast(String),
+    #[command(description = "off")]
+    ConfirmBroadcast(String),
+    #[command(description = "off")]
+    CancelBroadcast(String),
+}
+
+const RETRY_LATER: &str = "The database is busy, please try again later";
+const SEARCH_PAGE_SIZE: i64 = 10;
+// Telegram 限制 callback data 最长 64 字节
+const CALLBACK_DATA_LIMIT: usize = 64;
+
+#[derive(Clone)]
+pub enum Broadcast {
+    Text(String),
+    Forward {
+        from_chat_id: ChatId,
+        message_id: i32,
+    },
+}
+
+fn menu() -> String {
+    Command::descriptions()
+        .to_string()
+        .replace(" - ", " \\- ")
+        .replace("HASH", &GIT_HASH[..8])
+}
+
+// 数据库繁忙等可重试的错误提示用户稍后再试，其他错误原样返回
+fn failure(err: &DbError) -> String {
+    if err.is_retryable() {
+        RETRY_LATER.to_string()
+    } else {
+        format!("Failure, error {:?}", err)
+    }
+}
+
+fn now_naive() -> NaiveDateTime {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    NaiveDateTime::from_timestamp_opt(now.as_secs() as i64, now.subsec_nanos()).unwrap()
+}
+
+fn parse_date(v: &str) -> Result<NaiveDateTime, String> {
+    chrono::NaiveDate::parse_from_str(v, "%Y-%m-%d")
+        .map(|d| d.and_hms_opt(0, 0, 0).unwrap())
+        .map_err(|_| format!("Invalid date {}, expected YYYY-MM-DD", v))
+}
+
+// 解析 /Search 参数：关键词 from:作者 since:开始日期 until:结束日期(含) page:页码
+fn parse_search(args: &str) -> Result<(SearchFilter, i64), String> {
+    let mut filter = SearchFilter::default();
+    let mut page = 1;
+    let mut words = Vec::new();
+    for w in args.split_whitespace() {
+        match w.split_once(':') {
+            Some(("from", v)) => filter.author = Some(v.trim_start_matches('@').to_string()),
+            Some(("since", v)) => filter.since = Some(parse_date(v)?),
+            Some(("until", v)) => filter.until = Some(parse_date(v)? + chrono::Duration::days(1)),
+            Some(("page", v)) => {
+                page = v
+                    .parse::<i64>()
+                    .ok()
+                    .filter(|p| *p > 0)
+                    .ok_or(format!("Invalid page {}", v))?
+            }
+            _ => words.push(w),
+        }
+    }
+    filter.query = words.join(" ");
+    if filter.query.is_empty() && filter.author.is_none() {
+        return Err(
+            "Usage: /Search keywords from:author since:YYYY-MM-DD until:YYYY-MM-DD".to_string(),
+        );
+    }
+    Ok((filter, page))
+}
+
+// 支持推文链接或直接输入推文 ID
+fn parse_tweet_id(s: &str) -> Option<i64> {
+    let s = s.trim();
+    if let Ok(id) = s.parse::<i64>() {
+        return Some(id);
+    }
+    let url = url::Url::parse(s).ok()?;
+    let mut segments = url.path_segments()?;
+    segments.find(|p| *p == "status")?;
+    segments.next()?.parse::<i64>().ok()
+}
+
+fn explain_decision(decision: Option<Decision>, detail: &str) -> String {
+    match decision {
+        Some(Decision::Delivered) => "delivered".to_string(),
+        Some(Decision::Suspended) => "skipped, your account is suspended".to_string(),
+        Some(Decision::DisableRetweet) => {
+            "skipped, retweets are disabled by /SetDisableRetweet".to_string()
+        }
+        Some(Decision::DisableTextMsg) => {
+            "skipped, text-only tweets are disabled by /SetDisableTextMsg".to_string()
+        }
+        Some(Decision::Duplicate) => "skipped, it was already forwarded to you".to_string(),
+        Some(Decision::BlockTwitter) => {
+            format!("skipped, you blocked all tweets from Twitter ID {}", detail)
+        }
+        Some(Decision::BlockRT) => {
+            format!("skipped, you blocked retweets from Twitter ID {}", detail)
+        }
+        Some(Decision::SendError) => format!("sending failed: {}", detail),
+        Some(Decision::DryRun) => format!("not sent, dry-run mode, {}", detail),
+        None => "unknown".to_string(),
+    }
 }
 
 pub struct TelegramContext {
     pub name: String,
-    pub db_pool: DbPool,
+    pub db: Repository,
     pub cache: Arc<Cache<i64, egg_mode::KeyPair>>,
-    pub telegram_admin_id: i64,
-    pub twitter_token: KeyPair,
-    pub twitter_subscriber: Option<Arc<RwLock<TwitterSubscriber>>>,
+    pub config: Arc<Config>,
+    pub twitter_subscriber: Option<SubscriberHandle>,
+    pub broadcasts: Cache<String, Broadcast>,
 }
 
 impl TelegramContext {
     pub fn new(
         name: String,
         cache: Arc<Cache<i64, egg_mode::KeyPair>>,
-        db_pool: DbPool,
-        telegram_admin_id: i64,
-        twitter_token: KeyPair,
+        db: Repository,
+        config: Arc<Config>,
     ) -> Self {
         TelegramContext {
             name: name,
             cache: cache,
-            db_pool: db_pool,
-            telegram_admin_id,
-            twitter_token: twitter_token,
+            db,
+            broadcasts: Cache::new(Some(config.telegram.broadcast_ttl())),
+            config,
             twitter_subscriber: None,
         }
     }
 
-    pub fn set_twitter_subscriber(&mut self, subscriber: Option<Arc<RwLock<TwitterSubscriber>>>) {
+    pub fn set_twitter_subscriber(&mut self, subscriber: Option<SubscriberHandle>) {
         self.twitter_subscriber = subscriber;
     }
 }
@@ -107,79 +294,216 @@ async fn command_handler(
     bot: AutoSend<DefaultParseMode<Bot>>,
     message: Message,
     command: Command,
+) -> Result<(), anyhow::Error> {
+    let chat_id = message.chat.id;
+    let res = handle_command(ctx, bot.clone(), message, command).await;
+    // 可重试的数据库错误只提示用户稍后再试
+    if let Some(e) = res.as_ref().err().and_then(|e| e.downcast_ref::<DbError>()) {
+        if e.is_retryable() {
+            log::warn!("telegram@{} {}", chat_id, e);
+            bot.send_message(chat_id, RETRY_LATER).await?;
+            return Ok(());
+        }
+    }
+    res
+}
+
+async fn handle_command(
+    ctx: Arc<TelegramContext>,
+    bot: AutoSend<DefaultParseMode<Bot>>,
+    message: Message,
+    command: Command,
 ) -> Result<(), anyhow::Error> {
     let sender = message.from().unwrap();
 
     let teloxide::types::UserId(user_id) = sender.id;
-    let user = match user_model::get_user_by_id(
-        &ctx.db_pool.get().unwrap(),
-        user_id.try_into().unwrap(),
-    ) {
+    let uid: i64 = user_id.try_into().unwrap();
+    let user = match ctx
+        .db
+        .run(move |conn| user_model::get_user_by_id(conn, uid))
+        .await
+    {
         Ok(u) => Some(u),
-        Err(_) => None,
+        Err(DbError::NotFound) => None,
+        Err(e) => return Err(e.into()),
     };
 
-    let user_pre_check = || async {
-        if user.is_none() {
-            bot.send_message(
-                message.chat.id,
-                format!(
-                    "User {:?} not authorized, please contact administrator to add permissions",
-                    sender.id.0
-                ),
-            )
-            .await
-            .unwrap();
-            return false;
-        };
-        true
+    let teloxide::types::UserId(sender_id) = sender.id;
+
+    // 配置的管理员永远是所有者，已添加但未分配角色的用户默认为普通用户
+    let role = if sender_id.eq(&(ctx.config.telegram.admin_id as u64)) {
+        Some(Role::Owner)
+    } else {
+        match ctx
+            .db
+            .run(move |conn| role_model::get_role_by_user_id(conn, sender_id as i64))
+            .await?
+        {
+            Some(r) => Role::from_i32(r.role),
+            None => user.as_ref().map(|_| Role::User),
+        }
     };
 
-    let teloxide::types::UserId(sender_id) = sender.id;
+    let user_pre_check = |required: Role| {
+        let (bot, message, user) = (&bot, &message, &user);
+        async move {
+            if user.is_none() {
+                bot.send_message(
+                    message.chat.id,
+                    format!(
+                        "User {:?} not authorized, please contact administrator to add permissions",
+                        sender_id
+                    ),
+                )
+                .await
+                .unwrap();
+                return false;
+            };
+            if user.as_ref().unwrap().suspended {
+                bot.send_message(
+                    message.chat.id,
+                    "Your account is suspended, please contact administrator",
+                )
+                .await
+                .unwrap();
+                return false;
+            };
+            if role.unwrap_or(Role::ReadOnly) < required {
+                bot.send_message(message.chat.id, "Your account is read\\-only")
+                    .await
+                    .unwrap();
+                return false;
+            };
+            true
+        }
+    };
 
-    let admin_pre_check = || async {
-        if !sender_id.eq(&(ctx.telegram_admin_id as u64)) {
-            bot.send_message(message.chat.id, "You are not an admin")
+    let role_pre_check = |required: Role| {
+        let (bot, message, user) = (&bot, &message, &user);
+        async move {
+            if user.as_ref().is_some_and(|u| u.suspended) {
+                bot.send_message(
+                    message.chat.id,
+                    "Your account is suspended, please contact administrator",
+                )
+                .await
+                .unwrap();
+                return false;
+            };
+            if role.is_none_or(|r| r < required) {
+                bot.send_message(
+                    message.chat.id,
+                    format!("Permission denied, *{:?}* role required", required),
+                )
                 .await
                 .unwrap();
-            return false;
-        };
-        true
+                return false;
+            };
+            true
+        }
+    };
+
+    // 只能管理角色低于自己的用户，所有者不受限制，配置的管理员视为所有者
+    let target_pre_check = |target_id: i64| {
+        let (ctx, bot, message) = (&ctx, &bot, &message);
+        async move {
+            if role == Some(Role::Owner) {
+                return Ok::<_, anyhow::Error>(true);
+            }
+            let target = if target_id == ctx.config.telegram.admin_id {
+                Some(Role::Owner)
+            } else {
+                ctx.db
+                    .run(move |conn| role_model::get_role_by_user_id(conn, target_id))
+                    .await?
+                    .and_then(|r| Role::from_i32(r.role))
+            };
+            match target {
+                Some(target) if role.is_none_or(|r| target >= r) => {
+                    bot.send_message(
+                        message.chat.id,
+                        format!(
+                            "Permission denied, _{:?}_ has *{:?}* role",
+                            target_id, target
+                        ),
+                    )
+                    .await?;
+                    Ok(false)
+                }
+                _ => Ok(true),
+            }
+        }
     };
 
     match command {
-        Command::Start => {
-            if !user_pre_check().await {
+        Command::Start(invite_code) => {
+            // 通过邀请链接 /start <code> 自助注册
+            if user.is_none() && !invite_code.trim().is_empty() {
+                let invite_code = invite_code.trim().to_string();
+                let new_user = User {
+                    id: sender_id as i64,
+                    label: "".to_string(),
+                    twitter_access_token: None,
+                    twitter_status: false,
+                    created_at: now_naive(),
+                    disable_retweet: false,
+                    disable_text_msg: false,
+                    suspended: false,
+                    follow_quota: 0,
+                    dry_run: false,
+                    remove_deleted: false,
+                };
+                let res = ctx
+                    .db
+                    .run(move |conn| {
+                        invite_model::redeem_invite(conn, &invite_code, new_user, now_naive())
+                    })
+                    .await?;
+                match res {
+                    Some(user) => {
+                        ctx.twitter_subscriber
+                            .as_ref()
+                            .unwrap()
+                            .set_user(user.clone())
+                            .await?;
+                        bot.send_message(
+                            message.chat.id,
+                            format!("Welcome *{}*\n\n{}", escape(&user.label), menu()),
+                        )
+                        .await?;
+                    }
+                    None => {
+                        bot.send_message(message.chat.id, "Invite code is invalid or expired")
+                            .await?;
+                    }
+                }
+                return Ok(());
+            }
+            if !user_pre_check(Role::ReadOnly).await {
                 return Ok(());
             };
-            bot.send_message(
-                message.chat.id,
-                Command::descriptions()
-                    .to_string()
-                    .replace(" - ", " \\- ")
-                    .replace("HASH", &GIT_HASH[..8]),
-            )
-            .await?
+            bot.send_message(message.chat.id, menu()).await?
         }
         Command::GetTwitterAuthURL => {
-            if !user_pre_check().await {
+            if !user_pre_check(Role::User).await {
                 return Ok(());
             };
-            let request_token = egg_mode::auth::request_token(&ctx.twitter_token, "oob")
-                .await
-                .unwrap();
+            let request_token =
+                egg_mode::auth::request_token(&ctx.config.twitter.consumer_token(), "oob")
+                    .await
+                    .unwrap();
             let auth_url = egg_mode::auth::authorize_url(&request_token);
             ctx.cache
                 .set(
                     user.unwrap().id,
                     request_token,
-                    Some(Duration::from_secs(600)),
+                    Some(ctx.config.twitter.request_token_ttl()),
                 )
                 .await;
             bot.send_message(message.chat.id, escape(&auth_url)).await?
         }
         Command::SetTwitterVerifyCode(code) => {
-            if !user_pre_check().await {
+            if !user_pre_check(Role::User).await {
                 return Ok(());
             };
             if !code.trim().len().eq(&7) {
@@ -200,22 +524,23 @@ async fn command_handler(
                 return Ok(());
             }
             let (token, _, _) = egg_mode::auth::access_token(
-                ctx.twitter_token.clone(),
+                ctx.config.twitter.consumer_token(),
                 &request_token.unwrap(),
                 code,
             )
             .await?;
             let user = user.unwrap();
             let token_str = serde_json::to_string(&token).unwrap();
-            let res = user_model::update_twitter_token(
-                &ctx.db_pool.get().unwrap(),
-                user.id,
-                token_str.clone(),
-                true,
-            );
-            let mut ts_write = ctx.twitter_subscriber.as_ref().unwrap().write().await;
-            ts_write.add_token(user.id, &token_str).await?;
-            drop(ts_write);
+            let (uid, token_json) = (user.id, token_str.clone());
+            let res = ctx
+                .db
+                .run(move |conn| user_model::update_twitter_token(conn, uid, token_json, true))
+                .await;
+            ctx.twitter_subscriber
+                .as_ref()
+                .unwrap()
+                .add_token(user.id, &token_str)
+                .await?;
             bot.send_message(
                 message.chat.id,
                 match res {
@@ -225,15 +550,13 @@ async fn command_handler(
                             count
                         )
                     }
-                    Err(err) => {
-                        format!("Failure, error {:?}", err)
-                    }
+                    Err(err) => failure(&err),
                 },
             )
             .await?
         }
         Command::FollowTwitterID(x_twitter_user_id, x_from_twitter_user_id) => {
-            if !user_pre_check().await {
+            if !user_pre_check(Role::User).await {
                 return Ok(());
             };
             let user = user.unwrap();
@@ -249,9 +572,30 @@ async fn command_handler(
                 bot.send_message(message.chat.id, "Incorrect ID").await?;
                 return Ok(());
             }
+            // 检查订阅配额
+            if user.follow_quota > 0 {
+                let uid = user.id;
+                let count = ctx
+                    .db
+                    .run(move |conn| follow_model::count_follows_by_user_id(conn, uid))
+                    .await?;
+                if count >= user.follow_quota as i64 {
+                    bot.send_message(
+                        message.chat.id,
+                        escape(&format!(
+                            "Follow quota reached ({}/{}), please contact administrator",
+                            count, user.follow_quota
+                        )),
+                    )
+                    .await?;
+                    return Ok(());
+                }
+            }
             let token: egg_mode::Token =
                 serde_json::from_str(&user.twitter_access_token.unwrap()).unwrap();
-            let twitter_user = egg_mode::user::show(x_twitter_user_id as u64, &token).await?;
+            let twitter_user =
+                twitter_api::show_user(&ctx.config.twitter, x_twitter_user_id as u64, &token)
+                    .await?;
 
             let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
             let follow = follow_model::Follow {
@@ -267,38 +611,36 @@ async fn command_handler(
                 )
                 .unwrap(),
             };
-            let res = follow_model::create_follow(&ctx.db_pool.get().unwrap(), follow.clone());
+            let new_follow = follow.clone();
+            let res = ctx
+                .db
+                .run(move |conn| follow_model::create_follow(conn, new_follow))
+                .await;
             bot.send_message(
                 message.chat.id,
                 match res {
                     Ok(count) => {
                         // 优质内容追踪计数
-                        follow_model::increase_follow_rt_count(
-                            &ctx.db_pool.get().unwrap(),
-                            user.id,
-                            x_from_twitter_user_id,
-                        )?;
-                        let token = ctx
-                            .twitter_subscriber
-                            .as_ref()
-                            .unwrap()
-                            .write()
-                            .await
-                            .add_follow(follow, x_twitter_user_id)
+                        let uid = user.id;
+                        ctx.db
+                            .run(move |conn| {
+                                follow_model::increase_follow_rt_count(
+                                    conn,
+                                    uid,
+                                    x_from_twitter_user_id,
+                                )
+                            })
                             .await?;
-                        if token.ne("") {
-                            TwitterSubscriber::subscribe(
-                                ctx.twitter_subscriber.as_ref().unwrap().clone(),
-                                token,
-                            )
-                            .await
-                            .unwrap();
-                        };
+                        let ts = ctx.twitter_subscriber.as_ref().unwrap();
+                        ts.add_follow(follow, x_twitter_user_id).await?;
+                        tokio::spawn(ts.clone().refresh_inline_buttons(
+                            bot.clone(),
+                            user.id,
+                            vec![x_twitter_user_id, x_from_twitter_user_id],
+                        ));
                         format!("Added successfully, affecting {:?} records", count)
                     }
-                    Err(err) => {
-                        format!("Failure, error {:?}", err)
-                    }
+                    Err(err) => failure(&err),
                 },
             )
             .await?
@@ -308,7 +650,7 @@ async fn command_handler(
             x_twitter_user_id,
             x_from_twitter_id,
         } => {
-            if !user_pre_check().await {
+            if !user_pre_check(Role::User).await {
                 return Ok(());
             };
             let user = user.unwrap();
@@ -326,7 +668,9 @@ async fn command_handler(
             }
             let token: egg_mode::Token =
                 serde_json::from_str(&user.twitter_access_token.unwrap()).unwrap();
-            let twitter_user = egg_mode::user::show(x_twitter_user_id as u64, &token).await?;
+            let twitter_user =
+                twitter_api::show_user(&ctx.config.twitter, x_twitter_user_id as u64, &token)
+                    .await?;
 
             let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
             let block = blacklist_model::Blacklist {
@@ -341,7 +685,11 @@ async fn command_handler(
                 )
                 .unwrap(),
             };
-            let res = blacklist_model::block_user(&ctx.db_pool.get().unwrap(), block.clone());
+            let new_block = block.clone();
+            let res = ctx
+                .db
+                .run(move |conn| blacklist_model::block_user(conn, new_block))
+                .await;
 
             bot.send_message(
                 message.chat.id,
@@ -349,24 +697,27 @@ async fn command_handler(
                     Ok(count) => {
                         // 劣质内容屏蔽计数
                         if x_type.eq(&2) {
-                            follow_model::increase_block_rt_count(
-                                &ctx.db_pool.get().unwrap(),
-                                user.id,
-                                x_from_twitter_id,
-                            )?;
+                            let uid = user.id;
+                            ctx.db
+                                .run(move |conn| {
+                                    follow_model::increase_block_rt_count(
+                                        conn,
+                                        uid,
+                                        x_from_twitter_id,
+                                    )
+                                })
+                                .await?;
                         }
-                        ctx.twitter_subscriber
-                            .as_ref()
-                            .unwrap()
-                            .write()
-                            .await
-                            .block(block, x_from_twitter_id)
-                            .await?;
+                        let ts = ctx.twitter_subscriber.as_ref().unwrap();
+                        ts.block(block, x_from_twitter_id).await?;
+                        tokio::spawn(ts.clone().refresh_inline_buttons(
+                            bot.clone(),
+                            user.id,
+                            vec![x_twitter_user_id, x_from_twitter_id],
+                        ));
                         format!("Added successfully, affecting {:?} records", count)
                     }
-                    Err(err) => {
-                        format!("Failure, error {:?}", err)
-                    }
+                    Err(err) => failure(&err),
                 },
             )
             .await?
@@ -375,7 +726,7 @@ async fn command_handler(
             x_type,
             x_twitter_user_id,
         } => {
-            if !user_pre_check().await {
+            if !user_pre_check(Role::User).await {
                 return Ok(());
             };
             let user = user.unwrap();
@@ -391,12 +742,11 @@ async fn command_handler(
                 bot.send_message(message.chat.id, "Incorrect ID").await?;
                 return Ok(());
             }
-            let res = blacklist_model::unblock(
-                &ctx.db_pool.get().unwrap(),
-                user.id,
-                x_twitter_user_id,
-                x_type,
-            );
+            let uid = user.id;
+            let res = ctx
+                .db
+                .run(move |conn| blacklist_model::unblock(conn, uid, x_twitter_user_id, x_type))
+                .await;
             bot.send_message(
                 message.chat.id,
                 match res {
@@ -404,21 +754,17 @@ async fn command_handler(
                         ctx.twitter_subscriber
                             .as_ref()
                             .unwrap()
-                            .write()
-                            .await
                             .unblock(user.id, x_twitter_user_id, x_type)
-                            .await;
+                            .await?;
                         format!("Unblock successfully, affecting {:?} records", count)
                     }
-                    Err(err) => {
-                        format!("Failure, error {:?}", err)
-                    }
+                    Err(err) => failure(&err),
                 },
             )
             .await?
         }
         Command::UnfollowTwitterID(x_twitter_user_id) => {
-            if !user_pre_check().await {
+            if !user_pre_check(Role::User).await {
                 return Ok(());
             };
             let user = user.unwrap();
@@ -434,32 +780,31 @@ async fn command_handler(
                 bot.send_message(message.chat.id, "Incorrect ID").await?;
                 return Ok(());
             }
-            let res =
-                follow_model::unfollow(&ctx.db_pool.get().unwrap(), user.id, x_twitter_user_id);
+            let uid = user.id;
+            let res = ctx
+                .db
+                .run(move |conn| follow_model::unfollow(conn, uid, x_twitter_user_id))
+                .await;
             let ts = ctx.twitter_subscriber.as_ref().unwrap();
-            let mut ts_write = ts.write().await;
-            let token = ts_write.remove_follow(user.id, x_twitter_user_id);
-            drop(ts_write);
-            if token.ne("") {
-                TwitterSubscriber::subscribe(ts.clone(), token)
-                    .await
-                    .unwrap();
-            };
+            ts.remove_follow(user.id, x_twitter_user_id).await?;
+            tokio::spawn(ts.clone().refresh_inline_buttons(
+                bot.clone(),
+                user.id,
+                vec![x_twitter_user_id],
+            ));
             bot.send_message(
                 message.chat.id,
                 match res {
                     Ok(count) => {
                         format!("Unsubscribe Success, affecting {:?} records", count)
                     }
-                    Err(err) => {
-                        format!("Failure, error {:?}", err)
-                    }
+                    Err(err) => failure(&err),
                 },
             )
             .await?
         }
         Command::ListFollowedTwitterID => {
-            if !user_pre_check().await {
+            if !user_pre_check(Role::ReadOnly).await {
                 return Ok(());
             };
             let user = user.unwrap();
@@ -471,13 +816,18 @@ async fn command_handler(
                 .await?;
                 return Ok(());
             };
-            let res = follow_model::get_follows_by_user_id(&ctx.db_pool.get().unwrap(), user.id);
-            if res.is_err() {
-                bot.send_message(message.chat.id, format!("Failure, error {:?}", res.err()))
-                    .await?;
-                return Ok(());
-            }
-            let follow_vec = res.unwrap();
+            let uid = user.id;
+            let res = ctx
+                .db
+                .run(move |conn| follow_model::get_follows_by_user_id(conn, uid))
+                .await;
+            let follow_vec = match res {
+                Ok(vec) => vec,
+                Err(err) => {
+                    bot.send_message(message.chat.id, failure(&err)).await?;
+                    return Ok(());
+                }
+            };
             let mut msg_list: Vec<String> = Vec::new();
             let mut msg = escape("You are currently subscribed to the following accounts.\n");
 
@@ -499,8 +849,128 @@ async fn command_handler(
 
             bot.send_message(message.chat.id, msg).await?
         }
+        Command::Search(args) => {
+            if !user_pre_check(Role::ReadOnly).await {
+                return Ok(());
+            };
+            let (filter, page) = match parse_search(&args) {
+                Ok(res) => res,
+                Err(e) => {
+                    bot.send_message(message.chat.id, escape(&e)).await?;
+                    return Ok(());
+                }
+            };
+            let uid = user.unwrap().id;
+            let offset = (page - 1) * SEARCH_PAGE_SIZE;
+            let res = ctx
+                .db
+                .run(move |conn| {
+                    tweet_model::search_tweets(conn, uid, &filter, offset, SEARCH_PAGE_SIZE)
+                })
+                .await;
+            let (total, list) = match res {
+                Ok(res) => res,
+                Err(err) => {
+                    bot.send_message(message.chat.id, failure(&err)).await?;
+                    return Ok(());
+                }
+            };
+            if total == 0 {
+                bot.send_message(message.chat.id, "No tweets found").await?;
+                return Ok(());
+            }
+            let pages = (total + SEARCH_PAGE_SIZE - 1) / SEARCH_PAGE_SIZE;
+            let mut msg = escape(&format!(
+                "Found {} tweets, page {}/{}\n",
+                total, page, pages
+            ));
+            for t in &list {
+                let mut text = t.text.replace('\n', " ");
+                if text.chars().count() > 80 {
+                    text = format!("{}…", text.chars().take(80).collect::<String>());
+                }
+                msg.push_str(&format!(
+                    "\\* {} {} {}\n",
+                    bold(&escape(&t.screen_name)),
+                    escape(&t.created_at.format("%Y-%m-%d %H:%M").to_string()),
+                    link(&t.url, &escape(&text))
+                ));
+            }
+
+            // 翻页按钮，参数太长放不进 callback data 时需要手动输入 page:n
+            let args = args
+                .split_whitespace()
+                .filter(|w| !w.starts_with("page:"))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let mut buttons = Vec::new();
+            for (label, p) in [("◀️Prev", page - 1), ("Next▶️", page + 1)] {
+                let data = format!("/Search {} page:{}", args, p);
+                if p >= 1 && p <= pages && data.len() <= CALLBACK_DATA_LIMIT {
+                    buttons.push(InlineKeyboardButton::callback(label.to_string(), data));
+                }
+            }
+            let req = bot
+                .send_message(message.chat.id, msg)
+                .disable_web_page_preview(true);
+            if buttons.is_empty() {
+                req.await?
+            } else {
+                req.reply_markup(InlineKeyboardMarkup::new(vec![buttons]))
+                    .await?
+            }
+        }
+        Command::WhyNot(tweet_url) => {
+            if !user_pre_check(Role::ReadOnly).await {
+                return Ok(());
+            };
+            let x_tweet_id = match parse_tweet_id(&tweet_url) {
+                Some(id) => id,
+                None => {
+                    bot.send_message(message.chat.id, "Usage: /WhyNot tweetURL")
+                        .await?;
+                    return Ok(());
+                }
+            };
+            let uid = user.unwrap().id;
+            let res = ctx
+                .db
+                .run(move |conn| delivery_log_model::get_logs_by_tweet(conn, uid, x_tweet_id))
+                .await;
+            let logs = match res {
+                Ok(logs) => logs,
+                Err(err) => {
+                    bot.send_message(message.chat.id, failure(&err)).await?;
+                    return Ok(());
+                }
+            };
+            if logs.is_empty() {
+                bot.send_message(
+                    message.chat.id,
+                    escape(&format!(
+                        "No delivery record for tweet {}. You may not follow the author or retweeter, the tweet may be older than {} days or a self retweet, or the record has expired.",
+                        x_tweet_id, ctx.config.forwarder.max_tweet_age_days
+                    )),
+                )
+                .await?;
+                return Ok(());
+            }
+            let mut msg = escape(&format!("Tweet {}:\n", x_tweet_id));
+            for l in &logs {
+                msg.push_str(&format!(
+                    "\\* {}\n",
+                    escape(&format!(
+                        "{} via @{}: {}",
+                        l.created_at.format("%Y-%m-%d %H:%M:%S"),
+                        l.screen_name,
+                        explain_decision(Decision::from_i32(l.decision), &l.detail)
+                    ))
+                ));
+            }
+            bot.send_message(message.chat.id, msg).await?
+        }
         Command::ListBlockedTwitterID(x_type) => {
-            if !user_pre_check().await {
+            if !user_pre_check(Role::ReadOnly).await {
                 return Ok(());
             };
             let user = user.unwrap();
@@ -512,17 +982,18 @@ async fn command_handler(
                 .await?;
                 return Ok(());
             };
-            let res = blacklist_model::get_blacklist_by_user_id(
-                &ctx.db_pool.get().unwrap(),
-                user.id,
-                x_type,
-            );
-            if res.is_err() {
-                bot.send_message(message.chat.id, format!("Failure, error {:?}", res.err()))
-                    .await?;
-                return Ok(());
-            }
-            let list = res.unwrap();
+            let uid = user.id;
+            let res = ctx
+                .db
+                .run(move |conn| blacklist_model::get_blacklist_by_user_id(conn, uid, x_type))
+                .await;
+            let list = match res {
+                Ok(list) => list,
+                Err(err) => {
+                    bot.send_message(message.chat.id, failure(&err)).await?;
+                    return Ok(());
+                }
+            };
             let mut msg_list: Vec<String> = Vec::new();
             let mut msg = escape("Your blacklist.\n");
 
@@ -548,7 +1019,7 @@ async fn command_handler(
             telegram_id,
             custom_label,
         } => {
-            if !admin_pre_check().await {
+            if !role_pre_check(Role::Admin).await {
                 return Ok(());
             }
             if telegram_id.le(&0) {
@@ -569,12 +1040,23 @@ async fn command_handler(
                 .unwrap(),
                 disable_retweet: false,
                 disable_text_msg: false,
+                suspended: false,
+                follow_quota: 0,
+                dry_run: false,
+                remove_deleted: false,
             };
-            let res = user_model::create_user(&ctx.db_pool.get().unwrap(), user.clone());
+            let new_user = user.clone();
+            let res = ctx
+                .db
+                .run(move |conn| user_model::create_user(conn, new_user))
+                .await;
 
             if res.is_ok() {
-                let mut ts_write = ctx.twitter_subscriber.as_ref().unwrap().write().await;
-                ts_write.user_info.insert(user.id, user);
+                ctx.twitter_subscriber
+                    .as_ref()
+                    .unwrap()
+                    .set_user(user)
+                    .await?;
             }
 
             bot.send_message(
@@ -588,66 +1070,755 @@ async fn command_handler(
                             format!("Success, affecting {:?} Records", count)
                         }
                         Err(err) => {
-                            format!("Failure, error {:?}", err)
+                            failure(&err)
                         }
                     }
                 ),
             )
             .await?
         }
-        Command::SetDisableRetweet(disable) => {
-            if !user_pre_check().await {
+        Command::RemoveUser(telegram_id) => {
+            if !role_pre_check(Role::Admin).await {
                 return Ok(());
-            };
-            let user = user.unwrap();
-            let res =
-                user_model::update_disable_retweet(&ctx.db_pool.get().unwrap(), user.id, disable);
-            if res.is_err() {
-                bot.send_message(message.chat.id, format!("Failure, error {:?}", res.err()))
-                    .await?
-            } else {
-                let mut user = user.clone();
-                user.disable_retweet = disable;
-                let mut ts_write = ctx.twitter_subscriber.as_ref().unwrap().write().await;
-                ts_write.user_info.insert(user.id, user);
-                bot.send_message(
-                    message.chat.id,
-                    format!("Success, affecting {:?} Records", res.unwrap()),
-                )
-                .await?
             }
-        }
-        Command::SetDisableTextMsg(disable) => {
-            if !user_pre_check().await {
+            if !target_pre_check(telegram_id).await? {
                 return Ok(());
-            };
-            let user = user.unwrap();
-            let res =
-                user_model::update_disable_text_msg(&ctx.db_pool.get().unwrap(), user.id, disable);
-            if res.is_err() {
-                bot.send_message(message.chat.id, format!("Failure, error {:?}", res.err()))
-                    .await?
-            } else {
-                let mut user = user.clone();
-                user.disable_text_msg = disable;
-                let mut ts_write = ctx.twitter_subscriber.as_ref().unwrap().write().await;
-                ts_write.user_info.insert(user.id, user);
-                bot.send_message(
-                    message.chat.id,
-                    format!("Success, affecting {:?} Records", res.unwrap()),
-                )
-                .await?
             }
+            let res = ctx
+                .db
+                .run(move |conn| user_model::remove_user(conn, telegram_id))
+                .await;
+            if res.is_ok() {
+                ctx.twitter_subscriber
+                    .as_ref()
+                    .unwrap()
+                    .remove_user(telegram_id)
+                    .await?;
+            }
+            bot.send_message(
+                message.chat.id,
+                format!(
+                    "_{:?}_ Remove {}",
+                    telegram_id,
+                    match res {
+                        Ok(count) => {
+                            format!("Success, affecting {:?} Records", count)
+                        }
+                        Err(err) => {
+                            failure(&err)
+                        }
+                    }
+                ),
+            )
+            .await?
         }
-    };
-    Ok(())
-}
+        Command::ListUsers => {
+            if !role_pre_check(Role::Admin).await {
+                return Ok(());
+            }
+            let (user_vec, follow_vec) = ctx
+                .db
+                .run(|conn| {
+                    Ok((
+                        user_model::get_all_users(conn)?,
+                        follow_model::get_all_follows(conn)?,
+                    ))
+                })
+                .await?;
+            let follow_count = follow_vec.iter().fold(HashMap::new(), |mut acc, f| {
+                *acc.entry(f.user_id).or_insert(0) += 1;
+                acc
+            });
 
-async fn callback_handler(
-    ctx: Arc<TelegramContext>,
-    bot: AutoSend<DefaultParseMode<Bot>>,
-    q: CallbackQuery,
-) -> Result<(), anyhow::Error> {
+            let mut msg_list: Vec<String> = Vec::new();
+            let mut msg = escape(&format!("There are {} users.\n", user_vec.len()));
+            user_vec.chunks(50).for_each(|chunk| {
+                chunk.iter().for_each(|u| {
+                    msg.push_str(&format!(
+                        "\\* *{}* _{:?}_ token: {} follows: {}{}{}{}\n",
+                        escape(&u.label),
+                        u.id,
+                        if u.twitter_status { "✅" } else { "❌" },
+                        follow_count.get(&u.id).unwrap_or(&0),
+                        match u.follow_quota {
+                            0 => "".to_string(),
+                            quota => format!("/{}", quota),
+                        },
+                        if u.suspended { " \\(suspended\\)" } else { "" },
+                        if u.dry_run { " \\(dry run\\)" } else { "" },
+                    ))
+                });
+                msg_list.push(msg.clone());
+                msg.clear();
+            });
+
+            for msg in msg_list {
+                bot.send_message(message.chat.id, msg).await?;
+            }
+
+            return Ok(());
+        }
+        Command::SetUserLabel {
+            telegram_id,
+            custom_label,
+        } => {
+            if !role_pre_check(Role::Admin).await {
+                return Ok(());
+            }
+            if !target_pre_check(telegram_id).await? {
+                return Ok(());
+            }
+            let new_label = custom_label.clone();
+            let res = ctx
+                .db
+                .run(move |conn| user_model::update_label(conn, telegram_id, new_label))
+                .await;
+            if let Ok(count) = res {
+                if count > 0 {
+                    let label = custom_label.clone();
+                    ctx.twitter_subscriber
+                        .as_ref()
+                        .unwrap()
+                        .update_user(telegram_id, move |u| u.label = label)
+                        .await?;
+                }
+            }
+            bot.send_message(
+                message.chat.id,
+                format!(
+                    "*{}* _{:?}_ Update {}",
+                    escape(&custom_label),
+                    telegram_id,
+                    match res {
+                        Ok(count) => {
+                            format!("Success, affecting {:?} Records", count)
+                        }
+                        Err(err) => {
+                            failure(&err)
+                        }
+                    }
+                ),
+            )
+            .await?
+        }
+        Command::SuspendUser(telegram_id) | Command::ResumeUser(telegram_id) => {
+            if !role_pre_check(Role::Admin).await {
+                return Ok(());
+            }
+            if !target_pre_check(telegram_id).await? {
+                return Ok(());
+            }
+            let suspend = matches!(command, Command::SuspendUser(_));
+            let res = ctx
+                .db
+                .run(move |conn| user_model::update_suspended(conn, telegram_id, suspend))
+                .await;
+            if let Ok(count) = res {
+                if count > 0 {
+                    ctx.twitter_subscriber
+                        .as_ref()
+                        .unwrap()
+                        .update_user(telegram_id, move |u| u.suspended = suspend)
+                        .await?;
+                }
+            }
+            bot.send_message(
+                message.chat.id,
+                format!(
+                    "_{:?}_ {} {}",
+                    telegram_id,
+                    if suspend { "Suspend" } else { "Resume" },
+                    match res {
+                        Ok(count) => {
+                            format!("Success, affecting {:?} Records", count)
+                        }
+                        Err(err) => {
+                            failure(&err)
+                        }
+                    }
+                ),
+            )
+            .await?
+        }
+        Command::SetDryRun {
+            telegram_id,
+            dry_run,
+        } => {
+            if !role_pre_check(Role::Admin).await {
+                return Ok(());
+            }
+            if !target_pre_check(telegram_id).await? {
+                return Ok(());
+            }
+            let res = ctx
+                .db
+                .run(move |conn| user_model::update_dry_run(conn, telegram_id, dry_run))
+                .await;
+            if let Ok(count) = res {
+                if count > 0 {
+                    ctx.twitter_subscriber
+                        .as_ref()
+                        .unwrap()
+                        .update_user(telegram_id, move |u| u.dry_run = dry_run)
+                        .await?;
+                }
+            }
+            bot.send_message(
+                message.chat.id,
+                format!(
+                    "_{:?}_ Dry run {} {}",
+                    telegram_id,
+                    dry_run,
+                    match res {
+                        Ok(count) => {
+                            format!("Success, affecting {:?} Records", count)
+                        }
+                        Err(err) => {
+                            failure(&err)
+                        }
+                    }
+                ),
+            )
+            .await?
+        }
+        Command::SetUserQuota { telegram_id, quota } => {
+            if !role_pre_check(Role::Admin).await {
+                return Ok(());
+            }
+            if !target_pre_check(telegram_id).await? {
+                return Ok(());
+            }
+            if quota.lt(&0) {
+                bot.send_message(message.chat.id, "Incorrect quota").await?;
+                return Ok(());
+            }
+            let res = ctx
+                .db
+                .run(move |conn| user_model::update_follow_quota(conn, telegram_id, quota))
+                .await;
+            bot.send_message(
+                message.chat.id,
+                format!(
+                    "_{:?}_ Quota {}",
+                    telegram_id,
+                    match res {
+                        Ok(count) => {
+                            format!("Success, affecting {:?} Records", count)
+                        }
+                        Err(err) => {
+                            failure(&err)
+                        }
+                    }
+                ),
+            )
+            .await?
+        }
+        Command::GrantRole {
+            telegram_id,
+            x_role,
+        } => {
+            if !role_pre_check(Role::Owner).await {
+                return Ok(());
+            }
+            let granted = match Role::from_i32(x_role) {
+                Some(granted) if telegram_id.gt(&0) => granted,
+                _ => {
+                    bot.send_message(message.chat.id, "Incorrect ID or role")
+                        .await?;
+                    return Ok(());
+                }
+            };
+            // 只能授予低于自己的角色，所有者只有配置的管理员一个
+            if role.is_none_or(|r| granted >= r) {
+                bot.send_message(
+                    message.chat.id,
+                    format!("Permission denied, cannot grant *{:?}* role", granted),
+                )
+                .await?;
+                return Ok(());
+            }
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+            let user_role = UserRole {
+                user_id: telegram_id,
+                role: x_role,
+                created_at: NaiveDateTime::from_timestamp_opt(
+                    now.as_secs() as i64,
+                    now.subsec_nanos(),
+                )
+                .unwrap(),
+            };
+            let res = ctx
+                .db
+                .run(move |conn| role_model::grant_role(conn, user_role))
+                .await;
+            bot.send_message(
+                message.chat.id,
+                format!(
+                    "_{:?}_ Grant *{:?}* {}",
+                    telegram_id,
+                    granted,
+                    match res {
+                        Ok(count) => {
+                            format!("Success, affecting {:?} Records", count)
+                        }
+                        Err(err) => {
+                            failure(&err)
+                        }
+                    }
+                ),
+            )
+            .await?
+        }
+        Command::RevokeRole(telegram_id) => {
+            if !role_pre_check(Role::Owner).await {
+                return Ok(());
+            }
+            let res = ctx
+                .db
+                .run(move |conn| role_model::revoke_role(conn, telegram_id))
+                .await;
+            bot.send_message(
+                message.chat.id,
+                format!(
+                    "_{:?}_ Revoke {}",
+                    telegram_id,
+                    match res {
+                        Ok(count) => {
+                            format!("Success, affecting {:?} Records", count)
+                        }
+                        Err(err) => {
+                            failure(&err)
+                        }
+                    }
+                ),
+            )
+            .await?
+        }
+        Command::ListRoles => {
+            if !role_pre_check(Role::Owner).await {
+                return Ok(());
+            }
+            let list = ctx.db.run(role_model::get_all_roles).await?;
+            let mut msg = escape("Granted roles.\n");
+            msg.push_str(&format!(
+                "\\* _{:?}_ *{:?}* \\(config\\)\n",
+                ctx.config.telegram.admin_id,
+                Role::Owner
+            ));
+            list.iter().for_each(|r| {
+                msg.push_str(&format!(
+                    "\\* _{:?}_ *{}*\n",
+                    r.user_id,
+                    match Role::from_i32(r.role) {
+                        Some(x_role) => format!("{:?}", x_role),
+                        None => r.role.to_string(),
+                    }
+                ))
+            });
+            bot.send_message(message.chat.id, msg).await?
+        }
+        Command::RotateTokenKey => {
+            if !role_pre_check(Role::Owner).await {
+                return Ok(());
+            }
+            let res = ctx.db.run(user_model::rotate_token_key).await;
+            bot.send_message(
+                message.chat.id,
+                match res {
+                    Ok(count) => escape(&format!(
+                        "Re-encrypted {} tokens with the current key, old_token_keys can be removed now",
+                        count
+                    )),
+                    Err(err) => escape(&failure(&err)),
+                },
+            )
+            .await?
+        }
+        Command::Backup => {
+            if !role_pre_check(Role::Owner).await {
+                return Ok(());
+            }
+            let dir = Path::new(&ctx.config.backup.dir);
+            let path = match backup::create(&ctx.db, dir).await {
+                Ok(path) => path,
+                Err(e) => {
+                    log::error!("backup {:?}", e);
+                    bot.send_message(
+                        message.chat.id,
+                        escape(&format!("Backup failed, error {}", e)),
+                    )
+                    .await?;
+                    return Ok(());
+                }
+            };
+            if let Err(e) = backup::prune(dir, ctx.config.backup.keep) {
+                log::error!("prune backups {:?}", e);
+            }
+            bot.send_document(message.chat.id, InputFile::file(&path))
+                .await?
+        }
+        Command::CreateInvite {
+            max_uses,
+            expire_hours,
+            custom_label,
+        } => {
+            if !role_pre_check(Role::Admin).await {
+                return Ok(());
+            }
+            if max_uses.le(&0) || expire_hours.le(&0) {
+                bot.send_message(message.chat.id, "Incorrect uses or expiry")
+                    .await?;
+                return Ok(());
+            }
+            let invite_code = rand::thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(16)
+                .map(char::from)
+                .collect::<String>();
+            let now = now_naive();
+            let invite = invite_model::Invite {
+                code: invite_code.clone(),
+                label: custom_label.clone(),
+                max_uses,
+                used_count: 0,
+                expires_at: now + chrono::Duration::hours(expire_hours),
+                created_by: sender_id as i64,
+                created_at: now,
+            };
+            let res = ctx
+                .db
+                .run(move |conn| invite_model::create_invite(conn, invite))
+                .await;
+            match res {
+                Ok(_) => {
+                    let me = bot.get_me().await?;
+                    bot.send_message(
+                        message.chat.id,
+                        format!(
+                            "*{}* invite, {} uses, expires in {} hours\n{}",
+                            escape(&custom_label),
+                            max_uses,
+                            expire_hours,
+                            escape(&format!(
+                                "https://t.me/{}?start={}",
+                                me.username(),
+                                invite_code
+                            ))
+                        ),
+                    )
+                    .await?
+                }
+                Err(err) => bot.send_message(message.chat.id, failure(&err)).await?,
+            }
+        }
+        Command::ListInvites => {
+            if !role_pre_check(Role::Admin).await {
+                return Ok(());
+            }
+            let list = ctx.db.run(invite_model::get_all_invites).await?;
+            if list.is_empty() {
+                bot.send_message(message.chat.id, "No invite codes").await?;
+                return Ok(());
+            }
+            let now = now_naive();
+            let mut msg_list: Vec<String> = Vec::new();
+            let mut msg = escape("Invite codes.\n");
+            list.chunks(50).for_each(|chunk| {
+                chunk.iter().for_each(|i| {
+                    msg.push_str(&format!(
+                        "\\* `{}` *{}* {}/{} {}\n",
+                        i.code,
+                        escape(&i.label),
+                        i.used_count,
+                        i.max_uses,
+                        if i.expires_at > now {
+                            escape(&format!(
+                                "expires {}",
+                                i.expires_at.format("%Y-%m-%d %H:%M")
+                            ))
+                        } else {
+                            "expired".to_string()
+                        }
+                    ))
+                });
+                msg_list.push(msg.clone());
+                msg.clear();
+            });
+
+            for msg in msg_list {
+                bot.send_message(message.chat.id, msg).await?;
+            }
+
+            return Ok(());
+        }
+        Command::RevokeInvite(invite_code) => {
+            if !role_pre_check(Role::Admin).await {
+                return Ok(());
+            }
+            let invite_code = invite_code.trim().to_string();
+            let res = ctx
+                .db
+                .run(move |conn| invite_model::revoke_invite(conn, &invite_code))
+                .await;
+            bot.send_message(
+                message.chat.id,
+                match res {
+                    Ok(count) => {
+                        format!("Revoke successfully, affecting {:?} records", count)
+                    }
+                    Err(err) => failure(&err),
+                },
+            )
+            .await?
+        }
+        Command::Broadcast(text) => {
+            if !role_pre_check(Role::Admin).await {
+                return Ok(());
+            }
+            let broadcast = match message.reply_to_message() {
+                Some(reply) => Broadcast::Forward {
+                    from_chat_id: reply.chat.id,
+                    message_id: reply.id,
+                },
+                None if !text.trim().is_empty() => Broadcast::Text(escape(text.trim())),
+                None => {
+                    bot.send_message(
+                        message.chat.id,
+                        "Please provide the text, or reply to the message to broadcast",
+                    )
+                    .await?;
+                    return Ok(());
+                }
+            };
+            let user_count = ctx.db.run(user_model::get_all_users).await?.len();
+            let broadcast_id = rand::thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(8)
+                .map(char::from)
+                .collect::<String>();
+            ctx.broadcasts
+                .set(broadcast_id.clone(), broadcast.clone(), None)
+                .await;
+
+            // 预览
+            match broadcast {
+                Broadcast::Text(text) => {
+                    bot.send_message(message.chat.id, text).await?;
+                }
+                Broadcast::Forward {
+                    from_chat_id,
+                    message_id,
+                } => {
+                    bot.forward_message(message.chat.id, from_chat_id, message_id)
+                        .await?;
+                }
+            }
+            bot.send_message(
+                message.chat.id,
+                format!("Broadcast the message above to {} users?", user_count),
+            )
+            .reply_markup(InlineKeyboardMarkup::new(vec![vec![
+                InlineKeyboardButton::callback(
+                    "✅Confirm".to_string(),
+                    format!("/ConfirmBroadcast {}", broadcast_id),
+                ),
+                InlineKeyboardButton::callback(
+                    "❌Cancel".to_string(),
+                    format!("/CancelBroadcast {}", broadcast_id),
+                ),
+            ]]))
+            .await?
+        }
+        Command::ConfirmBroadcast(broadcast_id) => {
+            if !role_pre_check(Role::Admin).await {
+                return Ok(());
+            }
+            // remove 只有一个调用能取到，连点确认按钮也只发送一次
+            ctx.broadcasts.remove_expired().await;
+            let broadcast = match ctx.broadcasts.remove(&broadcast_id).await {
+                Some(broadcast) => broadcast,
+                None => {
+                    bot.send_message(message.chat.id, "Broadcast expired or already sent")
+                        .await?;
+                    return Ok(());
+                }
+            };
+            let user_vec = ctx.db.run(user_model::get_all_users).await?;
+            bot.send_message(
+                message.chat.id,
+                escape(&format!("Broadcasting to {} users...", user_vec.len())),
+            )
+            .await?;
+            tokio::spawn(run_broadcast(
+                bot.clone(),
+                message.chat.id,
+                broadcast,
+                user_vec,
+            ));
+            return Ok(());
+        }
+        Command::CancelBroadcast(broadcast_id) => {
+            if !role_pre_check(Role::Admin).await {
+                return Ok(());
+            }
+            ctx.broadcasts.remove(&broadcast_id).await;
+            bot.send_message(message.chat.id, "Broadcast cancelled")
+                .await?
+        }
+        Command::SetDisableRetweet(disable) => {
+            if !user_pre_check(Role::User).await {
+                return Ok(());
+            };
+            let user = user.unwrap();
+            let uid = user.id;
+            let res = ctx
+                .db
+                .run(move |conn| user_model::update_disable_retweet(conn, uid, disable))
+                .await;
+            match res {
+                Err(err) => bot.send_message(message.chat.id, failure(&err)).await?,
+                Ok(count) => {
+                    let mut user = user.clone();
+                    user.disable_retweet = disable;
+                    ctx.twitter_subscriber
+                        .as_ref()
+                        .unwrap()
+                        .set_user(user)
+                        .await?;
+                    bot.send_message(
+                        message.chat.id,
+                        format!("Success, affecting {:?} Records", count),
+                    )
+                    .await?
+                }
+            }
+        }
+        Command::SetDisableTextMsg(disable) => {
+            if !user_pre_check(Role::User).await {
+                return Ok(());
+            };
+            let user = user.unwrap();
+            let uid = user.id;
+            let res = ctx
+                .db
+                .run(move |conn| user_model::update_disable_text_msg(conn, uid, disable))
+                .await;
+            match res {
+                Err(err) => bot.send_message(message.chat.id, failure(&err)).await?,
+                Ok(count) => {
+                    let mut user = user.clone();
+                    user.disable_text_msg = disable;
+                    ctx.twitter_subscriber
+                        .as_ref()
+                        .unwrap()
+                        .set_user(user)
+                        .await?;
+                    bot.send_message(
+                        message.chat.id,
+                        format!("Success, affecting {:?} Records", count),
+                    )
+                    .await?
+                }
+            }
+        }
+        Command::SetRemoveDeleted(remove) => {
+            if !user_pre_check(Role::User).await {
+                return Ok(());
+            };
+            let user = user.unwrap();
+            let uid = user.id;
+            let res = ctx
+                .db
+                .run(move |conn| user_model::update_remove_deleted(conn, uid, remove))
+                .await;
+            match res {
+                Err(err) => bot.send_message(message.chat.id, failure(&err)).await?,
+                Ok(count) => {
+                    let mut user = user.clone();
+                    user.remove_deleted = remove;
+                    ctx.twitter_subscriber
+                        .as_ref()
+                        .unwrap()
+                        .set_user(user)
+                        .await?;
+                    bot.send_message(
+                        message.chat.id,
+                        format!("Success, affecting {:?} Records", count),
+                    )
+                    .await?
+                }
+            }
+        }
+    };
+    Ok(())
+}
+
+// 逐个发送广播，控制在 Telegram 的频率限制内，最后向管理员汇报结果
+async fn run_broadcast(
+    bot: AutoSend<DefaultParseMode<Bot>>,
+    report_chat_id: ChatId,
+    broadcast: Broadcast,
+    user_vec: Vec<User>,
+) {
+    let mut delivered = 0;
+    let mut failed = 0;
+    let mut blocked_users = Vec::new();
+    for u in &user_vec {
+        let mut res = Ok(());
+        for _ in 0..3 {
+            res = match &broadcast {
+                Broadcast::Text(text) => bot
+                    .send_message(UserId(u.id as u64), text)
+                    .await
+                    .map(|_| ()),
+                Broadcast::Forward {
+                    from_chat_id,
+                    message_id,
+                } => bot
+                    .forward_message(UserId(u.id as u64), *from_chat_id, *message_id)
+                    .await
+                    .map(|_| ()),
+            };
+            match &res {
+                Err(RequestError::RetryAfter(d)) => tokio::time::sleep(*d).await,
+                _ => break,
+            }
+        }
+        match res {
+            Ok(_) => delivered += 1,
+            Err(RequestError::Api(
+                ApiError::BotBlocked | ApiError::UserDeactivated | ApiError::ChatNotFound,
+            )) => blocked_users.push(u),
+            Err(e) => {
+                log::error!("telegram@{} broadcast {:?}", &u.id, e);
+                failed += 1;
+            }
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    let mut msg = escape(&format!(
+        "Broadcast finished, delivered {}, failed {}, blocked {}.\n",
+        delivered,
+        failed,
+        blocked_users.len()
+    ));
+    if !blocked_users.is_empty() {
+        msg.push_str(&escape("Users who have blocked the bot:\n"));
+        blocked_users
+            .iter()
+            .for_each(|u| msg.push_str(&format!("\\* *{}* _{:?}_\n", escape(&u.label), u.id)));
+    }
+    if let Err(e) = bot.send_message(report_chat_id, msg).await {
+        log::error!("telegram@{} broadcast report {:?}", &report_chat_id, e);
+    }
+}
+
+async fn callback_handler(
+    ctx: Arc<TelegramContext>,
+    bot: AutoSend<DefaultParseMode<Bot>>,
+    q: CallbackQuery,
+) -> Result<(), anyhow::Error> {
     if q.data.is_some() && q.message.is_some() {
         if let Ok(cmd) = Command::parse(&q.data.unwrap(), ctx.name.clone()) {
             let mut message = q.message.unwrap();
@@ -661,7 +1832,11 @@ async fn callback_handler(
     Ok(())
 }
 
-pub async fn run(bot: AutoSend<DefaultParseMode<Bot>>, tg_ctx: Arc<TelegramContext>) {
+pub async fn run(
+    bot: AutoSend<DefaultParseMode<Bot>>,
+    tg_ctx: Arc<TelegramContext>,
+    mut shutdown: watch::Receiver<bool>,
+) {
     let handler = dptree::entry()
         .branch(
             Update::filter_message()
@@ -670,7 +1845,9 @@ pub async fn run(bot: AutoSend<DefaultParseMode<Bot>>, tg_ctx: Arc<TelegramConte
         )
         .branch(Update::filter_callback_query().endpoint(callback_handler));
     log::info!("Tg bot started");
-    Dispatcher::builder(bot, handler)
+    let _alive = health::TaskGuard::new("telegram_dispatcher");
+    let webhook_config = tg_ctx.config.telegram.webhook.clone();
+    let mut dispatcher = Dispatcher::builder(bot.clone(), handler)
         .dependencies(dptree::deps![tg_ctx.clone()])
         .default_handler(|upd| async move {
             log::warn!("Unhandled update: {:?}", upd);
@@ -678,7 +1855,46 @@ pub async fn run(bot: AutoSend<DefaultParseMode<Bot>>, tg_ctx: Arc<TelegramConte
         .error_handler(LoggingErrorHandler::with_custom_text(
             "An error has occurred in the dispatcher",
         ))
-        .build()
-        .dispatch()
+        .build();
+
+    // 收到退出信号后停止拉取更新，等待正在处理的更新完成
+    let shutdown_token = dispatcher.shutdown_token();
+    tokio::spawn(async move {
+        let _ = shutdown.changed().await;
+        match shutdown_token.shutdown() {
+            Ok(f) => {
+                log::info!("Tg bot shutting down");
+                f.await;
+            }
+            Err(e) => log::warn!("Tg bot shutdown {:?}", e),
+        }
+    });
+
+    if !webhook_config.enabled() {
+        dispatcher.dispatch().await;
+        log::info!("Tg bot stopped");
+        return;
+    }
+
+    let (listener, server) = match webhook::listener(bot, &webhook_config).await {
+        Ok(res) => res,
+        Err(e) => {
+            log::error!("Tg webhook setup {:?}", e);
+            return;
+        }
+    };
+    dispatcher
+        .dispatch_with_listener(
+            listener,
+            LoggingErrorHandler::with_custom_text("An error from the webhook listener"),
+        )
         .await;
+    // 等待服务退出，期间会调用 deleteWebhook
+    if tokio::time::timeout(Duration::from_secs(10), server)
+        .await
+        .is_err()
+    {
+        log::warn!("Tg webhook server did not stop in time");
+    }
+    log::info!("Tg bot stopped");
 }
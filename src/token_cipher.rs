@@ -0,0 +1,114 @@
+use std::sync::RwLock;
+
+use anyhow::anyhow;
+use lazy_static::lazy_static;
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN},
+    rand::{SecureRandom, SystemRandom},
+};
+
+use crate::config::DatabaseConfig;
+
+// 密文格式 v1:base64(nonce || ciphertext || tag)，用户 id 作为附加数据，防止把密文挪到别的用户下
+const PREFIX: &str = "v1:";
+const KEY_LEN: usize = 32;
+
+lazy_static! {
+    // 第一个是当前密钥，其余是轮换前的旧密钥，只用于解密
+    static ref KEYS: RwLock<Vec<LessSafeKey>> = RwLock::new(Vec::new());
+    static ref RNG: SystemRandom = SystemRandom::new();
+}
+
+// 数据库里 token 的加密状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyState {
+    Plaintext,
+    Current,
+    Old,
+}
+
+// 解析 base64 编码的 32 字节密钥
+pub fn parse_key(key: &str) -> Result<[u8; KEY_LEN], String> {
+    let bytes = base64::decode(key.trim()).map_err(|e| e.to_string())?;
+    bytes
+        .try_into()
+        .map_err(|b: Vec<u8>| format!("expected {} bytes, got {}", KEY_LEN, b.len()))
+}
+
+// 按配置加载密钥，token_key 为空时 token 以明文保存
+pub fn init(config: &DatabaseConfig) -> Result<(), anyhow::Error> {
+    let mut keys = Vec::new();
+    if !config.token_key.is_empty() {
+        for key in std::iter::once(&config.token_key).chain(config.old_token_keys.iter()) {
+            let bytes = parse_key(key).map_err(|e| anyhow!("invalid token key: {}", e))?;
+            let unbound =
+                UnboundKey::new(&AES_256_GCM, &bytes).map_err(|_| anyhow!("invalid token key"))?;
+            keys.push(LessSafeKey::new(unbound));
+        }
+    }
+    *KEYS.write().unwrap() = keys;
+    Ok(())
+}
+
+pub fn enabled() -> bool {
+    !KEYS.read().unwrap().is_empty()
+}
+
+// 用当前密钥加密，未配置密钥时原样返回
+pub fn encrypt(uid: i64, plain: &str) -> Result<String, anyhow::Error> {
+    let keys = KEYS.read().unwrap();
+    let key = match keys.first() {
+        Some(key) => key,
+        None => return Ok(plain.to_string()),
+    };
+    let mut nonce = [0u8; NONCE_LEN];
+    RNG.fill(&mut nonce)
+        .map_err(|_| anyhow!("generate nonce failed"))?;
+    let mut in_out = plain.as_bytes().to_vec();
+    key.seal_in_place_append_tag(
+        Nonce::assume_unique_for_key(nonce),
+        Aad::from(uid.to_be_bytes()),
+        &mut in_out,
+    )
+    .map_err(|_| anyhow!("encrypt token of user {} failed", uid))?;
+    let mut data = nonce.to_vec();
+    data.extend_from_slice(&in_out);
+    Ok(format!("{}{}", PREFIX, base64::encode(data)))
+}
+
+// 依次尝试当前密钥和旧密钥，没有前缀的是加密上线前写入的明文
+pub fn decrypt(uid: i64, stored: &str) -> Result<(String, KeyState), anyhow::Error> {
+    let encoded = match stored.strip_prefix(PREFIX) {
+        Some(encoded) => encoded,
+        None => return Ok((stored.to_string(), KeyState::Plaintext)),
+    };
+    let keys = KEYS.read().unwrap();
+    if keys.is_empty() {
+        return Err(anyhow!(
+            "token of user {} is encrypted but database.token_key is not configured",
+            uid
+        ));
+    }
+    let data = base64::decode(encoded).map_err(|e| anyhow!("token of user {}: {}", uid, e))?;
+    if data.len() < NONCE_LEN {
+        return Err(anyhow!("token of user {} is truncated", uid));
+    }
+    let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+    for (i, key) in keys.iter().enumerate() {
+        let mut in_out = ciphertext.to_vec();
+        let nonce = Nonce::try_assume_unique_for_key(nonce).unwrap();
+        if let Ok(plain) = key.open_in_place(nonce, Aad::from(uid.to_be_bytes()), &mut in_out) {
+            let plain = String::from_utf8(plain.to_vec())?;
+            let state = if i == 0 {
+                KeyState::Current
+            } else {
+                KeyState::Old
+            };
+            return Ok((plain, state));
+        }
+    }
+    Err(anyhow!(
+        "token of user {} can not be decrypted with any configured key",
+        uid
+    ))
+}
@@ -0,0 +1,80 @@
+use std::{io, pin::Pin};
+
+use egg_mode::{
+    raw::{self, ParamList},
+    user::TwitterUser,
+    Token,
+};
+use futures::Stream;
+use hyper::{body::HttpBody, client::ResponseFuture, Body};
+
+use crate::config::TwitterConfig;
+
+// 与 egg_mode 同样的接口，但地址来自配置，便于测试时指向本地模拟服务
+
+pub async fn show_user(
+    config: &TwitterConfig,
+    user_id: u64,
+    token: &Token,
+) -> Result<TwitterUser, egg_mode::error::Error> {
+    let params = ParamList::new()
+        .extended_tweets()
+        .add_param("user_id", user_id.to_string());
+    let req = raw::request_get(
+        &format!("{}/1.1/users/show.json", config.api_url),
+        token,
+        Some(&params),
+    );
+    Ok(raw::response_json::<TwitterUser>(req).await?.response)
+}
+
+// 按 \r\n 分行返回 stream 的原始文本，和 egg_mode 的 TwitterStream 一样，但保留原文以便录制
+pub type RawStream = Pin<Box<dyn Stream<Item = Result<String, egg_mode::error::Error>> + Send>>;
+
+enum RawState {
+    Connecting(ResponseFuture),
+    Reading(Body, Vec<u8>),
+}
+
+pub fn filter_stream(config: &TwitterConfig, follows: &[u64], token: &Token) -> RawStream {
+    let follow = follows
+        .iter()
+        .map(|id| id.to_string())
+        .collect::<Vec<String>>()
+        .join(",");
+    let params = ParamList::new().add_param("follow", follow);
+    let req = raw::request_post(
+        &format!("{}/1.1/statuses/filter.json", config.stream_url),
+        token,
+        Some(&params),
+    );
+    let state = RawState::Connecting(raw::response_future(req));
+    Box::pin(futures::stream::try_unfold(state, |state| async move {
+        let (mut body, mut buf) = match state {
+            RawState::Connecting(resp) => {
+                let resp = resp.await?;
+                if !resp.status().is_success() {
+                    return Err(egg_mode::error::Error::BadStatus(resp.status()));
+                }
+                (resp.into_body(), Vec::new())
+            }
+            RawState::Reading(body, buf) => (body, buf),
+        };
+        loop {
+            if let Some(pos) = buf.windows(2).position(|w| w == b"\r\n") {
+                let line = buf.drain(..pos + 2).collect::<Vec<u8>>();
+                let line = String::from_utf8(line).map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "stream did not contain valid UTF-8",
+                    )
+                })?;
+                return Ok(Some((line, RawState::Reading(body, buf))));
+            }
+            match body.data().await {
+                Some(chunk) => buf.extend_from_slice(&chunk?),
+                None => return Ok(None),
+            }
+        }
+    }))
+}
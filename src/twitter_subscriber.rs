@@ -1,146 +1,798 @@
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
     ops::Add,
     sync::Arc,
+    time::Duration,
 };
 
+use anyhow::anyhow;
+use chrono::NaiveDateTime;
+
 use egg_mode::{entities::MediaEntity, stream::StreamMessage};
-use futures::{FutureExt, TryStreamExt};
 use log::{error, info, warn};
-use r_cache::cache::Cache;
 use teloxide::{
     adaptors::{AutoSend, DefaultParseMode},
-    payloads::SendMessageSetters,
+    payloads::{EditMessageReplyMarkupSetters, EditMessageTextSetters, SendMessageSetters},
     prelude::Requester,
     types::{
         InlineKeyboardButton, InlineKeyboardMarkup, InputFile, InputMedia, InputMediaAnimation,
         InputMediaPhoto, InputMediaVideo, UserId,
     },
     utils::markdown::{bold, escape, link},
-    Bot,
+    ApiError, Bot, RequestError,
 };
-use tokio::sync::{
-    mpsc::{Receiver, Sender},
-    RwLock,
+use tokio::{
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        oneshot, watch,
+    },
+    time::Instant,
 };
 use url::Url;
 
+use crate::config::{Config, TwitterConfig};
+use crate::health;
+use crate::metrics;
 use crate::models::{
     blacklist_model::{self, Blacklist},
+    delivery_log_model::{self, Decision, DeliveryLog},
     follow_model::Follow,
+    forward_history_model::ForwardHistory,
+    sent_message_model::{self, SentMessage},
+    tweet_model,
     user_model::User,
+    Repository,
 };
+use crate::stream_supervisor::{StreamEvent, StreamSupervisor, DISCONNECT_TOKEN_REVOKED};
+use crate::twitter_api;
+
+// 每个用户保留的带按钮消息数量
+const BUTTON_MESSAGE_HISTORY: usize = 100;
+
+#[derive(Clone)]
+struct ButtonMessage {
+    message_id: i32,
+    twitter_user_id: u64,
+    retweet_user_id: u64,
+}
+
+// 推送去重记录，退出时写回数据库，启动时再读回来
+pub struct ForwardHistoryCache {
+    ttl: chrono::Duration,
+    items: HashMap<String, NaiveDateTime>,
+}
+
+impl ForwardHistoryCache {
+    pub fn new(ttl: Duration, records: Vec<ForwardHistory>) -> Self {
+        ForwardHistoryCache {
+            ttl: chrono::Duration::from_std(ttl).unwrap(),
+            items: records
+                .into_iter()
+                .map(|r| (r.cache_key, r.expires_at))
+                .collect(),
+        }
+    }
+
+    fn contains(&self, key: &str) -> bool {
+        let now = chrono::Utc::now().naive_utc();
+        self.items.get(key).is_some_and(|e| *e > now)
+    }
+
+    fn insert(&mut self, key: String) {
+        let expires_at = chrono::Utc::now().naive_utc() + self.ttl;
+        self.items.insert(key, expires_at);
+    }
+
+    fn remove_expired(&mut self) {
+        let now = chrono::Utc::now().naive_utc();
+        self.items.retain(|_, e| *e > now);
+    }
+
+    pub fn into_records(mut self) -> Vec<ForwardHistory> {
+        self.remove_expired();
+        self.items
+            .into_iter()
+            .map(|(cache_key, expires_at)| ForwardHistory {
+                cache_key,
+                expires_at,
+            })
+            .collect()
+    }
+}
 
 struct TwitterTokenContext {
     follows: Vec<u64>,
-    end_tx: Option<tokio::sync::oneshot::Sender<()>>,
     token: String,
     user_id: i64,
 }
 
+// 推送过滤与按钮生成读取的状态，由 actor 在每次变更后发布
+// 各字段写时复制，克隆一份快照只是增加引用计数
+#[derive(Clone, Default)]
+pub struct SubscriberSnapshot {
+    pub user_info: Arc<HashMap<i64, User>>,
+    pub blacklist_map: Arc<HashMap<i64, HashSet<(i64, i32)>>>,
+    pub follow_map: Arc<HashMap<i64, HashSet<i64>>>,
+    pub follow_to_twitter: Arc<HashMap<i64, Vec<i64>>>,
+    pub follow_rt_count_map: Arc<HashMap<i64, HashMap<i64, i64>>>,
+    pub block_rt_count_map: Arc<HashMap<i64, HashMap<i64, i64>>>,
+    // (token hash, follow 数)
+    pub follow_counts: Arc<Vec<(String, usize)>>,
+    // 所有 token 都已满，暂时没有订阅的 twitter 账号数
+    pub unassigned_follows: usize,
+}
+
+type Reply<T> = oneshot::Sender<T>;
+type PendingReply = Box<dyn FnOnce() + Send>;
+
+// 延后发送的回复
+fn pending<T: Send + 'static>(reply: Reply<T>, value: T) -> PendingReply {
+    Box::new(move || {
+        let _ = reply.send(value);
+    })
+}
+
+enum Command {
+    AddToken {
+        user_id: i64,
+        token: String,
+        reply: Reply<()>,
+    },
+    LoadFollow(Follow),
+    AddFollow {
+        follow: Follow,
+        from_twitter_user_id: i64,
+        reply: Reply<Result<(), anyhow::Error>>,
+    },
+    RemoveFollow {
+        user_id: i64,
+        twitter_user_id: i64,
+        reply: Reply<()>,
+    },
+    Block {
+        blacklist: Blacklist,
+        from_twitter_user_id: i64,
+        reply: Reply<()>,
+    },
+    Unblock {
+        user_id: i64,
+        twitter_user_id: i64,
+        type_: i32,
+        reply: Reply<()>,
+    },
+    SetUser {
+        user: User,
+        reply: Reply<()>,
+    },
+    UpdateUser {
+        user_id: i64,
+        update: Box<dyn FnOnce(&mut User) + Send>,
+        reply: Reply<()>,
+    },
+    RemoveUser {
+        user_id: i64,
+        reply: Reply<()>,
+    },
+    RecordButtonMessage {
+        tg_user_id: i64,
+        message: ButtonMessage,
+    },
+    ButtonMessages {
+        tg_user_id: i64,
+        twitter_ids: Vec<i64>,
+        reply: Reply<Vec<ButtonMessage>>,
+    },
+    StopStreams(Reply<()>),
+    Flush(Reply<()>),
+}
+
+// 与 TwitterSubscriber actor 通信的句柄，可以随意克隆
+#[derive(Clone)]
+pub struct SubscriberHandle {
+    config: Arc<Config>,
+    command_tx: Sender<Command>,
+    snapshot_rx: watch::Receiver<SubscriberSnapshot>,
+    tweet_tx: Sender<StreamMessage>,
+}
+
+impl SubscriberHandle {
+    pub fn snapshot(&self) -> SubscriberSnapshot {
+        self.snapshot_rx.borrow().clone()
+    }
+
+    async fn request<T>(
+        &self,
+        command: impl FnOnce(Reply<T>) -> Command,
+    ) -> Result<T, anyhow::Error> {
+        let (tx, rx) = oneshot::channel();
+        if self.command_tx.send(command(tx)).await.is_err() {
+            return Err(anyhow!("Twitter subscriber has stopped"));
+        }
+        rx.await
+            .map_err(|_| anyhow!("Twitter subscriber has stopped"))
+    }
+
+    pub async fn add_token(&self, user_id: i64, token: &str) -> Result<(), anyhow::Error> {
+        // 校验需要请求 twitter，不占用 actor
+        if !TwitterSubscriber::check_token_valid(&self.config.twitter, token).await? {
+            return Err(anyhow!("Twitter authorization has expired"));
+        }
+        self.load_token(user_id, token).await
+    }
+
+    // 不请求 twitter 校验直接加入，回放时使用
+    pub async fn load_token(&self, user_id: i64, token: &str) -> Result<(), anyhow::Error> {
+        self.request(|reply| Command::AddToken {
+            user_id,
+            token: token.to_string(),
+            reply,
+        })
+        .await
+    }
+
+    // 启动时恢复数据库中的订阅及计数
+    pub async fn load_follow(&self, follow: Follow) {
+        if self
+            .command_tx
+            .send(Command::LoadFollow(follow))
+            .await
+            .is_err()
+        {
+            error!("Twitter subscriber has stopped");
+        }
+    }
+
+    pub async fn add_follow(
+        &self,
+        follow: Follow,
+        from_twitter_user_id: i64,
+    ) -> Result<(), anyhow::Error> {
+        self.request(|reply| Command::AddFollow {
+            follow,
+            from_twitter_user_id,
+            reply,
+        })
+        .await?
+    }
+
+    pub async fn remove_follow(
+        &self,
+        user_id: i64,
+        twitter_user_id: i64,
+    ) -> Result<(), anyhow::Error> {
+        self.request(|reply| Command::RemoveFollow {
+            user_id,
+            twitter_user_id,
+            reply,
+        })
+        .await
+    }
+
+    pub async fn block(
+        &self,
+        blacklist: Blacklist,
+        from_twitter_user_id: i64,
+    ) -> Result<(), anyhow::Error> {
+        self.request(|reply| Command::Block {
+            blacklist,
+            from_twitter_user_id,
+            reply,
+        })
+        .await
+    }
+
+    pub async fn unblock(
+        &self,
+        user_id: i64,
+        twitter_user_id: i64,
+        type_: i32,
+    ) -> Result<(), anyhow::Error> {
+        self.request(|reply| Command::Unblock {
+            user_id,
+            twitter_user_id,
+            type_,
+            reply,
+        })
+        .await
+    }
+
+    pub async fn set_user(&self, user: User) -> Result<(), anyhow::Error> {
+        self.request(|reply| Command::SetUser { user, reply }).await
+    }
+
+    // 只更新已加载的用户
+    pub async fn update_user(
+        &self,
+        user_id: i64,
+        update: impl FnOnce(&mut User) + Send + 'static,
+    ) -> Result<(), anyhow::Error> {
+        self.request(|reply| Command::UpdateUser {
+            user_id,
+            update: Box::new(update),
+            reply,
+        })
+        .await
+    }
+
+    // 移除用户的全部订阅、token 及缓存状态
+    pub async fn remove_user(&self, user_id: i64) -> Result<(), anyhow::Error> {
+        self.request(|reply| Command::RemoveUser { user_id, reply })
+            .await
+    }
+
+    // 关闭所有 stream，之后不再重新订阅
+    pub async fn stop_streams(&self) -> Result<(), anyhow::Error> {
+        self.request(Command::StopStreams).await
+    }
+
+    // 等待之前发送的命令处理完，此时快照已经发布
+    pub async fn flush(&self) -> Result<(), anyhow::Error> {
+        self.request(Command::Flush).await
+    }
+
+    async fn record_button_message(&self, tg_user_id: i64, message: ButtonMessage) {
+        let _ = self
+            .command_tx
+            .send(Command::RecordButtonMessage {
+                tg_user_id,
+                message,
+            })
+            .await;
+    }
+
+    // 关注/屏蔽状态变化后，刷新最近推送消息上的按钮
+    pub async fn refresh_inline_buttons(
+        self,
+        tg: AutoSend<DefaultParseMode<Bot>>,
+        tg_user_id: i64,
+        twitter_ids: Vec<i64>,
+    ) {
+        let targets = match self
+            .request(|reply| Command::ButtonMessages {
+                tg_user_id,
+                twitter_ids,
+                reply,
+            })
+            .await
+        {
+            Ok(targets) => targets,
+            Err(e) => {
+                error!("telegram@{} refresh inline buttons {:?}", &tg_user_id, e);
+                return;
+            }
+        };
+
+        let snapshot = self.snapshot();
+        for m in targets {
+            let markup = InlineKeyboardMarkup::new(vec![get_inline_buttons(
+                tg_user_id,
+                m.retweet_user_id,
+                &snapshot,
+                m.twitter_user_id,
+            )]);
+            let res = tg
+                .edit_message_reply_markup(UserId(tg_user_id as u64), m.message_id)
+                .reply_markup(markup)
+                .await;
+            match res {
+                Ok(_) | Err(RequestError::Api(ApiError::MessageNotModified)) => {}
+                Err(e) => {
+                    warn!(
+                        "telegram@{} edit_message_reply_markup {} {:?}",
+                        &tg_user_id, m.message_id, e
+                    );
+                }
+            }
+        }
+    }
+
+    pub fn follow_counts(&self) -> Vec<(String, usize)> {
+        self.snapshot_rx.borrow().follow_counts.to_vec()
+    }
+
+    // (当前长度, 容量)
+    pub fn queue_depths(&self) -> [(&'static str, usize, usize); 2] {
+        [
+            (
+                "tweet_tx",
+                self.tweet_tx.max_capacity() - self.tweet_tx.capacity(),
+                self.tweet_tx.max_capacity(),
+            ),
+            (
+                "command_tx",
+                self.command_tx.max_capacity() - self.command_tx.capacity(),
+                self.command_tx.max_capacity(),
+            ),
+        ]
+    }
+}
+
+// 订阅状态由单独的任务持有，其他任务通过 SubscriberHandle 发送命令
 pub struct TwitterSubscriber {
+    config: Arc<Config>,
     tg_bot: AutoSend<DefaultParseMode<Bot>>,
-    tweet_tx: Sender<StreamMessage>,
-    subscribe_tx: Sender<String>,
+    streams: StreamSupervisor,
+    snapshot_tx: watch::Sender<SubscriberSnapshot>,
+    state: SubscriberSnapshot,
     token_map: HashMap<String, TwitterTokenContext>,
     token_vec: Vec<String>,
     twitter_sub_to_token_map: HashMap<i64, String>,
-    blacklist_map: HashMap<i64, HashSet<(i64, i32)>>,
-    follow_map: HashMap<i64, HashSet<i64>>,
-    follow_to_twiiter: HashMap<i64, Vec<i64>>,
-    pub block_rt_count_map: HashMap<i64, HashMap<i64, i64>>,
-    pub follow_rt_count_map: HashMap<i64, HashMap<i64, i64>>,
-    pub user_info: HashMap<i64, User>,
+    // 没有 token 有空余容量时，等待分配的 twitter 账号
+    unassigned: BTreeSet<i64>,
+    capacity_warned: bool,
+    button_messages: HashMap<i64, VecDeque<ButtonMessage>>,
+    shutting_down: bool,
 }
 
 impl TwitterSubscriber {
-    pub fn new(
+    pub fn spawn(
         tweet_tx: Sender<StreamMessage>,
-        subscribe_tx: Sender<String>,
         tg_bot: AutoSend<DefaultParseMode<Bot>>,
         blacklist_map: HashMap<i64, HashSet<(i64, i32)>>,
-        users: &Vec<User>,
-    ) -> Self {
+        users: &[User],
+        config: Arc<Config>,
+    ) -> SubscriberHandle {
         let user_info = users.iter().fold(HashMap::new(), |mut acc, user| {
             acc.insert(user.id, user.clone());
             acc
         });
-        TwitterSubscriber {
+        let state = SubscriberSnapshot {
+            user_info: Arc::new(user_info),
+            blacklist_map: Arc::new(blacklist_map),
+            ..Default::default()
+        };
+        let (command_tx, command_rx) = mpsc::channel(config.forwarder.channel_capacity);
+        let (events_tx, events_rx) = mpsc::channel(config.forwarder.channel_capacity);
+        let (snapshot_tx, snapshot_rx) = watch::channel(state.clone());
+        let handle = SubscriberHandle {
+            config: config.clone(),
+            command_tx,
+            snapshot_rx,
+            tweet_tx: tweet_tx.clone(),
+        };
+        let ts = TwitterSubscriber {
+            streams: StreamSupervisor::new(config.clone(), tweet_tx, events_tx),
+            config,
             tg_bot,
-            tweet_tx,
-            subscribe_tx,
+            snapshot_tx,
+            state,
             token_map: HashMap::new(),
-            twitter_sub_to_token_map: HashMap::new(),
-            blacklist_map,
-            follow_map: HashMap::new(),
             token_vec: Vec::new(),
-            follow_to_twiiter: HashMap::new(),
-            block_rt_count_map: HashMap::new(),
-            follow_rt_count_map: HashMap::new(),
-            user_info,
-        }
+            twitter_sub_to_token_map: HashMap::new(),
+            unassigned: BTreeSet::new(),
+            capacity_warned: false,
+            button_messages: HashMap::new(),
+            shutting_down: false,
+        };
+        tokio::spawn(ts.run(command_rx, events_rx));
+        handle
     }
 
     fn token_hash(token: &str) -> String {
         format!("{:x}", md5::compute(token))
     }
 
-    pub async fn subscribe_worker(ts: Arc<RwLock<TwitterSubscriber>>, mut rx: Receiver<String>) {
-        while let Some(t) = rx.recv().await {
-            let _ = TwitterSubscriber::subscribe(ts.clone(), t).await;
+    // 命令逐个处理，处理过程中不做任何网络请求
+    async fn run(
+        mut self,
+        mut command_rx: Receiver<Command>,
+        mut events_rx: Receiver<StreamEvent>,
+    ) {
+        let _alive = health::TaskGuard::new("twitter_subscriber");
+        loop {
+            let command = tokio::select! {
+                command = command_rx.recv() => match command {
+                    Some(command) => command,
+                    None => break,
+                },
+                Some(event) = events_rx.recv() => {
+                    match event {
+                        StreamEvent::Expired(token) => self.remove_token(&token),
+                        StreamEvent::Disconnected { token, code, reason } => {
+                            self.notify_disconnected(&token, code, &reason);
+                            continue;
+                        }
+                    }
+                    self.check_capacity();
+                    self.sync_streams();
+                    self.publish();
+                    continue;
+                },
+            };
+            let reply = match command {
+                Command::AddToken {
+                    user_id,
+                    token,
+                    reply,
+                } => {
+                    self.add_token(user_id, token);
+                    self.rebalance();
+                    Some(pending(reply, ()))
+                }
+                Command::LoadFollow(f) => {
+                    Arc::make_mut(&mut self.state.block_rt_count_map)
+                        .entry(f.user_id)
+                        .or_default()
+                        .insert(f.twitter_user_id, f.block_rt_count);
+                    Arc::make_mut(&mut self.state.follow_rt_count_map)
+                        .entry(f.user_id)
+                        .or_default()
+                        .insert(f.twitter_user_id, f.follow_rt_count);
+                    if let Err(e) = self.add_follow(f, 0) {
+                        error!("load follow {:?}", e);
+                    }
+                    None
+                }
+                Command::AddFollow {
+                    follow,
+                    from_twitter_user_id,
+                    reply,
+                } => Some(pending(
+                    reply,
+                    self.add_follow(follow, from_twitter_user_id),
+                )),
+                Command::RemoveFollow {
+                    user_id,
+                    twitter_user_id,
+                    reply,
+                } => {
+                    self.remove_follow(user_id, twitter_user_id);
+                    Some(pending(reply, ()))
+                }
+                Command::Block {
+                    blacklist,
+                    from_twitter_user_id,
+                    reply,
+                } => {
+                    self.block(blacklist, from_twitter_user_id);
+                    Some(pending(reply, ()))
+                }
+                Command::Unblock {
+                    user_id,
+                    twitter_user_id,
+                    type_,
+                    reply,
+                } => {
+                    if let Some(list) =
+                        Arc::make_mut(&mut self.state.blacklist_map).get_mut(&user_id)
+                    {
+                        list.remove(&(twitter_user_id, type_));
+                    }
+                    Some(pending(reply, ()))
+                }
+                Command::SetUser { user, reply } => {
+                    Arc::make_mut(&mut self.state.user_info).insert(user.id, user);
+                    Some(pending(reply, ()))
+                }
+                Command::UpdateUser {
+                    user_id,
+                    update,
+                    reply,
+                } => {
+                    if let Some(u) = Arc::make_mut(&mut self.state.user_info).get_mut(&user_id) {
+                        update(u);
+                    }
+                    Some(pending(reply, ()))
+                }
+                Command::RemoveUser { user_id, reply } => {
+                    self.remove_user(user_id);
+                    Some(pending(reply, ()))
+                }
+                Command::RecordButtonMessage {
+                    tg_user_id,
+                    message,
+                } => {
+                    let history = self.button_messages.entry(tg_user_id).or_default();
+                    if history.len() >= BUTTON_MESSAGE_HISTORY {
+                        history.pop_front();
+                    }
+                    history.push_back(message);
+                    // 不影响快照
+                    continue;
+                }
+                Command::ButtonMessages {
+                    tg_user_id,
+                    twitter_ids,
+                    reply,
+                } => {
+                    let targets = match self.button_messages.get(&tg_user_id) {
+                        Some(history) => history
+                            .iter()
+                            .filter(|m| {
+                                twitter_ids.contains(&(m.twitter_user_id as i64))
+                                    || (m.retweet_user_id > 0
+                                        && twitter_ids.contains(&(m.retweet_user_id as i64)))
+                            })
+                            .cloned()
+                            .collect(),
+                        None => Vec::new(),
+                    };
+                    // 不影响快照，直接回复
+                    let _ = reply.send(targets);
+                    continue;
+                }
+                Command::Flush(reply) => {
+                    // 之前的命令都在发布快照后才回复，这里只需排在它们之后
+                    let _ = reply.send(());
+                    continue;
+                }
+                Command::StopStreams(reply) => {
+                    self.shutting_down = true;
+                    self.streams.stop_all();
+                    Some(pending(reply, ()))
+                }
+            };
+            self.check_capacity();
+            self.sync_streams();
+            self.publish();
+            // 快照发布后再回复，调用方随后读取的快照已包含本次修改
+            if let Some(reply) = reply {
+                reply();
+            }
+        }
+    }
+
+    // 把各 token 当前分配的 follow 交给 supervisor，没有变化的 token 不会重连
+    fn sync_streams(&mut self) {
+        if self.shutting_down {
+            return;
+        }
+        for (hash, ctx) in &self.token_map {
+            self.streams.update(hash, &ctx.token, &ctx.follows);
         }
     }
 
-    pub async fn check_token_valid(token: &str) -> Result<bool, anyhow::Error> {
+    fn publish(&mut self) {
+        self.state.follow_counts = Arc::new(
+            self.token_map
+                .iter()
+                .map(|(hash, ctx)| (hash.clone(), ctx.follows.len()))
+                .collect(),
+        );
+        self.state.unassigned_follows = self.unassigned.len();
+        self.snapshot_tx.send_replace(self.state.clone());
+    }
+
+    pub async fn check_token_valid(
+        config: &TwitterConfig,
+        token: &str,
+    ) -> Result<bool, anyhow::Error> {
         let t: egg_mode::Token = serde_json::from_str(token)?;
-        let user = egg_mode::user::show(783214, &t).await?;
+        let user = twitter_api::show_user(config, 783214, &t).await?;
         Ok(user.screen_name.eq("Twitter"))
     }
 
     pub async fn forward_tweet(
-        forward_history: Arc<Cache<String, ()>>,
-        ts: Arc<RwLock<TwitterSubscriber>>,
+        mut forward_history: ForwardHistoryCache,
+        ts: SubscriberHandle,
+        db: Repository,
+        tg: AutoSend<DefaultParseMode<Bot>>,
         mut tweet_rx: Receiver<StreamMessage>,
-    ) {
-        while let Some(m) = tweet_rx.recv().await {
+        mut shutdown: watch::Receiver<bool>,
+    ) -> ForwardHistoryCache {
+        let _alive = health::TaskGuard::new("forward_tweet");
+        let config = ts.config.clone();
+        let max_tweet_age = config.forwarder.max_tweet_age();
+        let mut cleanup = tokio::time::interval(config.forwarder.history_cleanup());
+        // 收到退出信号后不再接收新消息，只在期限内处理队列中剩余的消息
+        let mut drain_deadline: Option<Instant> = None;
+        loop {
+            let m = tokio::select! {
+                m = tweet_rx.recv() => match m {
+                    Some(m) => m,
+                    None => break,
+                },
+                _ = shutdown.changed(), if drain_deadline.is_none() => {
+                    info!("forward_tweet draining queued messages");
+                    tweet_rx.close();
+                    drain_deadline = Some(Instant::now() + config.forwarder.shutdown_timeout());
+                    continue;
+                },
+                _ = tokio::time::sleep_until(drain_deadline.unwrap_or_else(Instant::now)), if drain_deadline.is_some() => {
+                    warn!("forward_tweet drain timeout, queued messages dropped");
+                    break;
+                },
+                _ = cleanup.tick() => {
+                    forward_history.remove_expired();
+                    let db = db.clone();
+                    let keep = config.forwarder.delivery_log_size;
+                    let before = (chrono::Utc::now()
+                        - chrono::Duration::days(config.forwarder.sent_message_days))
+                    .naive_utc();
+                    tokio::spawn(async move {
+                        if let Err(e) = db.run(move |conn| delivery_log_model::prune_logs(conn, keep)).await {
+                            error!("prune delivery logs {:?}", e);
+                        }
+                        if let Err(e) = db.run(move |conn| sent_message_model::prune_messages(conn, before)).await {
+                            error!("prune sent messages {:?}", e);
+                        }
+                    });
+                    continue;
+                },
+            };
             let t = match m {
-                StreamMessage::Tweet(t) => format_tweet(t),
+                StreamMessage::Tweet(t) => {
+                    let archived = archived_tweet(&t);
+                    let log = delivery_log(&t, &archived);
+                    format_tweet(t, max_tweet_age).map(|f| (f, archived, log))
+                }
+                StreamMessage::Delete { status_id, .. } => {
+                    if config.forwarder.track_sent_messages() {
+                        handle_deleted_tweet(&db, &tg, &ts.snapshot(), status_id as i64).await;
+                    }
+                    None
+                }
+                // 控制消息已经由 stream_supervisor 记录
                 _ => None,
             };
-            if let Some((twitter_user_id, retweet_user_id, tweet_url, msg, media)) = t {
-                let ts_read = ts.read().await;
-                let users = match ts_read.follow_to_twiiter.get(&(twitter_user_id as i64)) {
+            if let Some((
+                (twitter_user_id, retweet_user_id, tweet_url, msg, media),
+                archived,
+                log,
+            )) = t
+            {
+                let snapshot = ts.snapshot();
+                let users = match snapshot.follow_to_twitter.get(&(twitter_user_id as i64)) {
                     Some(users) => users.clone(),
                     None => Vec::new(),
                 };
                 if users.len().eq(&0) {
-                    drop(ts_read);
+                    metrics::TWEETS_FILTERED
+                        .with_label_values(&["no_follower"])
+                        .inc();
                     continue;
                 }
+                // 记录对每个用户的推送决定，供 /WhyNot 查询
+                let mut logs = Vec::new();
+                let decide = |tg_user_id: i64, decision: Decision, detail: String| DeliveryLog {
+                    user_id: tg_user_id,
+                    decision: decision.toi32(),
+                    detail,
+                    ..log.clone()
+                };
+                let history_key = |tg_user_id: i64| {
+                    format!(
+                        "{:x}",
+                        md5::compute(format!("{:?}-{}", tg_user_id, &tweet_url))
+                    )
+                };
                 let mut tg_user_to_send = Vec::new();
                 for tg_user_id in users {
-                    if let Some(u) = ts_read.user_info.get(&tg_user_id) {
+                    if let Some(u) = snapshot.user_info.get(&tg_user_id) {
+                        // 检查用户是否被停用
+                        if u.suspended {
+                            metrics::TWEETS_FILTERED
+                                .with_label_values(&["suspended"])
+                                .inc();
+                            logs.push(decide(tg_user_id, Decision::Suspended, String::new()));
+                            continue;
+                        }
                         // 检查是否禁止推送转发消息
                         if u.disable_retweet && retweet_user_id > 0 {
+                            metrics::TWEETS_FILTERED
+                                .with_label_values(&["disable_retweet"])
+                                .inc();
+                            logs.push(decide(tg_user_id, Decision::DisableRetweet, String::new()));
                             continue;
                         }
                         // 检查是否禁止纯文本消息
                         if u.disable_text_msg && media.is_empty() {
+                            metrics::TWEETS_FILTERED
+                                .with_label_values(&["disable_text_msg"])
+                                .inc();
+                            logs.push(decide(tg_user_id, Decision::DisableTextMsg, String::new()));
                             continue;
                         }
                     }
                     // 检查重复推送记录
-                    let cache_key = format!(
-                        "{:x}",
-                        md5::compute(format!("{:?}-{}", tg_user_id, &tweet_url))
-                    );
-                    if forward_history.get(&cache_key).await.is_some() {
+                    if forward_history.contains(&history_key(tg_user_id)) {
+                        metrics::TWEETS_DEDUPED.inc();
+                        logs.push(decide(tg_user_id, Decision::Duplicate, String::new()));
                         continue;
                     }
-                    forward_history.set(cache_key, (), None).await;
 
                     // 检查直推转推黑名单
-                    if let Some(blacklist) = ts_read.blacklist_map.get(&(tg_user_id as i64)) {
+                    if let Some(blacklist) = snapshot.blacklist_map.get(&tg_user_id) {
                         if retweet_user_id.ne(&0) {
                             // 检查转推的 Author 黑名单
                             if blacklist
@@ -150,6 +802,14 @@ impl TwitterSubscriber {
                                 ))
                                 .is_some()
                             {
+                                metrics::TWEETS_FILTERED
+                                    .with_label_values(&["block_twitter"])
+                                    .inc();
+                                logs.push(decide(
+                                    tg_user_id,
+                                    Decision::BlockTwitter,
+                                    retweet_user_id.to_string(),
+                                ));
                                 continue;
                             }
                             // 检查转推黑名单
@@ -160,6 +820,14 @@ impl TwitterSubscriber {
                                 ))
                                 .is_some()
                             {
+                                metrics::TWEETS_FILTERED
+                                    .with_label_values(&["block_rt"])
+                                    .inc();
+                                logs.push(decide(
+                                    tg_user_id,
+                                    Decision::BlockRT,
+                                    twitter_user_id.to_string(),
+                                ));
                                 continue;
                             }
                         }
@@ -168,356 +836,460 @@ impl TwitterSubscriber {
                     // 添加至通知列表
                     tg_user_to_send.push(tg_user_id);
                 }
-                let tg = ts_read.tg_bot.clone();
-                drop(ts_read);
 
+                let mut delivered = Vec::new();
+                let mut sent_messages = Vec::new();
                 for tg_user_id in tg_user_to_send {
-                    let markup = InlineKeyboardMarkup::new(vec![
-                        get_inline_buttons(tg_user_id, retweet_user_id, &ts, twitter_user_id).await,
-                    ]);
+                    if drain_deadline.is_some_and(|d| Instant::now() >= d) {
+                        warn!("forward_tweet drain timeout, tweet {} not sent", &tweet_url);
+                        break;
+                    }
+                    // 试运行时不推送给用户
+                    if config.forwarder.dry_run
+                        || snapshot
+                            .user_info
+                            .get(&tg_user_id)
+                            .is_some_and(|u| u.dry_run)
+                    {
+                        metrics::TWEETS_FILTERED
+                            .with_label_values(&["dry_run"])
+                            .inc();
+                        let detail = dry_run_send(&tg, &config, tg_user_id, &msg, &media).await;
+                        logs.push(decide(tg_user_id, Decision::DryRun, detail));
+                        continue;
+                    }
+                    let markup = InlineKeyboardMarkup::new(vec![get_inline_buttons(
+                        tg_user_id,
+                        retweet_user_id,
+                        &snapshot,
+                        twitter_user_id,
+                    )]);
+                    let mut media_message_ids = Vec::new();
                     if !media.is_empty() {
                         let media_group_id = tg
-                            .send_media_group(UserId(tg_user_id.clone() as u64), media.clone())
+                            .send_media_group(UserId(tg_user_id as u64), media.clone())
                             .await;
-                        if let Err(e) = media_group_id {
-                            error!("telegram@{} send_media_group {:?}", &tg_user_id, e);
+                        match media_group_id {
+                            Ok(sent) => media_message_ids = sent.iter().map(|m| m.id).collect(),
+                            Err(e) => {
+                                metrics::record_telegram_error(&e);
+                                error!("telegram@{} send_media_group {:?}", &tg_user_id, e);
+                            }
                         }
                     }
                     let res = tg
-                        .send_message(UserId(tg_user_id.clone() as u64), &msg)
+                        .send_message(UserId(tg_user_id as u64), &msg)
                         .disable_web_page_preview(true)
                         .reply_markup(markup.clone())
                         .await;
-                    if res.is_err() {
-                        error!(
-                            "telegram@{} send_message {:?}",
-                            &tg_user_id,
-                            res.err().unwrap()
-                        );
+                    match res {
+                        Ok(sent) => {
+                            metrics::TWEETS_FORWARDED.inc();
+                            // 只记录真正推送成功的，试运行和被过滤的之后仍可推送
+                            forward_history.insert(history_key(tg_user_id));
+                            delivered.push(tg_user_id);
+                            logs.push(decide(tg_user_id, Decision::Delivered, String::new()));
+                            if config.forwarder.track_sent_messages() {
+                                sent_messages.push(SentMessage {
+                                    id: None,
+                                    user_id: tg_user_id,
+                                    tweet_id: log.tweet_id,
+                                    status_id: log.status_id,
+                                    message_id: sent.id,
+                                    media_message_ids: media_message_ids
+                                        .iter()
+                                        .map(|m| m.to_string())
+                                        .collect::<Vec<_>>()
+                                        .join(","),
+                                    text: msg.clone(),
+                                    created_at: log.created_at,
+                                });
+                            }
+                            ts.record_button_message(
+                                tg_user_id,
+                                ButtonMessage {
+                                    message_id: sent.id,
+                                    twitter_user_id,
+                                    retweet_user_id,
+                                },
+                            )
+                            .await;
+                        }
+                        Err(e) => {
+                            metrics::record_telegram_error(&e);
+                            error!("telegram@{} send_message {:?}", &tg_user_id, e);
+                            logs.push(decide(tg_user_id, Decision::SendError, e.to_string()));
+                        }
+                    }
+                }
+
+                // 存档已推送的推文，供 /Search 搜索
+                if !delivered.is_empty() {
+                    let res = db
+                        .run(move |conn| tweet_model::archive_tweet(conn, archived, delivered))
+                        .await;
+                    if let Err(e) = res {
+                        error!("archive tweet {} {:?}", &tweet_url, e);
+                    }
+                }
+                if !logs.is_empty() {
+                    let res = db
+                        .run(move |conn| delivery_log_model::insert_logs(conn, logs))
+                        .await;
+                    if let Err(e) = res {
+                        error!("delivery logs {} {:?}", &tweet_url, e);
+                    }
+                }
+                if !sent_messages.is_empty() {
+                    let res = db
+                        .run(move |conn| sent_message_model::insert_messages(conn, sent_messages))
+                        .await;
+                    if let Err(e) = res {
+                        error!("sent messages {} {:?}", &tweet_url, e);
                     }
                 }
             }
         }
+        forward_history
     }
 
-    pub async fn add_follow(
-        &mut self,
-        f: Follow,
-        from_twitter_user_id: i64,
-    ) -> Result<String, anyhow::Error> {
+    fn add_follow(&mut self, f: Follow, from_twitter_user_id: i64) -> Result<(), anyhow::Error> {
         if self.token_vec.len().eq(&0) {
-            return Err(anyhow::anyhow!("No valid Twitter token"));
+            return Err(anyhow!("No valid Twitter token"));
         }
 
         // 添加到个人订阅列表
-        if let Some(list) = self.follow_map.get_mut(&f.user_id) {
+        let follow_map = Arc::make_mut(&mut self.state.follow_map);
+        if let Some(list) = follow_map.get_mut(&f.user_id) {
             if !list.contains(&f.twitter_user_id) {
                 // 优质内容来源计数
                 if from_twitter_user_id.gt(&0) {
-                    if !self.follow_rt_count_map.contains_key(&f.user_id) {
-                        self.follow_rt_count_map
-                            .insert(f.user_id, HashMap::from([(from_twitter_user_id, 1)]));
-                    } else {
-                        let brc = self.follow_rt_count_map.get_mut(&f.user_id).unwrap();
-                        if !brc.contains_key(&from_twitter_user_id) {
-                            brc.insert(from_twitter_user_id, 1);
-                        } else {
-                            let count = brc.get_mut(&from_twitter_user_id).unwrap();
-                            *count = count.add(1);
-                        }
-                    }
+                    let brc = Arc::make_mut(&mut self.state.follow_rt_count_map)
+                        .entry(f.user_id)
+                        .or_default();
+                    let count = brc.entry(from_twitter_user_id).or_insert(0);
+                    *count = count.add(1);
                 }
                 list.insert(f.twitter_user_id);
             }
         } else {
-            self.follow_map
-                .insert(f.user_id, HashSet::from([f.twitter_user_id]));
+            follow_map.insert(f.user_id, HashSet::from([f.twitter_user_id]));
+        }
+
+        // 记录推送对象
+        let followers = Arc::make_mut(&mut self.state.follow_to_twitter)
+            .entry(f.twitter_user_id)
+            .or_default();
+        if !followers.contains(&f.user_id) {
+            followers.push(f.user_id);
         }
 
         // 检查是否存在于全局订阅列表
         if self
             .twitter_sub_to_token_map
             .contains_key(&f.twitter_user_id)
+            || self.unassigned.contains(&f.twitter_user_id)
         {
-            return Ok("".to_string());
+            return Ok(());
         }
 
         // 添加到全局订阅列表
-        let first = &self.token_map.get(&self.token_vec[0]).unwrap();
-        // 检查第 0 个 follow 的 id 是否是 0，如果是直接插入
-        let mut minimum_follow_token = self.token_vec[0].clone();
-        let mut minimum_follow_count = first.follows.len();
-        if first.follows.len() > 0 {
-            // 将 follow 分配给 token
-            for t in &self.token_vec {
-                let count = self.token_map.get(t).unwrap().follows.len();
-                if count.lt(&minimum_follow_count) {
-                    minimum_follow_count = count;
-                    minimum_follow_token = t.to_string();
-                    if minimum_follow_count == 0 {
-                        break;
-                    }
-                }
+        self.assign_token(f.twitter_user_id);
+        Ok(())
+    }
+
+    // 将 twitter 用户分配给 follow 最少且未满的 token，都满了则放入待分配列表
+    fn assign_token(&mut self, twitter_user_id: i64) -> Option<String> {
+        let cap = self.config.twitter.max_follows_per_token;
+        let minimum_follow_token = self
+            .token_vec
+            .iter()
+            .map(|t| (t, self.token_map.get(t).unwrap().follows.len()))
+            .filter(|(_, len)| *len < cap)
+            .min_by_key(|(_, len)| *len)
+            .map(|(t, _)| t.clone());
+        let minimum_follow_token = match minimum_follow_token {
+            Some(t) => t,
+            None => {
+                self.unassigned.insert(twitter_user_id);
+                return None;
             }
-        }
+        };
+        self.unassigned.remove(&twitter_user_id);
         self.twitter_sub_to_token_map
-            .insert(f.twitter_user_id, minimum_follow_token.clone());
+            .insert(twitter_user_id, minimum_follow_token.clone());
         let minimum = self.token_map.get_mut(&minimum_follow_token).unwrap();
-        minimum.follows.push(f.twitter_user_id as u64);
-        let followers = self.follow_to_twiiter.get_mut(&f.twitter_user_id);
-        if followers.is_none() {
-            self.follow_to_twiiter
-                .insert(f.twitter_user_id, vec![f.user_id]);
-        } else {
-            followers.unwrap().push(f.user_id);
-        }
-        Ok(minimum.token.clone())
+        minimum.follows.push(twitter_user_id as u64);
+        Some(minimum.token.clone())
     }
 
-    pub fn remove_follow(&mut self, user_id: i64, twitter_id: i64) -> String {
-        if !self.follow_to_twiiter.contains_key(&twitter_id) {
-            return "".to_string();
-        }
-
+    fn remove_follow(&mut self, user_id: i64, twitter_id: i64) {
         // 删掉订阅关系
-        self.follow_map
-            .get_mut(&user_id)
-            .unwrap()
-            .remove(&twitter_id);
+        if let Some(list) = Arc::make_mut(&mut self.state.follow_map).get_mut(&user_id) {
+            list.remove(&twitter_id);
+        }
 
         // 从全局订阅记录删掉
-        let users = self.follow_to_twiiter.get_mut(&twitter_id).unwrap();
-        let index = users.iter().position(|f| f.eq(&user_id)).unwrap();
-        users.remove(index);
+        let follow_to_twitter = Arc::make_mut(&mut self.state.follow_to_twitter);
+        let users = match follow_to_twitter.get_mut(&twitter_id) {
+            Some(users) => users,
+            None => return,
+        };
+        users.retain(|u| u.ne(&user_id));
 
         // 如果还有其他人订阅直接退出
         if users.len().gt(&0) {
-            return "".to_string();
+            return;
         };
+        follow_to_twitter.remove(&twitter_id);
+        self.unassigned.remove(&twitter_id);
 
-        let hash = self.twitter_sub_to_token_map.get(&twitter_id).unwrap();
-        let ctx = self.token_map.get_mut(hash).unwrap();
-        let index = ctx
-            .follows
-            .iter()
-            .position(|f| f.eq(&(twitter_id as u64)))
-            .unwrap();
-        ctx.follows.remove(index);
-        self.twitter_sub_to_token_map.remove(&twitter_id);
-        ctx.end_tx.take().unwrap().send(()).unwrap();
-        ctx.token.clone()
+        if let Some(hash) = self.twitter_sub_to_token_map.remove(&twitter_id) {
+            if let Some(ctx) = self.token_map.get_mut(&hash) {
+                ctx.follows.retain(|f| f.ne(&(twitter_id as u64)));
+            }
+            // 空出的位置留给等待中的账号
+            self.assign_unassigned();
+        }
     }
 
-    pub async fn block(
-        &mut self,
-        b: Blacklist,
-        from_twitter_user_id: i64,
-    ) -> Result<(), anyhow::Error> {
-        let list = self.blacklist_map.get_mut(&b.user_id);
-        let item = (b.twitter_user_id, b.type_);
-        if list.is_none() {
-            self.blacklist_map.insert(b.user_id, HashSet::from([item]));
-        } else {
-            if list.as_ref().unwrap().contains(&item) {
-                return Ok(());
+    fn assign_unassigned(&mut self) {
+        let pending: Vec<i64> = self.unassigned.iter().copied().collect();
+        for twitter_id in pending {
+            if self.assign_token(twitter_id).is_none() {
+                break;
             }
-            list.unwrap().insert(item);
         }
+    }
 
-        // 劣质内容屏蔽计数
-        if b.type_.eq(&2) {
-            if !self.block_rt_count_map.contains_key(&b.user_id) {
-                self.block_rt_count_map
-                    .insert(b.user_id, HashMap::from([(from_twitter_user_id, 1)]));
+    // token 增减后重新分配：先安排等待中的账号，再从最多的 token 往最少的移动，直到相差不超过 1
+    fn rebalance(&mut self) {
+        self.assign_unassigned();
+        loop {
+            let loads = self
+                .token_vec
+                .iter()
+                .map(|t| (t.clone(), self.token_map.get(t).unwrap().follows.len()));
+            let (max_hash, max) = match loads.clone().max_by_key(|(_, len)| *len) {
+                Some(m) => m,
+                None => return,
+            };
+            let (min_hash, min) = loads.min_by_key(|(_, len)| *len).unwrap();
+            if max <= min + 1 {
+                return;
+            }
+            let twitter_id = self
+                .token_map
+                .get_mut(&max_hash)
+                .unwrap()
+                .follows
+                .pop()
+                .unwrap();
+            self.token_map
+                .get_mut(&min_hash)
+                .unwrap()
+                .follows
+                .push(twitter_id);
+            self.twitter_sub_to_token_map
+                .insert(twitter_id as i64, min_hash);
+        }
+    }
+
+    // 容量不足时提醒管理员，恢复之前只提醒一次
+    fn check_capacity(&mut self) {
+        if self.unassigned.is_empty() {
+            self.capacity_warned = false;
+            return;
+        }
+        if self.capacity_warned {
+            return;
+        }
+        self.capacity_warned = true;
+        let msg = format!(
+            "Twitter follow capacity exhausted: {} accounts are not subscribed. Each token follows at most {} accounts, please add more Twitter tokens.",
+            self.unassigned.len(),
+            self.config.twitter.max_follows_per_token
+        );
+        warn!("{}", msg);
+        let admin_id = self.config.telegram.admin_id;
+        let tg_bot = self.tg_bot.clone();
+        tokio::spawn(async move {
+            if let Err(e) = tg_bot
+                .send_message(UserId(admin_id as u64), escape(&msg))
+                .await
+            {
+                error!("telegram@{} {:?}", &admin_id, e);
+            }
+        });
+    }
+
+    // twitter 因为 token 的问题断开 stream 时提醒管理员
+    fn notify_disconnected(&self, token: &str, code: u64, reason: &str) {
+        let hash = Self::token_hash(token);
+        let owner = match self.token_map.get(&hash) {
+            Some(ctx) => match self.state.user_info.get(&ctx.user_id) {
+                Some(u) => format!("{} ({})", u.label, u.id),
+                None => ctx.user_id.to_string(),
+            },
+            None => return,
+        };
+        let msg = format!(
+            "Twitter disconnected the stream of {}'s token {}: {} {}. {}",
+            owner,
+            metrics::token_label(&hash),
+            code,
+            reason,
+            if code == DISCONNECT_TOKEN_REVOKED {
+                "The token has been revoked and is removed."
             } else {
-                let brc = self.block_rt_count_map.get_mut(&b.user_id).unwrap();
-                if !brc.contains_key(&from_twitter_user_id) {
-                    brc.insert(from_twitter_user_id, 1);
-                } else {
-                    let count = brc.get_mut(&from_twitter_user_id).unwrap();
-                    *count = count.add(1);
-                }
+                "The token is connected too many times, check whether another instance is running with it."
+            }
+        );
+        warn!("{}", msg);
+        let admin_id = self.config.telegram.admin_id;
+        let tg_bot = self.tg_bot.clone();
+        tokio::spawn(async move {
+            if let Err(e) = tg_bot
+                .send_message(UserId(admin_id as u64), escape(&msg))
+                .await
+            {
+                error!("telegram@{} {:?}", &admin_id, e);
+            }
+        });
+    }
+
+    // 停掉 token 的 stream，并将仍有人订阅的 twitter 转移到其他 token
+    fn drop_token(&mut self, hash: &str) -> Option<TwitterTokenContext> {
+        let ctx = self.token_map.remove(hash)?;
+        self.token_vec.retain(|t| t.ne(hash));
+        self.streams.stop(hash);
+        health::remove_stream(hash);
+        for twitter_id in &ctx.follows {
+            self.twitter_sub_to_token_map.remove(&(*twitter_id as i64));
+            if self.assign_token(*twitter_id as i64).is_none() {
+                warn!("Twitter {} has no token left to subscribe", twitter_id);
             }
         }
+        self.rebalance();
+        Some(ctx)
+    }
 
-        Ok(())
+    fn remove_user(&mut self, user_id: i64) {
+        // 逐个取消该用户的订阅
+        let followed: Vec<i64> = match self.state.follow_map.get(&user_id) {
+            Some(list) => list.iter().copied().collect(),
+            None => Vec::new(),
+        };
+        for twitter_id in followed {
+            self.remove_follow(user_id, twitter_id);
+        }
+
+        // 停掉该用户的 token
+        let hash = self
+            .token_map
+            .iter()
+            .find(|(_, ctx)| ctx.user_id.eq(&user_id))
+            .map(|(hash, _)| hash.clone());
+        if let Some(hash) = hash {
+            self.drop_token(&hash);
+        }
+
+        Arc::make_mut(&mut self.state.follow_map).remove(&user_id);
+        Arc::make_mut(&mut self.state.blacklist_map).remove(&user_id);
+        Arc::make_mut(&mut self.state.block_rt_count_map).remove(&user_id);
+        Arc::make_mut(&mut self.state.follow_rt_count_map).remove(&user_id);
+        Arc::make_mut(&mut self.state.user_info).remove(&user_id);
+        self.button_messages.remove(&user_id);
     }
 
-    pub async fn unblock(&mut self, user_id: i64, twitter_id: i64, x_type: i32) {
-        let list = self.blacklist_map.get_mut(&user_id);
-        if let Some(list) = list {
-            list.remove(&(twitter_id, x_type));
+    fn block(&mut self, b: Blacklist, from_twitter_user_id: i64) {
+        let list = Arc::make_mut(&mut self.state.blacklist_map)
+            .entry(b.user_id)
+            .or_default();
+        if !list.insert((b.twitter_user_id, b.type_)) {
+            return;
+        }
+
+        // 劣质内容屏蔽计数
+        if b.type_.eq(&2) {
+            let brc = Arc::make_mut(&mut self.state.block_rt_count_map)
+                .entry(b.user_id)
+                .or_default();
+            let count = brc.entry(from_twitter_user_id).or_insert(0);
+            *count = count.add(1);
         }
     }
 
-    pub async fn add_token(&mut self, user_id: i64, token: &str) -> Result<(), anyhow::Error> {
-        let hash = Self::token_hash(token);
+    fn add_token(&mut self, user_id: i64, token: String) {
+        let hash = Self::token_hash(&token);
         if self.token_map.contains_key(&hash) {
-            warn!("Token has been added {}", token);
-            return Ok(());
-        }
-        if !Self::check_token_valid(token).await? {
-            return Err(anyhow::anyhow!("Twitter authorization has expired"));
+            warn!(
+                "Twitter token {:?} of user {} has been added",
+                hash, user_id
+            );
+            return;
         }
         self.token_vec.insert(0, hash.clone());
         self.token_map.insert(
-            hash.clone(),
+            hash,
             TwitterTokenContext {
                 user_id,
                 follows: Vec::new(),
-                end_tx: None,
-                token: token.to_string(),
+                token,
             },
         );
-        Ok(())
     }
 
-    pub async fn remove_token(
-        ts: Arc<RwLock<TwitterSubscriber>>,
-        token: &str,
-    ) -> Result<(), anyhow::Error> {
-        let mut ts_writer = ts.write().await;
-        let tg_bot = ts_writer.tg_bot.clone();
-        let hash = Self::token_hash(&token);
-        let ctx = ts_writer.token_map.get_mut(&hash).unwrap();
-        // 停掉 token 订阅
-        if let Some(ch) = ctx.end_tx.as_ref() {
-            drop(ch);
-        }
-        // 逐个将用户订阅的 twitter 暂停
-        let follows = ctx.follows.clone();
+    // token 已失效，停掉订阅并通知用户
+    fn remove_token(&mut self, token: &str) {
+        let hash = Self::token_hash(token);
+        let ctx = match self.drop_token(&hash) {
+            Some(ctx) => ctx,
+            None => return,
+        };
         let user_id = ctx.user_id;
-        drop(ctx);
-        let mut tokens_need_to_react = HashSet::new();
-        for f in follows {
-            let mut ts_writer = ts.write().await;
-            let using_token = ts_writer.remove_follow(user_id, f as i64);
-            drop(ts_writer);
-            if using_token.ne("") && using_token.ne(token) {
-                tokens_need_to_react.insert(using_token);
-            }
-        }
-        // Reorganizing token subscription relationships
-        let ts_read = ts.read().await;
-        let subscribe_tx = ts_read.subscribe_tx.clone();
-        drop(ts_read);
-        for t in tokens_need_to_react {
-            let _ = subscribe_tx.send(t).await;
-        }
-        // 给用户一个通知
-        let res = tg_bot
-            .send_message(
-                UserId(user_id as u64),
-                escape(
-                    "Your Twitter authorization has expired, you will not receive future messages.",
-                ),
-            )
-            .await;
-        if res.is_err() {
-            error!("telegram@{} {:?}", &user_id, res.err().unwrap());
-        }
-        Ok(())
-    }
-
-    pub async fn subscribe(
-        ts: Arc<RwLock<TwitterSubscriber>>,
-        token: String,
-    ) -> Result<(), anyhow::Error> {
-        let t: egg_mode::Token = serde_json::from_str(&token)?;
-        let hash = Self::token_hash(&token);
+        let tg_bot = self.tg_bot.clone();
         tokio::spawn(async move {
-            loop {
-                info!("Twitter token {:?} subscribe get writer", hash);
-                let mut ts_writer = ts.write().await;
-                info!("Twitter token {:?} subscribe geted writer", hash);
-                let ctx = ts_writer.token_map.get_mut(&hash).unwrap();
-                // 停掉之前的 follow 线程
-                if let Some(ch) = ctx.end_tx.as_ref() {
-                    drop(ch);
-                }
-                let follows = ctx.follows.clone();
-                // 如果此 Token 下没有分配的 follow 了，直接退出
-                if follows.is_empty() {
-                    info!("Twitter token {:?} no follows exit", hash);
-                    return;
-                }
-                let (tx, rx) = tokio::sync::oneshot::channel::<()>();
-                ctx.end_tx = Some(tx);
-                drop(ts_writer);
-                info!("Twitter {:?} subscribe", &follows);
-                let mut stream = egg_mode::stream::filter()
-                    .follow(follows.as_slice())
-                    .start(&t);
-                let mut rx_fuse = rx.fuse();
-                loop {
-                    tokio::select! {
-                       res = stream.try_next() => {
-                            match res {
-                                Ok(m) => {
-                                    if let Some(m) = m {
-                                        let ts_read = ts.read().await;
-                                        ts_read.tweet_tx.send(m).await.unwrap();
-                                    }
-                                    continue;
-                                },
-                                Err(e)=>{
-                                    // twitter 的 stream 出错退出，先打印错误信息
-                                    warn!("Twitter {:?} subscribe error {:?}", &follows, e);
-                                    // 再检查一下 token 有效性，如果确认无效，走删除 token 流程
-                                    let res = Self::check_token_valid(&token).await;
-                                    if res.is_err() || !res.unwrap() {
-                                        let res = Self::remove_token(ts.clone(), &token).await;
-                                        if let Err(e) = res {
-                                            error!("Twitter {:?} remove token error {:?}", &follows, e);
-                                        }
-                                        return;
-                                    }
-                                    tokio::time::sleep(tokio::time::Duration::from_secs(3)).await;
-                                    break;
-                                }
-                            };
-                        },
-                        _ = &mut rx_fuse => {
-                            error!("Twitter {:?} subscribe active exit", &follows);
-                            return;
-                       },
-                    };
-                }
+            let res = tg_bot
+                .send_message(
+                    UserId(user_id as u64),
+                    escape(
+                        "Your Twitter authorization has expired, you will not receive future messages.",
+                    ),
+                )
+                .await;
+            if let Err(e) = res {
+                error!("telegram@{} {:?}", &user_id, e);
             }
         });
-        Ok(())
     }
 }
 
-async fn get_inline_buttons(
+fn get_inline_buttons(
     tg_user_id: i64,
     retweet_user_id: u64,
-    ts: &Arc<RwLock<TwitterSubscriber>>,
+    snapshot: &SubscriberSnapshot,
     twitter_user_id: u64,
 ) -> Vec<InlineKeyboardButton> {
-    let ts_read = ts.read().await;
-    let follow_count = match ts_read.follow_rt_count_map.get(&tg_user_id) {
-        Some(m) => m.get(&(twitter_user_id as i64)).unwrap_or(&0).clone(),
+    let follow_count = match snapshot.follow_rt_count_map.get(&tg_user_id) {
+        Some(m) => *m.get(&(twitter_user_id as i64)).unwrap_or(&0),
         None => 0,
     };
-    let block_count = match ts_read.block_rt_count_map.get(&tg_user_id) {
-        Some(m) => m.get(&(twitter_user_id as i64)).unwrap_or(&0).clone(),
+    let block_count = match snapshot.block_rt_count_map.get(&tg_user_id) {
+        Some(m) => *m.get(&(twitter_user_id as i64)).unwrap_or(&0),
         None => 0,
     };
-    drop(ts_read);
 
     let mut inline_buttons = Vec::new();
     if retweet_user_id > 0 {
-        let ts_read = ts.read().await;
         inline_buttons.push(InlineKeyboardButton::callback(
             "🚫RTer".to_string(),
             format!("/BlockTwitterID 2 {} {}", retweet_user_id, twitter_user_id),
         ));
-        if !ts_read
+        if !snapshot
             .follow_map
             .get(&tg_user_id)
-            .unwrap()
-            .contains(&(retweet_user_id as i64))
+            .is_some_and(|list| list.contains(&(retweet_user_id as i64)))
         {
             inline_buttons.push(InlineKeyboardButton::callback(
                 format!("👀RTer({})", follow_count),
@@ -546,7 +1318,107 @@ async fn get_inline_buttons(
     inline_buttons
 }
 
-fn format_tweet(t: egg_mode::tweet::Tweet) -> Option<(u64, u64, String, String, Vec<InputMedia>)> {
+// 推文被删除后，按用户设置删除已推送的消息，或在消息前标记已删除
+async fn handle_deleted_tweet(
+    db: &Repository,
+    tg: &AutoSend<DefaultParseMode<Bot>>,
+    snapshot: &SubscriberSnapshot,
+    status_id: i64,
+) {
+    let sent = match db
+        .run(move |conn| sent_message_model::take_messages_by_status(conn, status_id))
+        .await
+    {
+        Ok(sent) => sent,
+        Err(e) => {
+            error!("sent messages of deleted tweet {} {:?}", status_id, e);
+            return;
+        }
+    };
+    for m in sent {
+        let chat = UserId(m.user_id as u64);
+        let remove = snapshot
+            .user_info
+            .get(&m.user_id)
+            .is_some_and(|u| u.remove_deleted);
+        if remove {
+            let mut removed = true;
+            for message_id in m.media_message_ids().into_iter().chain([m.message_id]) {
+                if let Err(e) = tg.delete_message(chat, message_id).await {
+                    warn!(
+                        "telegram@{} delete_message {} {:?}",
+                        m.user_id, message_id, e
+                    );
+                    removed = false;
+                }
+            }
+            if removed {
+                metrics::TWEETS_DELETED
+                    .with_label_values(&["removed"])
+                    .inc();
+                continue;
+            }
+            // 超过 48 小时的消息无法删除，改为标记
+        }
+        let res = tg
+            .edit_message_text(
+                chat,
+                m.message_id,
+                format!("{}\n{}", escape("🗑 deleted"), m.text),
+            )
+            .disable_web_page_preview(true)
+            .await;
+        match res {
+            Ok(_) => metrics::TWEETS_DELETED.with_label_values(&["marked"]).inc(),
+            Err(e) => {
+                metrics::record_telegram_error(&e);
+                warn!(
+                    "telegram@{} edit_message_text {} {:?}",
+                    m.user_id, m.message_id, e
+                );
+            }
+        }
+    }
+}
+
+// 试运行的消息写日志或加上前缀发给管理员，不带按钮，返回记录到推送记录的说明
+async fn dry_run_send(
+    tg: &AutoSend<DefaultParseMode<Bot>>,
+    config: &Config,
+    tg_user_id: i64,
+    msg: &str,
+    media: &[InputMedia],
+) -> String {
+    let prefix = format!("[dry-run for user {}]", tg_user_id);
+    if !config.forwarder.dry_run_to_admin {
+        info!("{} {} media {}", prefix, msg, media.len());
+        return "logged".to_string();
+    }
+    let admin = UserId(config.telegram.admin_id as u64);
+    if !media.is_empty() {
+        if let Err(e) = tg.send_media_group(admin, media.to_vec()).await {
+            metrics::record_telegram_error(&e);
+            error!("{} send_media_group {:?}", prefix, e);
+        }
+    }
+    let res = tg
+        .send_message(admin, format!("{}\n{}", escape(&prefix), msg))
+        .disable_web_page_preview(true)
+        .await;
+    match res {
+        Ok(_) => "sent to admin".to_string(),
+        Err(e) => {
+            metrics::record_telegram_error(&e);
+            error!("{} send_message {:?}", prefix, e);
+            format!("sending to admin failed: {}", e)
+        }
+    }
+}
+
+fn format_tweet(
+    t: egg_mode::tweet::Tweet,
+    max_tweet_age: chrono::Duration,
+) -> Option<(u64, u64, String, String, Vec<InputMedia>)> {
     let user = t.user.as_ref().unwrap();
     let mut caption = user.screen_name.clone();
     let mut retweet_user_id = 0;
@@ -564,13 +1436,19 @@ fn format_tweet(t: egg_mode::tweet::Tweet) -> Option<(u64, u64, String, String,
         }
     }
 
-    // 忽略三天前的 tweet
-    if real_created_at < chrono::Utc::now() - chrono::Duration::days(3) {
+    // 忽略过旧的 tweet（默认三天前）
+    if real_created_at < chrono::Utc::now() - max_tweet_age {
+        metrics::TWEETS_FILTERED
+            .with_label_values(&["too_old"])
+            .inc();
         return None;
     }
 
     // 忽略自己转发自己的推文
     if user.id.eq(&retweet_user_id) {
+        metrics::TWEETS_FILTERED
+            .with_label_values(&["self_retweet"])
+            .inc();
         return None;
     };
 
@@ -605,6 +1483,55 @@ fn format_tweet(t: egg_mode::tweet::Tweet) -> Option<(u64, u64, String, String,
     ))
 }
 
+// 转推存档原推文
+fn archived_tweet(t: &egg_mode::tweet::Tweet) -> tweet_model::Tweet {
+    let t = match &t.retweeted_status {
+        Some(rt) if rt.user.is_some() => rt,
+        _ => t,
+    };
+    let user = t.user.as_ref().unwrap();
+    let media_urls = match &t.extended_entities {
+        Some(ext) => &ext.media,
+        None => t.entities.media.as_ref().map_or(&[][..], |m| &m[..]),
+    }
+    .iter()
+    .map(|m| {
+        get_max_video_bitrate(m)
+            .1
+            .unwrap_or_else(|| m.media_url_https.clone())
+    })
+    .collect::<Vec<_>>();
+    tweet_model::Tweet {
+        id: t.id as i64,
+        twitter_user_id: user.id as i64,
+        screen_name: user.screen_name.clone(),
+        text: t.text.clone(),
+        media_urls: media_urls.join("\n"),
+        url: format!(
+            "https://twitter.com/{}/status/{:?}",
+            &user.screen_name, t.id
+        ),
+        created_at: t.created_at.naive_utc(),
+    }
+}
+
+// 推送记录模板，user_id 和 decision 在推送时填写
+fn delivery_log(t: &egg_mode::tweet::Tweet, archived: &tweet_model::Tweet) -> DeliveryLog {
+    DeliveryLog {
+        id: None,
+        user_id: 0,
+        tweet_id: archived.id,
+        status_id: t.id as i64,
+        screen_name: t
+            .user
+            .as_ref()
+            .map_or_else(String::new, |u| u.screen_name.clone()),
+        decision: 0,
+        detail: String::new(),
+        created_at: chrono::Utc::now().naive_utc(),
+    }
+}
+
 fn get_media_from_media_entity(
     m: &egg_mode::entities::MediaEntity,
     caption: &str,
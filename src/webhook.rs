@@ -0,0 +1,149 @@
+use std::{convert::Infallible, fmt::Debug, fs::File, io::BufReader, sync::Arc, time::Duration};
+
+use anyhow::anyhow;
+use futures::Future;
+use log::{error, info, warn};
+use teloxide::{
+    dispatching::update_listeners::{webhooks, UpdateListener},
+    requests::Requester,
+    types::InputFile,
+};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+    task::JoinHandle,
+};
+use tokio_rustls::{rustls, server::TlsStream, TlsAcceptor};
+
+use crate::config::WebhookConfig;
+
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// 启动接收更新的 HTTP(S) 服务并调用 setWebhook，监听停止后服务退出时会调用 deleteWebhook
+pub async fn listener<R>(
+    bot: R,
+    config: &WebhookConfig,
+) -> Result<(impl UpdateListener<Infallible>, JoinHandle<()>), anyhow::Error>
+where
+    R: Requester + Send + 'static,
+    <R as Requester>::DeleteWebhook: Send,
+    R::Err: Debug,
+{
+    let listen = config
+        .listen_addr()
+        .ok_or_else(|| anyhow!("invalid webhook listen address {:?}", config.listen))?;
+    let url = url::Url::parse(&config.url)?;
+    let tls = if config.tls_enabled() {
+        Some(tls_acceptor(&config.tls_cert, &config.tls_key)?)
+    } else {
+        None
+    };
+    // 先绑定端口，避免 setWebhook 之后才发现端口不可用
+    let tcp = std::net::TcpListener::bind(listen)?;
+    tcp.set_nonblocking(true)?;
+
+    let mut options = webhooks::Options::new(listen, url);
+    if !config.secret.is_empty() {
+        options = options.secret_token(config.secret.clone());
+    }
+    if config.self_signed {
+        options = options.certificate(InputFile::file(&config.tls_cert));
+    }
+    let (listener, stop, app) = webhooks::axum_to_router(bot, options)
+        .await
+        .map_err(|e| anyhow!("setWebhook {:?}", e))?;
+
+    info!(
+        "Tg webhook listening on {} ({})",
+        listen,
+        if tls.is_some() { "https" } else { "http" }
+    );
+    let server = tokio::spawn(async move {
+        if let Err(e) = serve(app, tcp, tls, stop).await {
+            error!("Tg webhook server {:?}", e);
+        }
+    });
+    Ok((listener, server))
+}
+
+async fn serve(
+    app: axum::Router,
+    tcp: std::net::TcpListener,
+    tls: Option<TlsAcceptor>,
+    stop: impl Future<Output = ()>,
+) -> Result<(), anyhow::Error> {
+    match tls {
+        None => {
+            axum::Server::from_tcp(tcp)?
+                .serve(app.into_make_service())
+                .with_graceful_shutdown(stop)
+                .await?
+        }
+        Some(acceptor) => {
+            let (tx, rx) = mpsc::channel(64);
+            tokio::spawn(accept_tls(TcpListener::from_std(tcp)?, acceptor, tx));
+            let incoming = futures::stream::unfold(rx, |mut rx| async move {
+                rx.recv().await.map(|s| (Ok::<_, std::io::Error>(s), rx))
+            });
+            axum::Server::builder(hyper::server::accept::from_stream(incoming))
+                .serve(app.into_make_service())
+                .with_graceful_shutdown(stop)
+                .await?
+        }
+    }
+    Ok(())
+}
+
+// 每个连接单独握手，避免慢连接阻塞其他连接
+async fn accept_tls(
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+    tx: mpsc::Sender<TlsStream<TcpStream>>,
+) {
+    loop {
+        let (stream, addr) = tokio::select! {
+            res = listener.accept() => match res {
+                Ok(conn) => conn,
+                Err(e) => {
+                    warn!("Tg webhook accept {:?}", e);
+                    continue;
+                }
+            },
+            // 服务已经退出
+            _ = tx.closed() => return,
+        };
+        let acceptor = acceptor.clone();
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            match tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                Ok(Ok(s)) => {
+                    let _ = tx.send(s).await;
+                }
+                Ok(Err(e)) => warn!("Tg webhook tls handshake {} {:?}", addr, e),
+                Err(_) => warn!("Tg webhook tls handshake {} timeout", addr),
+            }
+        });
+    }
+}
+
+fn tls_acceptor(cert_path: &str, key_path: &str) -> Result<TlsAcceptor, anyhow::Error> {
+    let certs = rustls_pemfile::certs(&mut BufReader::new(File::open(cert_path)?))?
+        .into_iter()
+        .map(rustls::Certificate)
+        .collect();
+    let mut reader = BufReader::new(File::open(key_path)?);
+    let key = loop {
+        match rustls_pemfile::read_one(&mut reader)? {
+            Some(rustls_pemfile::Item::RSAKey(k))
+            | Some(rustls_pemfile::Item::PKCS8Key(k))
+            | Some(rustls_pemfile::Item::ECKey(k)) => break rustls::PrivateKey(k),
+            Some(_) => continue,
+            None => return Err(anyhow!("no private key found in {}", key_path)),
+        }
+    };
+    let config = rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
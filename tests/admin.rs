@@ -0,0 +1,345 @@
+mod common;
+
+use common::{seed_follow, seed_user, TestBot, ADMIN_ID};
+use twitter2telegram::models::{
+    follow_model,
+    invite_model::{self, Invite},
+    role_model::{self, Role, UserRole},
+    user_model, DbConnection, DbError,
+};
+
+fn seed_role(conn: &DbConnection, user_id: i64, role: Role) {
+    role_model::grant_role(
+        conn,
+        UserRole {
+            user_id,
+            role: role.toi32(),
+            created_at: chrono::Utc::now().naive_utc(),
+        },
+    )
+    .unwrap();
+}
+
+// 发送命令并返回 bot 的下一条回复
+async fn reply(bot: &TestBot, from: i64, text: &str) -> String {
+    let count = bot.telegram.messages_to(from).len();
+    bot.telegram.send_text(from, text);
+    let messages = bot.telegram.wait_for_messages(from, count + 1).await;
+    messages[count].text().to_string()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn admin_adds_lists_and_removes_users() {
+    let bot = TestBot::start(|conn| {
+        seed_user(conn, 2);
+        seed_follow(conn, 2, 100);
+    })
+    .await;
+
+    let text = reply(&bot, 5, "/ListFollowedTwitterID").await;
+    assert!(text.contains("not authorized"), "{}", text);
+    let text = reply(&bot, ADMIN_ID, "/AddUser 5 bob").await;
+    assert_eq!(text, "*bob* _5_ Add Success, affecting 1 Records");
+    let text = reply(&bot, ADMIN_ID, "/AddUser 5 again").await;
+    assert!(!text.contains("Success"), "{}", text);
+    // 新用户立即可用
+    let text = reply(&bot, 5, "/ListFollowedTwitterID").await;
+    assert!(!text.contains("not authorized"), "{}", text);
+
+    reply(&bot, ADMIN_ID, "/SetUserQuota 2 3").await;
+    reply(&bot, ADMIN_ID, "/SuspendUser 2").await;
+    let text = reply(&bot, ADMIN_ID, "/ListUsers").await;
+    assert!(text.starts_with("There are 2 users\\."), "{}", text);
+    assert!(
+        text.contains("\\* *u2* _2_ token: ✅ follows: 1/3 \\(suspended\\)\n"),
+        "{}",
+        text
+    );
+    assert!(
+        text.contains("\\* *bob* _5_ token: ❌ follows: 0\n"),
+        "{}",
+        text
+    );
+
+    // 删除用户同时删除关注
+    let text = reply(&bot, ADMIN_ID, "/RemoveUser 2").await;
+    assert!(text.starts_with("_2_ Remove Success"), "{}", text);
+    assert!(user_model::get_user_by_id(&bot.conn(), 2).is_err());
+    assert!(follow_model::get_all_follows(&bot.conn())
+        .unwrap()
+        .is_empty());
+    let text = reply(&bot, 2, "/ListFollowedTwitterID").await;
+    assert!(text.contains("not authorized"), "{}", text);
+
+    bot.stop().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn admins_only_manage_lower_roles() {
+    let bot = TestBot::start(|conn| {
+        for id in [2, 3, 4] {
+            seed_user(conn, id);
+        }
+        seed_role(conn, 2, Role::Admin);
+        seed_role(conn, 3, Role::Admin);
+    })
+    .await;
+
+    // 普通用户不能执行管理命令
+    let text = reply(&bot, 4, "/SuspendUser 3").await;
+    assert!(text.contains("*Admin* role required"), "{}", text);
+
+    // 管理员不能管理同级的管理员和配置的所有者
+    for command in [
+        "/SuspendUser 3",
+        "/SetUserLabel 3 x",
+        "/SetUserQuota 3 1",
+        "/SetDryRun 3 true",
+        "/RemoveUser 3",
+        &format!("/SuspendUser {}", ADMIN_ID),
+    ] {
+        let text = reply(&bot, 2, command).await;
+        assert!(
+            text.starts_with("Permission denied"),
+            "{}: {}",
+            command,
+            text
+        );
+    }
+    let admin = user_model::get_user_by_id(&bot.conn(), 3).unwrap();
+    assert!(!admin.suspended && admin.label == "u3" && !admin.dry_run);
+
+    let text = reply(&bot, 2, "/SetUserQuota 4 5").await;
+    assert!(text.contains("Success"), "{}", text);
+    assert_eq!(
+        user_model::get_user_by_id(&bot.conn(), 4)
+            .unwrap()
+            .follow_quota,
+        5
+    );
+
+    // 只能授予低于自己的角色
+    let text = reply(&bot, ADMIN_ID, "/GrantRole 4 4").await;
+    assert!(text.starts_with("Permission denied"), "{}", text);
+    assert!(role_model::get_role_by_user_id(&bot.conn(), 4)
+        .unwrap()
+        .is_none());
+    let text = reply(&bot, ADMIN_ID, "/GrantRole 4 1").await;
+    assert!(text.contains("Success"), "{}", text);
+
+    // 所有者不受限制
+    let text = reply(&bot, ADMIN_ID, "/SetUserLabel 3 ops").await;
+    assert!(text.contains("Success"), "{}", text);
+    assert_eq!(
+        user_model::get_user_by_id(&bot.conn(), 3).unwrap().label,
+        "ops"
+    );
+
+    bot.stop().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn suspended_admin_is_rejected() {
+    let bot = TestBot::start(|conn| {
+        seed_user(conn, 2);
+        seed_user(conn, 3);
+        seed_role(conn, 2, Role::Admin);
+    })
+    .await;
+
+    let text = reply(&bot, ADMIN_ID, "/SuspendUser 2").await;
+    assert!(text.contains("Success"), "{}", text);
+
+    for command in ["/SuspendUser 3", "/ListUsers", "/ListFollowedTwitterID"] {
+        let text = reply(&bot, 2, command).await;
+        assert!(text.contains("suspended"), "{}: {}", command, text);
+    }
+    assert!(
+        !user_model::get_user_by_id(&bot.conn(), 3)
+            .unwrap()
+            .suspended
+    );
+
+    let text = reply(&bot, ADMIN_ID, "/ResumeUser 2").await;
+    assert!(text.contains("Success"), "{}", text);
+    let text = reply(&bot, 2, "/SuspendUser 3").await;
+    assert!(text.contains("Success"), "{}", text);
+    assert!(
+        user_model::get_user_by_id(&bot.conn(), 3)
+            .unwrap()
+            .suspended
+    );
+
+    bot.stop().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn invite_codes_are_redeemed_until_used_up_or_expired() {
+    let bot = TestBot::start(|conn| {
+        let now = chrono::Utc::now().naive_utc();
+        invite_model::create_invite(
+            conn,
+            Invite {
+                code: "expired".to_string(),
+                label: "old".to_string(),
+                max_uses: 5,
+                used_count: 0,
+                expires_at: now - chrono::Duration::hours(1),
+                created_by: ADMIN_ID,
+                created_at: now - chrono::Duration::hours(2),
+            },
+        )
+        .unwrap();
+    })
+    .await;
+
+    let text = reply(&bot, ADMIN_ID, "/RevokeInvite expired").await;
+    assert!(text.contains("affecting 1 records"), "{}", text);
+    let text = reply(&bot, ADMIN_ID, "/ListInvites").await;
+    assert_eq!(text, "No invite codes");
+
+    let text = reply(&bot, ADMIN_ID, "/CreateInvite 1 24 team").await;
+    let code = text.rsplit("start\\=").next().unwrap().to_string();
+    assert_eq!(code.len(), 16, "{}", text);
+
+    let text = reply(&bot, 5, &format!("/start {}", code)).await;
+    assert!(text.starts_with("Welcome *team*"), "{}", text);
+    assert_eq!(
+        user_model::get_user_by_id(&bot.conn(), 5).unwrap().label,
+        "team"
+    );
+
+    // 次数用完
+    let text = reply(&bot, 6, &format!("/start {}", code)).await;
+    assert_eq!(text, "Invite code is invalid or expired");
+    assert!(matches!(
+        user_model::get_user_by_id(&bot.conn(), 6),
+        Err(DbError::NotFound)
+    ));
+    let text = reply(&bot, ADMIN_ID, "/ListInvites").await;
+    assert!(text.contains(&format!("`{}` *team* 1/1", code)), "{}", text);
+
+    // 过期
+    let now = chrono::Utc::now().naive_utc();
+    invite_model::create_invite(
+        &bot.conn(),
+        Invite {
+            code: "late".to_string(),
+            label: "late".to_string(),
+            max_uses: 5,
+            used_count: 0,
+            expires_at: now - chrono::Duration::minutes(1),
+            created_by: ADMIN_ID,
+            created_at: now - chrono::Duration::hours(1),
+        },
+    )
+    .unwrap();
+    let text = reply(&bot, 7, "/start late").await;
+    assert_eq!(text, "Invite code is invalid or expired");
+    assert!(user_model::get_user_by_id(&bot.conn(), 7).is_err());
+    let text = reply(&bot, ADMIN_ID, "/ListInvites").await;
+    assert!(text.contains("`late` *late* 0/5 expired"), "{}", text);
+
+    bot.stop().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn broadcast_is_sent_after_confirmation() {
+    let bot = TestBot::start_with(
+        |conn| {
+            seed_user(conn, 2);
+            seed_user(conn, 3);
+        },
+        |config| config.telegram.broadcast_ttl_secs = 2,
+    )
+    .await;
+
+    bot.telegram
+        .send_text(ADMIN_ID, "/Broadcast hello everyone");
+    let messages = bot.telegram.wait_for_messages(ADMIN_ID, 2).await;
+    assert_eq!(messages[0].text(), "hello everyone");
+    assert_eq!(
+        messages[1].text(),
+        "Broadcast the message above to 2 users?"
+    );
+    let (_, confirm) = messages[1].keyboard()[0][0].clone();
+    assert!(confirm.starts_with("/ConfirmBroadcast "), "{}", confirm);
+    // 确认前不发送
+    assert!(bot.telegram.messages_to(2).is_empty());
+
+    bot.telegram.press_button(ADMIN_ID, &messages[1], &confirm);
+    let messages = bot.telegram.wait_for_messages(ADMIN_ID, 4).await;
+    assert_eq!(messages[2].text(), "Broadcasting to 2 users\\.\\.\\.");
+    assert!(messages[3]
+        .text()
+        .starts_with("Broadcast finished, delivered 2"));
+    for id in [2, 3] {
+        assert_eq!(bot.telegram.messages_to(id)[0].text(), "hello everyone");
+    }
+
+    // 只能确认一次
+    bot.telegram.press_button(ADMIN_ID, &messages[1], &confirm);
+    let messages = bot.telegram.wait_for_messages(ADMIN_ID, 5).await;
+    assert_eq!(messages[4].text(), "Broadcast expired or already sent");
+
+    // 预览过期后不能再确认
+    bot.telegram.send_text(ADMIN_ID, "/Broadcast too late");
+    let messages = bot.telegram.wait_for_messages(ADMIN_ID, 7).await;
+    let (_, confirm) = messages[6].keyboard()[0][0].clone();
+    tokio::time::sleep(std::time::Duration::from_millis(2500)).await;
+    bot.telegram.press_button(ADMIN_ID, &messages[6], &confirm);
+    let messages = bot.telegram.wait_for_messages(ADMIN_ID, 8).await;
+    assert_eq!(messages[7].text(), "Broadcast expired or already sent");
+    assert_eq!(bot.telegram.messages_to(2).len(), 1);
+
+    bot.stop().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn concurrent_confirms_send_broadcast_once() {
+    let bot = TestBot::start(|conn| {
+        seed_user(conn, 2);
+        seed_user(conn, 3);
+        seed_role(conn, 3, Role::Admin);
+    })
+    .await;
+
+    bot.telegram.send_text(ADMIN_ID, "/Broadcast once");
+    let messages = bot.telegram.wait_for_messages(ADMIN_ID, 2).await;
+    let (_, confirm) = messages[1].keyboard()[0][0].clone();
+
+    // 两个管理员同时确认
+    bot.telegram.press_button(ADMIN_ID, &messages[1], &confirm);
+    bot.telegram.press_button(3, &messages[1], &confirm);
+    common::wait_until("both confirms answered", || {
+        bot.telegram
+            .requests()
+            .iter()
+            .filter(|r| {
+                r.method == "sendMessage"
+                    && (r.text().starts_with("Broadcasting")
+                        || r.text() == "Broadcast expired or already sent")
+            })
+            .count()
+            == 2
+    })
+    .await;
+    common::wait_until("broadcast finished", || {
+        bot.telegram
+            .requests()
+            .iter()
+            .any(|r| r.text().starts_with("Broadcast finished"))
+    })
+    .await;
+    let received = |id: i64| {
+        bot.telegram
+            .messages_to(id)
+            .iter()
+            .filter(|m| m.text() == "once")
+            .count()
+    };
+    assert_eq!(received(2), 1);
+    assert_eq!(received(3), 1);
+
+    bot.stop().await;
+}
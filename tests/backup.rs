@@ -0,0 +1,118 @@
+#![cfg(not(feature = "postgres"))]
+
+mod common;
+
+use std::{fs, path::PathBuf};
+
+use diesel::{Connection, RunQueryDsl};
+use twitter2telegram::{
+    backup,
+    config::DatabaseConfig,
+    models::{run_migrations, user_model, DbConnection},
+};
+
+use common::{seed_user, TestBot, ADMIN_ID};
+
+fn backup_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("t2t-backup-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn backup_command_sends_snapshot_and_restore_replaces_database() {
+    let dir = backup_dir("command");
+    let bot = TestBot::start_with(
+        |conn| seed_user(conn, 1),
+        |config| {
+            config.backup.dir = dir.to_str().unwrap().to_string();
+            config.backup.keep = 2;
+        },
+    )
+    .await;
+
+    for count in 1..=3 {
+        bot.telegram.send_text(ADMIN_ID, "/Backup");
+        common::wait_until("backup document", || {
+            bot.telegram
+                .requests()
+                .iter()
+                .filter(|r| r.method == "sendDocument")
+                .count()
+                == count
+        })
+        .await;
+        // 备份文件名精确到秒
+        tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+    }
+    let document = bot
+        .telegram
+        .requests()
+        .into_iter()
+        .rfind(|r| r.method == "sendDocument")
+        .unwrap();
+    assert_eq!(document.chat_id(), Some(ADMIN_ID));
+    let file_name = document.params["document"]["file_name"].as_str().unwrap();
+    assert!(file_name.starts_with("t2t-") && file_name.ends_with(".db.gz"));
+    // 只保留最近的两个备份
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+
+    // 发送的备份恢复到一个新的数据库
+    let downloaded = dir.join("downloaded.db.gz");
+    fs::write(
+        &downloaded,
+        base64::decode(document.params["document"]["content"].as_str().unwrap()).unwrap(),
+    )
+    .unwrap();
+    bot.stop().await;
+
+    let target = common::test_database(&format!("t2t-restore-{}", std::process::id()));
+    {
+        let conn = DbConnection::establish(&target).unwrap();
+        run_migrations(&conn).unwrap();
+        seed_user(&conn, 2);
+    }
+    let config = DatabaseConfig {
+        url: target.clone(),
+        ..DatabaseConfig::default()
+    };
+    let old = backup::restore(&config, &downloaded).unwrap().unwrap();
+    let conn = DbConnection::establish(&target).unwrap();
+    let ids: Vec<i64> = user_model::get_all_users(&conn)
+        .unwrap()
+        .iter()
+        .map(|u| u.id)
+        .collect();
+    assert_eq!(ids, vec![1]);
+    // 原数据库改名保留
+    let conn = DbConnection::establish(old.to_str().unwrap()).unwrap();
+    assert_eq!(user_model::get_all_users(&conn).unwrap()[0].id, 2);
+}
+
+#[test]
+fn restore_rejects_newer_schema() {
+    let dir = backup_dir("newer");
+    fs::create_dir_all(&dir).unwrap();
+    let backup_file = dir.join("newer.db");
+    {
+        let conn = DbConnection::establish(backup_file.to_str().unwrap()).unwrap();
+        run_migrations(&conn).unwrap();
+        diesel::sql_query(
+            "INSERT INTO __diesel_schema_migrations (version) VALUES ('20991231000000')",
+        )
+        .execute(&conn)
+        .unwrap();
+    }
+    let target = dir.join("main.db");
+    fs::write(&target, b"current").unwrap();
+    let config = DatabaseConfig {
+        url: target.to_str().unwrap().to_string(),
+        ..DatabaseConfig::default()
+    };
+
+    let err = backup::restore(&config, &backup_file).unwrap_err();
+    assert!(err.to_string().contains("20991231000000"), "{}", err);
+    // 校验失败时不动当前数据库
+    assert_eq!(fs::read(&target).unwrap(), b"current");
+    assert!(!dir.join("main.restore").exists());
+}
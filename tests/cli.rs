@@ -0,0 +1,309 @@
+mod common;
+
+use std::{fs, path::PathBuf};
+
+use diesel::prelude::*;
+use twitter2telegram::{
+    app, cli,
+    config::Config,
+    models::{
+        self,
+        blacklist_model::{self, Blacklist},
+        role_model::{self, Role},
+        run_migrations,
+        schema::users,
+        tweet_model::{self, Tweet},
+        user_model, DbConnection,
+    },
+    token_cipher,
+};
+
+use common::{
+    seed_follow, seed_user, test_config, test_database, FakeTelegram, FakeTwitter, TOKEN_KEY,
+};
+
+fn config(name: &str) -> Config {
+    let mut config = Config::default();
+    config.database.url = test_database(&format!("t2t-cli-{}-{}", std::process::id(), name));
+    config.database.token_key = TOKEN_KEY.to_string();
+    token_cipher::init(&config.database).unwrap();
+    config
+}
+
+fn temp_file(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("t2t-cli-{}-{}", std::process::id(), name))
+}
+
+#[test]
+fn migrate_lists_and_runs_pending_migrations() {
+    let config = config("migrate");
+    // 迁移编译在程序里，和运行目录无关
+    let all = models::known_migrations().len();
+    let dir = if cfg!(feature = "postgres") {
+        "migrations_postgres"
+    } else {
+        "migrations"
+    };
+    let dirs = fs::read_dir(dir)
+        .unwrap()
+        .filter(|e| e.as_ref().unwrap().path().is_dir())
+        .count();
+    assert_eq!(all, dirs);
+    let pending = cli::migrate(&config, true).unwrap();
+    assert_eq!(pending.len(), all);
+    assert_eq!(pending[0], "20210924121809");
+    // 只查看时不执行
+    assert_eq!(cli::migrate(&config, true).unwrap().len(), all);
+
+    assert_eq!(cli::migrate(&config, false).unwrap().len(), all);
+    assert!(cli::migrate(&config, true).unwrap().is_empty());
+}
+
+#[test]
+fn add_user_bootstraps_new_database() {
+    let config = config("add-user");
+    // 离线子命令只要求 [database]，没有 Telegram 和 Twitter 配置也能运行
+    assert!(config.validate_database().is_empty());
+    assert!(config
+        .validate()
+        .iter()
+        .any(|e| e.starts_with("telegram.bot_token")));
+    assert_eq!(cli::parse_role("ADMIN"), Some(Role::Admin));
+    assert_eq!(cli::parse_role("readonly"), Some(Role::ReadOnly));
+    assert_eq!(cli::parse_role("root"), None);
+
+    cli::add_user(&config, 7, "ops", Some(Role::Admin)).unwrap();
+    cli::add_user(&config, 8, "viewer", None).unwrap();
+    assert!(cli::add_user(&config, 7, "again", None).is_err());
+    assert!(cli::add_user(&config, 0, "nobody", None).is_err());
+
+    let conn = DbConnection::establish(&config.database.url).unwrap();
+    assert_eq!(user_model::get_user_by_id(&conn, 7).unwrap().label, "ops");
+    let role = role_model::get_role_by_user_id(&conn, 7).unwrap().unwrap();
+    assert_eq!(Role::from_i32(role.role), Some(Role::Admin));
+    assert!(role_model::get_role_by_user_id(&conn, 8).unwrap().is_none());
+}
+
+#[test]
+fn export_and_import_round_trip() {
+    let source = config("export");
+    {
+        let conn = DbConnection::establish(&source.database.url).unwrap();
+        run_migrations(&conn).unwrap();
+        seed_user(&conn, 1);
+        seed_user(&conn, 2);
+        seed_follow(&conn, 1, 100);
+        seed_follow(&conn, 2, 100);
+        blacklist_model::block_user(
+            &conn,
+            Blacklist {
+                id: None,
+                user_id: 1,
+                twitter_user_id: 300,
+                twitter_username: "carol".to_string(),
+                created_at: chrono::Utc::now().naive_utc(),
+                type_: 2,
+            },
+        )
+        .unwrap();
+        tweet_model::archive_tweet(
+            &conn,
+            Tweet {
+                id: 5000,
+                twitter_user_id: 100,
+                screen_name: "alice".to_string(),
+                text: "hello export".to_string(),
+                media_urls: String::new(),
+                url: "https://twitter.com/alice/status/5000".to_string(),
+                created_at: chrono::Utc::now().naive_utc(),
+            },
+            vec![1, 2],
+        )
+        .unwrap();
+    }
+    cli::add_user(&source, 3, "ops", Some(Role::Owner)).unwrap();
+
+    let file = temp_file("export.json");
+    let exported = cli::export(&source, &file).unwrap();
+    // 3 个用户、1 个角色、2 个订阅、1 条黑名单、1 条推文、2 个收件人
+    assert_eq!(exported, 10);
+    let text = fs::read_to_string(&file).unwrap();
+    // token 保持加密
+    assert!(!text.contains("access1"));
+
+    let target = config("import");
+    assert_eq!(cli::import(&target, &file).unwrap(), exported);
+    let copy = temp_file("copy.json");
+    cli::export(&target, &copy).unwrap();
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(&text).unwrap(),
+        serde_json::from_str::<serde_json::Value>(&fs::read_to_string(&copy).unwrap()).unwrap()
+    );
+    let conn = DbConnection::establish(&target.database.url).unwrap();
+    assert_eq!(
+        user_model::get_user_by_id(&conn, 1)
+            .unwrap()
+            .twitter_access_token,
+        Some(common::token_json(1))
+    );
+
+    // 只能导入到空数据库
+    let err = cli::import(&target, &file).unwrap_err();
+    assert!(err.to_string().contains("not empty"), "{}", err);
+
+    // 更新版本导出的文件
+    let mut newer: serde_json::Value = serde_json::from_str(&text).unwrap();
+    newer["schema_version"] = "20991231000000".into();
+    fs::write(&copy, newer.to_string()).unwrap();
+    let err = cli::import(&config("newer"), &copy).unwrap_err();
+    assert!(err.to_string().contains("20991231000000"), "{}", err);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn check_tokens_reports_revoked_tokens() {
+    let twitter = FakeTwitter::start();
+    let mut config = config("check-tokens");
+    config.twitter.api_url = twitter.url.clone();
+    {
+        let conn = DbConnection::establish(&config.database.url).unwrap();
+        run_migrations(&conn).unwrap();
+        seed_user(&conn, 1);
+        seed_user(&conn, 2);
+    }
+    // 没有 token 的用户不检查
+    cli::add_user(&config, 3, "no token", None).unwrap();
+    {
+        let conn = DbConnection::establish(&config.database.url).unwrap();
+        seed_user(&conn, 4);
+        diesel::update(users::table.filter(users::id.eq(4)))
+            .set(users::twitter_access_token.eq("v1:broken"))
+            .execute(&conn)
+            .unwrap();
+    }
+    twitter.revoke_token(2);
+
+    let checks = cli::check_tokens(&config).await.unwrap();
+    let mut checks = checks
+        .iter()
+        .map(|c| (c.user_id, &c.status))
+        .collect::<Vec<_>>();
+    checks.sort_by_key(|(id, _)| *id);
+    assert_eq!(checks.len(), 3);
+    assert_eq!(checks[0], (1, &cli::TokenStatus::Valid));
+    assert!(
+        matches!(checks[1], (2, cli::TokenStatus::Error(e)) if e.contains("Invalid or expired token")),
+        "{:?}",
+        checks[1]
+    );
+    // 无法解密的 token 单独报错，不影响其他用户
+    assert!(
+        matches!(checks[2], (4, cli::TokenStatus::Error(_))),
+        "{:?}",
+        checks[2]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn replay_forwards_recorded_stream_messages() {
+    let telegram = FakeTelegram::start();
+    let twitter = FakeTwitter::start();
+    let database_url = test_database(&format!("t2t-cli-{}-replay", std::process::id()));
+    let config = test_config(&database_url, &telegram, &twitter);
+    token_cipher::init(&config.database).unwrap();
+    {
+        let conn = DbConnection::establish(&database_url).unwrap();
+        run_migrations(&conn).unwrap();
+        seed_user(&conn, 1);
+        seed_follow(&conn, 1, 100);
+    }
+    twitter.add_user(100, "alice");
+
+    // 空行是 stream 的保活消息，无法解析的行跳过
+    let lines = [
+        twitter.tweet(100, "first").to_string(),
+        String::new(),
+        "not json".to_string(),
+        twitter.tweet(200, "nobody follows").to_string(),
+        twitter.tweet(100, "second").to_string(),
+    ];
+    let file = temp_file("replay.jsonl");
+    fs::write(&file, lines.join("\r\n")).unwrap();
+
+    // 默认试运行，不推送给用户
+    let count = app::replay(
+        std::sync::Arc::new(config.clone()),
+        &file,
+        Default::default(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(count, 3);
+    assert!(telegram.messages_to(1).is_empty());
+
+    let live = app::ReplayOptions {
+        live: true,
+        ..Default::default()
+    };
+    let count = app::replay(std::sync::Arc::new(config.clone()), &file, live)
+        .await
+        .unwrap();
+    assert_eq!(count, 3);
+    let messages = telegram.messages_to(1);
+    assert_eq!(messages.len(), 2);
+    assert!(messages[0].text().contains("first"));
+    assert!(messages[1].text().contains("second"));
+    // 回放不连接 twitter stream
+    assert_eq!(twitter.connections(), 0);
+
+    // 推送记录不保留，可以再次回放
+    assert_eq!(
+        app::replay(std::sync::Arc::new(config), &file, live)
+            .await
+            .unwrap(),
+        3
+    );
+    assert_eq!(telegram.messages_to(1).len(), 4);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn replay_keeps_recorded_pace_of_old_tweets() {
+    let telegram = FakeTelegram::start();
+    let twitter = FakeTwitter::start();
+    let database_url = test_database(&format!("t2t-cli-{}-pace", std::process::id()));
+    let config = test_config(&database_url, &telegram, &twitter);
+    token_cipher::init(&config.database).unwrap();
+    {
+        let conn = DbConnection::establish(&database_url).unwrap();
+        run_migrations(&conn).unwrap();
+        seed_user(&conn, 1);
+        seed_follow(&conn, 1, 100);
+    }
+
+    // 录制于十天前、相隔两秒的推文
+    let recorded_at = chrono::Utc::now() - chrono::Duration::days(10);
+    let lines = (0..2)
+        .map(|i| {
+            let mut t = twitter.tweet(100, &format!("old {}", i));
+            t["created_at"] = (recorded_at + chrono::Duration::seconds(2 * i))
+                .format("%a %b %d %T %z %Y")
+                .to_string()
+                .into();
+            t.to_string()
+        })
+        .collect::<Vec<_>>();
+    let file = temp_file("pace.jsonl");
+    fs::write(&file, lines.join("\n")).unwrap();
+
+    let started = std::time::Instant::now();
+    let options = app::ReplayOptions {
+        speed: 4.0,
+        live: true,
+    };
+    let count = app::replay(std::sync::Arc::new(config), &file, options)
+        .await
+        .unwrap();
+    assert_eq!(count, 2);
+    assert!(started.elapsed() >= std::time::Duration::from_millis(500));
+    // 回放时不按发推时间过滤
+    assert_eq!(telegram.messages_to(1).len(), 2);
+}
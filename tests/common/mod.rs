@@ -0,0 +1,812 @@
+// 集成测试用的模拟 Telegram Bot API 与 Twitter API/stream，以及启动整个 bot 的辅助函数
+#![allow(dead_code)]
+
+use std::{
+    collections::{HashMap, HashSet},
+    convert::Infallible,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use axum::{
+    body::{Bytes, StreamBody},
+    extract::{Extension, Path, Query},
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+    routing::{get, post},
+    Json, Router,
+};
+use diesel::Connection;
+use serde_json::{json, Value};
+use tokio::{
+    sync::{mpsc, oneshot, Notify},
+    task::JoinHandle,
+};
+
+use twitter2telegram::{
+    app,
+    config::Config,
+    models::{
+        follow_model::{self, Follow},
+        run_migrations,
+        user_model::{self, User},
+        DbConnection,
+    },
+};
+
+pub const ADMIN_ID: i64 = 1000;
+pub const BOT_ID: i64 = 42;
+pub const BOT_USERNAME: &str = "T2TBot";
+const BOT_TOKEN: &str = "42:test-token";
+// 测试用的 token 加密密钥
+pub const TOKEN_KEY: &str = "MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY=";
+// 检查 token 有效性时查询的账号
+const TWITTER_ACCOUNT_ID: u64 = 783214;
+
+pub async fn wait_until<F: FnMut() -> bool>(what: &str, mut f: F) {
+    for _ in 0..250 {
+        if f() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("timed out waiting for {}", what);
+}
+
+fn spawn_server(app: Router) -> SocketAddr {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(app.into_make_service())
+            .await
+            .unwrap();
+    });
+    addr
+}
+
+fn now_unix() -> i64 {
+    chrono::Utc::now().timestamp()
+}
+
+// ---------------------------------------------------------------------------
+// Telegram
+
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    pub params: Value,
+}
+
+impl Request {
+    pub fn chat_id(&self) -> Option<i64> {
+        match &self.params["chat_id"] {
+            Value::Number(n) => n.as_i64(),
+            Value::String(s) => s.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        self.params["text"].as_str().unwrap_or_default()
+    }
+
+    // 按行返回 (按钮文字, callback data)
+    pub fn keyboard(&self) -> Vec<Vec<(String, String)>> {
+        let rows = match self.params["reply_markup"]["inline_keyboard"].as_array() {
+            Some(rows) => rows.clone(),
+            None => return Vec::new(),
+        };
+        rows.iter()
+            .map(|row| {
+                row.as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|b| {
+                        (
+                            b["text"].as_str().unwrap_or_default().to_string(),
+                            b["callback_data"].as_str().unwrap_or_default().to_string(),
+                        )
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+#[derive(Default)]
+struct TelegramState {
+    requests: Mutex<Vec<Request>>,
+    updates: Mutex<Vec<Value>>,
+    new_update: Notify,
+    next_update_id: AtomicI64,
+    next_message_id: AtomicI64,
+}
+
+#[derive(Clone)]
+pub struct FakeTelegram {
+    pub url: String,
+    state: Arc<TelegramState>,
+}
+
+impl FakeTelegram {
+    pub fn start() -> FakeTelegram {
+        let state = Arc::new(TelegramState::default());
+        let app = Router::new()
+            .route("/:bot/:method", post(telegram_method))
+            .layer(Extension(state.clone()));
+        let addr = spawn_server(app);
+        FakeTelegram {
+            url: format!("http://{}", addr),
+            state,
+        }
+    }
+
+    pub fn requests(&self) -> Vec<Request> {
+        self.state.requests.lock().unwrap().clone()
+    }
+
+    // 发给某个 chat 的所有消息（sendMessage）
+    pub fn messages_to(&self, chat_id: i64) -> Vec<Request> {
+        self.requests()
+            .into_iter()
+            .filter(|r| r.method == "sendMessage" && r.chat_id() == Some(chat_id))
+            .collect()
+    }
+
+    pub async fn wait_for_messages(&self, chat_id: i64, count: usize) -> Vec<Request> {
+        wait_until(&format!("{} messages to {}", count, chat_id), || {
+            self.messages_to(chat_id).len() >= count
+        })
+        .await;
+        self.messages_to(chat_id)
+    }
+
+    pub async fn wait_for_method(&self, method: &str) {
+        wait_until(method, || {
+            self.requests().iter().any(|r| r.method == method)
+        })
+        .await;
+    }
+
+    fn push_update(&self, mut update: Value) {
+        let update_id = self.state.next_update_id.fetch_add(1, Ordering::SeqCst) + 1;
+        update["update_id"] = json!(update_id);
+        self.state.updates.lock().unwrap().push(update);
+        self.state.new_update.notify_waiters();
+    }
+
+    // 模拟用户在私聊中发送文本
+    pub fn send_text(&self, from: i64, text: &str) {
+        self.push_update(self.text_update(from, text));
+    }
+
+    // 私聊文本消息的 update，不含 update_id
+    pub fn text_update(&self, from: i64, text: &str) -> Value {
+        let message_id = self.state.next_message_id.fetch_add(1, Ordering::SeqCst) + 1;
+        json!({
+            "message": {
+                "message_id": message_id,
+                "date": now_unix(),
+                "chat": private_chat(from),
+                "from": user(from),
+                "text": text,
+            }
+        })
+    }
+
+    // 模拟用户点击 bot 某条消息上的按钮
+    pub fn press_button(&self, from: i64, message: &Request, callback_data: &str) {
+        let message_id = self.state.next_message_id.fetch_add(1, Ordering::SeqCst) + 1;
+        self.push_update(json!({
+            "callback_query": {
+                "id": format!("cb{}", message_id),
+                "from": user(from),
+                "chat_instance": "test",
+                "data": callback_data,
+                "message": {
+                    "message_id": message_id,
+                    "date": now_unix(),
+                    "chat": private_chat(from),
+                    "from": bot_user(),
+                    "text": message.text(),
+                },
+            }
+        }));
+    }
+}
+
+fn user(id: i64) -> Value {
+    json!({"id": id, "is_bot": false, "first_name": format!("user{}", id)})
+}
+
+fn bot_user() -> Value {
+    json!({
+        "id": BOT_ID,
+        "is_bot": true,
+        "first_name": "T2T",
+        "username": BOT_USERNAME,
+        "can_join_groups": false,
+        "can_read_all_group_messages": false,
+        "supports_inline_queries": false,
+    })
+}
+
+fn private_chat(id: i64) -> Value {
+    json!({"id": id, "type": "private", "first_name": format!("user{}", id)})
+}
+
+async fn telegram_method(
+    Path((_bot, mut method)): Path<(String, String)>,
+    headers: HeaderMap,
+    body: Bytes,
+    Extension(state): Extension<Arc<TelegramState>>,
+) -> Json<Value> {
+    // teloxide 使用 GetMe 这样的大写方法名，统一成文档中的写法
+    method[..1].make_ascii_lowercase();
+    let content_type = headers
+        .get("content-type")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    let params: Value = if content_type.starts_with("application/json") {
+        serde_json::from_slice(&body).unwrap_or(Value::Null)
+    } else if content_type.starts_with("multipart/form-data") {
+        multipart_params(content_type, &body)
+    } else {
+        Value::Null
+    };
+
+    let result = match method.as_str() {
+        "getMe" => bot_user(),
+        "getUpdates" => {
+            let offset = params["offset"].as_i64().unwrap_or(0);
+            let pending = |state: &TelegramState| -> Vec<Value> {
+                state
+                    .updates
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .filter(|u| u["update_id"].as_i64().unwrap() >= offset)
+                    .cloned()
+                    .collect()
+            };
+            let mut updates = pending(&state);
+            if updates.is_empty() {
+                let _ =
+                    tokio::time::timeout(Duration::from_millis(500), state.new_update.notified())
+                        .await;
+                updates = pending(&state);
+            }
+            json!(updates)
+        }
+        "sendMessage" | "editMessageReplyMarkup" | "editMessageText" | "sendDocument" => {
+            let message_id = state.next_message_id.fetch_add(1, Ordering::SeqCst) + 1;
+            let chat_id = params["chat_id"].as_i64().unwrap_or_default();
+            json!({
+                "message_id": message_id,
+                "date": now_unix(),
+                "chat": private_chat(chat_id),
+                "from": bot_user(),
+                "text": params["text"].as_str().unwrap_or_default(),
+            })
+        }
+        "sendMediaGroup" => json!([]),
+        _ => json!(true),
+    };
+    if method != "getUpdates" {
+        state
+            .requests
+            .lock()
+            .unwrap()
+            .push(Request { method, params });
+    }
+    Json(json!({"ok": true, "result": result}))
+}
+
+fn find(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    haystack[from..]
+        .windows(needle.len())
+        .position(|w| w == needle)
+        .map(|i| i + from)
+}
+
+fn header_value(head: &str, key: &str) -> Option<String> {
+    let start = head.find(&format!("{}=\"", key))? + key.len() + 2;
+    let len = head[start..].find('"')?;
+    Some(head[start..start + len].to_string())
+}
+
+// 上传文件时使用 multipart，文件以 {"file_name", "content"(base64)} 保存在对应参数下
+fn multipart_params(content_type: &str, body: &[u8]) -> Value {
+    let boundary = match content_type.split("boundary=").nth(1) {
+        Some(b) => format!("--{}", b.trim_matches('"')),
+        None => return Value::Null,
+    };
+    let mut params = serde_json::Map::new();
+    let mut pos = 0;
+    while let Some(start) = find(body, boundary.as_bytes(), pos) {
+        let part_start = start + boundary.len() + 2;
+        let end = match find(body, boundary.as_bytes(), part_start) {
+            Some(end) => end,
+            None => break,
+        };
+        pos = end;
+        let part = &body[part_start..end - 2];
+        let split = match find(part, b"\r\n\r\n", 0) {
+            Some(split) => split,
+            None => continue,
+        };
+        let head = String::from_utf8_lossy(&part[..split]).to_string();
+        let data = &part[split + 4..];
+        let name = match header_value(&head, "name") {
+            Some(name) => name,
+            None => continue,
+        };
+        let value = match header_value(&head, "filename") {
+            Some(file_name) => json!({"file_name": file_name, "content": base64::encode(data)}),
+            None => json!(String::from_utf8_lossy(data)),
+        };
+        params.insert(name, value);
+    }
+    // teloxide 把文件放在随机命名的字段里，参数中用 attach://<字段名> 引用
+    let attached: Vec<(String, String)> = params
+        .iter()
+        .filter_map(|(k, v)| {
+            Some((
+                k.clone(),
+                v.as_str()?.strip_prefix("attach://")?.to_string(),
+            ))
+        })
+        .collect();
+    for (key, field) in attached {
+        if let Some(file) = params.remove(&field) {
+            params.insert(key, file);
+        }
+    }
+    Value::Object(params)
+}
+
+// ---------------------------------------------------------------------------
+// Twitter
+
+struct StreamConnection {
+    follows: Vec<u64>,
+    tx: mpsc::UnboundedSender<Result<Bytes, Infallible>>,
+}
+
+#[derive(Default)]
+struct TwitterState {
+    users: Mutex<HashMap<u64, String>>,
+    // 已撤销授权的 access token
+    revoked: Mutex<HashSet<String>>,
+    streams: Mutex<Vec<StreamConnection>>,
+    connections: AtomicI64,
+    next_tweet_id: AtomicI64,
+}
+
+#[derive(Clone)]
+pub struct FakeTwitter {
+    pub url: String,
+    state: Arc<TwitterState>,
+}
+
+impl FakeTwitter {
+    pub fn start() -> FakeTwitter {
+        let state = Arc::new(TwitterState::default());
+        state
+            .users
+            .lock()
+            .unwrap()
+            .insert(TWITTER_ACCOUNT_ID, "Twitter".to_string());
+        let app = Router::new()
+            .route("/1.1/users/show.json", get(twitter_user_show))
+            .route("/1.1/statuses/filter.json", post(twitter_filter))
+            .layer(Extension(state.clone()));
+        let addr = spawn_server(app);
+        FakeTwitter {
+            url: format!("http://{}", addr),
+            state,
+        }
+    }
+
+    pub fn add_user(&self, id: u64, screen_name: &str) {
+        self.state
+            .users
+            .lock()
+            .unwrap()
+            .insert(id, screen_name.to_string());
+    }
+
+    // 之后用该用户 token 的请求都返回授权失效
+    pub fn revoke_token(&self, user_id: i64) {
+        self.state
+            .revoked
+            .lock()
+            .unwrap()
+            .insert(format!("oauth_token=\"access{}\"", user_id));
+    }
+
+    // 当前打开的 stream 各自 follow 的账号
+    pub fn open_streams(&self) -> Vec<Vec<u64>> {
+        let mut streams = self.state.streams.lock().unwrap();
+        streams.retain(|s| !s.tx.is_closed());
+        streams.iter().map(|s| s.follows.clone()).collect()
+    }
+
+    // 累计建立过的 stream 连接数
+    pub fn connections(&self) -> usize {
+        self.state.connections.load(Ordering::SeqCst) as usize
+    }
+
+    // 服务端断开所有 stream
+    pub fn close_streams(&self) {
+        self.state.streams.lock().unwrap().clear();
+    }
+
+    pub async fn wait_for_stream_following(&self, twitter_id: u64) {
+        wait_until(&format!("a stream following {}", twitter_id), || {
+            self.open_streams()
+                .iter()
+                .any(|follows| follows.contains(&twitter_id))
+        })
+        .await;
+    }
+
+    pub fn new_tweet_id(&self) -> u64 {
+        self.state.next_tweet_id.fetch_add(1, Ordering::SeqCst) as u64 + 1
+    }
+
+    // 推送到所有 follow 了作者的 stream，返回推送的 stream 数
+    pub fn push_tweet(&self, tweet: &Value) -> usize {
+        let author = tweet["user"]["id"].as_u64().unwrap();
+        self.push_message(|follows| follows.contains(&author), tweet)
+    }
+
+    // limit、disconnect 等控制消息，推送到所有 stream
+    pub fn push_control(&self, message: &Value) -> usize {
+        self.push_message(|_| true, message)
+    }
+
+    // 推文被删除的通知
+    pub fn push_delete(&self, tweet: &Value) -> usize {
+        let author = tweet["user"]["id"].as_u64().unwrap();
+        self.push_message(
+            |follows| follows.contains(&author),
+            &json!({
+                "delete": {
+                    "status": {
+                        "id": tweet["id"],
+                        "id_str": tweet["id"].to_string(),
+                        "user_id": author,
+                        "user_id_str": author.to_string(),
+                    }
+                }
+            }),
+        )
+    }
+
+    fn push_message<F: Fn(&[u64]) -> bool>(&self, to: F, message: &Value) -> usize {
+        let line = Bytes::from(format!("{}\r\n", message));
+        let mut streams = self.state.streams.lock().unwrap();
+        streams.retain(|s| !s.tx.is_closed());
+        streams
+            .iter()
+            .filter(|s| to(&s.follows))
+            .filter(|s| s.tx.send(Ok(line.clone())).is_ok())
+            .count()
+    }
+
+    pub fn tweet(&self, author: u64, text: &str) -> Value {
+        let id = self.new_tweet_id();
+        tweet_json(id, self.user_json(author), text, None)
+    }
+
+    pub fn retweet(&self, retweeter: u64, original: &Value) -> Value {
+        let id = self.new_tweet_id();
+        let text = format!(
+            "RT @{}: {}",
+            original["user"]["screen_name"].as_str().unwrap(),
+            original["full_text"].as_str().unwrap()
+        );
+        tweet_json(id, self.user_json(retweeter), &text, Some(original.clone()))
+    }
+
+    pub fn user_json(&self, id: u64) -> Value {
+        let screen_name = self
+            .state
+            .users
+            .lock()
+            .unwrap()
+            .get(&id)
+            .cloned()
+            .unwrap_or_else(|| format!("user{}", id));
+        user_json(id, &screen_name)
+    }
+}
+
+fn twitter_time(t: chrono::DateTime<chrono::Utc>) -> String {
+    t.format("%a %b %d %T %z %Y").to_string()
+}
+
+pub fn user_json(id: u64, screen_name: &str) -> Value {
+    json!({
+        "contributors_enabled": false,
+        "created_at": twitter_time(chrono::Utc::now()),
+        "default_profile": true,
+        "default_profile_image": true,
+        "description": null,
+        "favourites_count": 0,
+        "followers_count": 0,
+        "friends_count": 0,
+        "geo_enabled": false,
+        "id": id,
+        "is_translator": false,
+        "listed_count": 0,
+        "name": screen_name,
+        "profile_background_color": "000000",
+        "profile_image_url": "http://example.com/a.png",
+        "profile_image_url_https": "https://example.com/a.png",
+        "profile_link_color": "000000",
+        "profile_sidebar_border_color": "000000",
+        "profile_sidebar_fill_color": "000000",
+        "profile_text_color": "000000",
+        "profile_use_background_image": false,
+        "protected": false,
+        "screen_name": screen_name,
+        "statuses_count": 0,
+        "verified": false,
+    })
+}
+
+pub fn tweet_json(id: u64, user: Value, text: &str, retweeted_status: Option<Value>) -> Value {
+    json!({
+        "created_at": twitter_time(chrono::Utc::now()),
+        "entities": {"hashtags": [], "symbols": [], "urls": [], "user_mentions": []},
+        "favorite_count": 0,
+        "id": id,
+        "retweet_count": 0,
+        "retweeted_status": retweeted_status,
+        "source": "<a href=\"https://example.com\" rel=\"nofollow\">test</a>",
+        "full_text": text,
+        "display_text_range": [0, text.chars().count()],
+        "truncated": false,
+        "user": user,
+    })
+}
+
+async fn twitter_user_show(
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    Extension(state): Extension<Arc<TwitterState>>,
+) -> impl IntoResponse {
+    let auth = headers
+        .get("authorization")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    if state
+        .revoked
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|t| auth.contains(t))
+    {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"errors": [{"code": 89, "message": "Invalid or expired token."}]})),
+        );
+    }
+    let id: u64 = query
+        .get("user_id")
+        .and_then(|id| id.parse().ok())
+        .unwrap_or_default();
+    let screen_name = state.users.lock().unwrap().get(&id).cloned();
+    match screen_name {
+        Some(screen_name) => (StatusCode::OK, Json(user_json(id, &screen_name))),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"errors": [{"code": 50, "message": "User not found."}]})),
+        ),
+    }
+}
+
+async fn twitter_filter(
+    body: Bytes,
+    Extension(state): Extension<Arc<TwitterState>>,
+) -> impl IntoResponse {
+    let follows = url::form_urlencoded::parse(&body)
+        .find(|(k, _)| k == "follow")
+        .map(|(_, v)| {
+            v.split(',')
+                .filter_map(|id| id.parse().ok())
+                .collect::<Vec<u64>>()
+        })
+        .unwrap_or_default();
+    state.connections.fetch_add(1, Ordering::SeqCst);
+    let (tx, rx) = mpsc::unbounded_channel();
+    // 先发一个保活的空行，让客户端立即收到响应
+    let _ = tx.send(Ok(Bytes::from("\r\n")));
+    state
+        .streams
+        .lock()
+        .unwrap()
+        .push(StreamConnection { follows, tx });
+    let body = futures::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|chunk| (chunk, rx))
+    });
+    StreamBody::new(body)
+}
+
+// ---------------------------------------------------------------------------
+// Bot
+
+pub fn token_json(user_id: i64) -> String {
+    let token = egg_mode::Token::Access {
+        consumer: egg_mode::KeyPair::new("key", "secret"),
+        access: egg_mode::KeyPair::new(format!("access{}", user_id), "access_secret"),
+    };
+    serde_json::to_string(&token).unwrap()
+}
+
+pub struct TestBot {
+    pub telegram: FakeTelegram,
+    pub twitter: FakeTwitter,
+    pub database_url: String,
+    shutdown: Option<oneshot::Sender<()>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+// 每个测试使用独立的空数据库
+#[cfg(not(feature = "postgres"))]
+pub fn test_database(name: &str) -> String {
+    let dir = std::env::temp_dir().join(name);
+    std::fs::create_dir_all(&dir).unwrap();
+    let database_url = dir.join("main.db").to_str().unwrap().to_string();
+    let _ = std::fs::remove_file(&database_url);
+    database_url
+}
+
+// TEST_DATABASE_URL 指向一个有建库权限的 PostgreSQL
+#[cfg(feature = "postgres")]
+pub fn test_database(name: &str) -> String {
+    use diesel::RunQueryDsl;
+
+    let admin_url =
+        std::env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL is required for postgres");
+    let conn = DbConnection::establish(&admin_url).unwrap();
+    let name = name.replace('-', "_");
+    diesel::sql_query(format!("DROP DATABASE IF EXISTS {}", name))
+        .execute(&conn)
+        .unwrap();
+    diesel::sql_query(format!("CREATE DATABASE {}", name))
+        .execute(&conn)
+        .unwrap();
+    let mut url = url::Url::parse(&admin_url).unwrap();
+    url.set_path(&name);
+    url.to_string()
+}
+
+// 指向模拟服务的配置
+pub fn test_config(database_url: &str, telegram: &FakeTelegram, twitter: &FakeTwitter) -> Config {
+    let mut config = Config::default();
+    config.database.url = database_url.to_string();
+    config.database.token_key = TOKEN_KEY.to_string();
+    config.telegram.bot_token = BOT_TOKEN.to_string();
+    config.telegram.admin_id = ADMIN_ID;
+    config.telegram.api_url = telegram.url.clone();
+    config.twitter.key = "key".to_string();
+    config.twitter.secret = "secret".to_string();
+    config.twitter.api_url = twitter.url.clone();
+    config.twitter.stream_url = twitter.url.clone();
+    config.twitter.stream_retry_secs = 1;
+    config.twitter.stream_restart_delay_ms = 300;
+    config.forwarder.shutdown_timeout_secs = 2;
+    config.http.listen = "".to_string();
+    config
+}
+
+impl TestBot {
+    // 建库并写入初始数据，然后启动 bot
+    pub async fn start<F: FnOnce(&DbConnection)>(seed: F) -> TestBot {
+        TestBot::start_with(seed, |_| {}).await
+    }
+
+    // configure 可以在启动前修改配置
+    pub async fn start_with<F, C>(seed: F, configure: C) -> TestBot
+    where
+        F: FnOnce(&DbConnection),
+        C: FnOnce(&mut Config),
+    {
+        let _ = pretty_env_logger::try_init();
+        let telegram = FakeTelegram::start();
+        let twitter = FakeTwitter::start();
+
+        let database_url = test_database(&format!(
+            "t2t-test-{}-{}",
+            std::process::id(),
+            twitter.url.rsplit(':').next().unwrap()
+        ));
+        {
+            let conn = DbConnection::establish(&database_url).unwrap();
+            run_migrations(&conn).unwrap();
+            seed(&conn);
+        }
+
+        let mut config = test_config(&database_url, &telegram, &twitter);
+        configure(&mut config);
+        assert!(config.validate().is_empty(), "{:?}", config.validate());
+
+        let (tx, rx) = oneshot::channel::<()>();
+        let handle = tokio::spawn(app::run(Arc::new(config), async move {
+            let _ = rx.await;
+        }));
+        let bot = TestBot {
+            telegram,
+            twitter,
+            database_url,
+            shutdown: Some(tx),
+            handle: Some(handle),
+        };
+        // 等 dispatcher 开始拉取更新
+        bot.telegram.wait_for_method("getMe").await;
+        bot
+    }
+
+    pub fn conn(&self) -> DbConnection {
+        let conn = DbConnection::establish(&self.database_url).unwrap();
+        // 测试中轮询读取时 bot 可能正在写入
+        #[cfg(not(feature = "postgres"))]
+        diesel::connection::SimpleConnection::batch_execute(&conn, "PRAGMA busy_timeout = 5000")
+            .unwrap();
+        conn
+    }
+
+    pub async fn stop(mut self) {
+        let _ = self.shutdown.take().unwrap().send(());
+        tokio::time::timeout(Duration::from_secs(15), self.handle.take().unwrap())
+            .await
+            .expect("bot did not shut down in time")
+            .unwrap();
+    }
+}
+
+pub fn seed_user(conn: &DbConnection, id: i64) {
+    user_model::create_user(
+        conn,
+        User {
+            id,
+            label: format!("u{}", id),
+            twitter_access_token: None,
+            twitter_status: false,
+            created_at: chrono::Utc::now().naive_utc(),
+            disable_retweet: false,
+            disable_text_msg: false,
+            suspended: false,
+            follow_quota: 0,
+            dry_run: false,
+            remove_deleted: false,
+        },
+    )
+    .unwrap();
+    user_model::update_twitter_token(conn, id, token_json(id), true).unwrap();
+}
+
+pub fn seed_follow(conn: &DbConnection, user_id: i64, twitter_user_id: i64) {
+    follow_model::create_follow(
+        conn,
+        Follow {
+            id: None,
+            user_id,
+            twitter_user_id,
+            twitter_username: format!("user{}", twitter_user_id),
+            created_at: chrono::Utc::now().naive_utc(),
+            follow_rt_count: 0,
+            block_rt_count: 0,
+        },
+    )
+    .unwrap();
+}
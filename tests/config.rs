@@ -0,0 +1,69 @@
+use twitter2telegram::config::Config;
+
+#[test]
+fn example_config_is_valid() {
+    let config = Config::from_toml(include_str!("../config.example.toml")).unwrap();
+    assert!(config.validate().is_empty(), "{:?}", config.validate());
+    assert!(Config::from_toml("[telegram]\nadmin = 1").is_err());
+}
+
+#[test]
+fn validate_lists_every_invalid_field() {
+    let mut config = Config::default();
+    config.database.url = " ".to_string();
+    config.database.pool_size = 0;
+    config.database.token_key = "short".to_string();
+    config.telegram.api_url = "not a url".to_string();
+    config.telegram.broadcast_ttl_secs = 0;
+    config.twitter.stream_retry_secs = 10;
+    config.twitter.stream_retry_max_secs = 5;
+    config.forwarder.channel_capacity = 0;
+    config.http.listen = "9090".to_string();
+
+    let errors = config.validate();
+    let fields = errors
+        .iter()
+        .map(|e| e.split(' ').next().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        fields,
+        vec![
+            "database.url",
+            "database.pool_size",
+            "database.token_key",
+            "telegram.bot_token",
+            "telegram.admin_id",
+            "telegram.broadcast_ttl_secs",
+            "telegram.api_url",
+            "twitter.key",
+            "twitter.secret",
+            "twitter.stream_retry_max_secs",
+            "forwarder.channel_capacity",
+            "http.listen",
+        ],
+        "{:#?}",
+        errors
+    );
+
+    // 离线子命令只检查 [database]
+    assert_eq!(config.validate_database().len(), 3);
+}
+
+#[test]
+fn env_overrides_documented_keys() {
+    std::env::set_var(
+        "CONFIG_FILE",
+        concat!(env!("CARGO_MANIFEST_DIR"), "/config.example.toml"),
+    );
+    std::env::set_var("FORWARDER_SENT_MESSAGE_DAYS", "3");
+    let config = Config::load().unwrap();
+    assert_eq!(config.forwarder.sent_message_days, 3);
+
+    std::env::set_var("FORWARDER_SENT_MESSAGE_DAYS", "-1");
+    let errors = Config::load().unwrap_err().0;
+    assert!(
+        errors.iter().any(|e| e.contains("sent_message_days")),
+        "{:?}",
+        errors
+    );
+}
@@ -0,0 +1,133 @@
+mod common;
+
+use twitter2telegram::{
+    config::DatabaseConfig,
+    models::{
+        establish_connection,
+        invite_model::{self, Invite},
+        role_model, run_migrations,
+        user_model::{self, User},
+        DbError, Repository,
+    },
+};
+
+use common::{test_database, TestBot};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn pool_exhaustion_is_retryable() {
+    let db = Repository::new(establish_connection(&DatabaseConfig {
+        url: test_database(&format!("t2t-test-{}-pool", std::process::id())),
+        pool_size: 1,
+        pool_timeout_secs: 1,
+        ..DatabaseConfig::default()
+    }));
+    db.run(run_migrations).await.unwrap();
+
+    // 唯一的连接被占用时，查询超时返回可重试的错误，不会 panic
+    let conn = db.pool().get().unwrap();
+    let err = db
+        .run(role_model::get_all_roles)
+        .await
+        .err()
+        .expect("query should time out");
+    assert!(matches!(err, DbError::Pool(_)), "{:?}", err);
+    assert!(err.is_retryable());
+
+    drop(conn);
+    assert!(db.run(role_model::get_all_roles).await.unwrap().is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn single_use_invite_is_redeemed_once_concurrently() {
+    let db = Repository::new(establish_connection(&DatabaseConfig {
+        url: test_database(&format!("t2t-test-{}-invite", std::process::id())),
+        pool_size: 8,
+        ..DatabaseConfig::default()
+    }));
+    db.run(run_migrations).await.unwrap();
+    let now = chrono::Utc::now().naive_utc();
+    db.run(move |conn| {
+        invite_model::create_invite(
+            conn,
+            Invite {
+                code: "once".to_string(),
+                label: "team".to_string(),
+                max_uses: 1,
+                used_count: 0,
+                expires_at: now + chrono::Duration::hours(1),
+                created_by: 1,
+                created_at: now,
+            },
+        )
+    })
+    .await
+    .unwrap();
+
+    let tasks = (10..18)
+        .map(|uid| {
+            let db = db.clone();
+            tokio::spawn(async move {
+                db.run(move |conn| {
+                    invite_model::redeem_invite(
+                        conn,
+                        "once",
+                        User {
+                            id: uid,
+                            label: String::new(),
+                            twitter_access_token: None,
+                            twitter_status: false,
+                            created_at: now,
+                            disable_retweet: false,
+                            disable_text_msg: false,
+                            suspended: false,
+                            follow_quota: 0,
+                            dry_run: false,
+                            remove_deleted: false,
+                        },
+                        now,
+                    )
+                })
+                .await
+            })
+        })
+        .collect::<Vec<_>>();
+    let mut redeemed = 0;
+    for task in tasks {
+        // SQLite 上并发写入可能返回数据库被锁的错误，但不能重复使用
+        if let Ok(Some(_)) = task.await.unwrap() {
+            redeemed += 1;
+        }
+    }
+    assert_eq!(redeemed, 1);
+    assert_eq!(db.run(user_model::get_all_users).await.unwrap().len(), 1);
+    let invites = db.run(invite_model::get_all_invites).await.unwrap();
+    assert_eq!(invites[0].used_count, 1);
+}
+
+// SQLite 写锁被其他连接持有时，提示用户稍后重试
+#[cfg(not(feature = "postgres"))]
+#[tokio::test(flavor = "multi_thread")]
+async fn locked_database_asks_user_to_retry() {
+    use diesel::{connection::SimpleConnection, Connection};
+
+    const USER: i64 = 1;
+    let bot = TestBot::start(|conn| common::seed_user(conn, USER)).await;
+
+    let lock = diesel::SqliteConnection::establish(&bot.database_url).unwrap();
+    lock.batch_execute("BEGIN EXCLUSIVE").unwrap();
+    bot.telegram.send_text(USER, "/ListFollowedTwitterID");
+    let messages = bot.telegram.wait_for_messages(USER, 1).await;
+    assert_eq!(
+        messages[0].text(),
+        "The database is busy, please try again later"
+    );
+
+    lock.batch_execute("COMMIT").unwrap();
+    bot.telegram.send_text(USER, "/ListFollowedTwitterID");
+    let messages = bot.telegram.wait_for_messages(USER, 2).await;
+    assert!(messages[1]
+        .text()
+        .starts_with("You are currently subscribed"));
+
+    bot.stop().await;
+}
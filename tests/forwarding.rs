@@ -0,0 +1,621 @@
+mod common;
+
+use common::{seed_follow, seed_user, TestBot};
+use diesel::prelude::*;
+use twitter2telegram::models::{
+    blacklist_model, delivery_log_model, follow_model, forward_history_model,
+    schema::{sent_messages, users},
+    user_model,
+};
+
+const USER: i64 = 1;
+
+fn tweet_messages(bot: &TestBot, chat_id: i64) -> Vec<common::Request> {
+    bot.telegram
+        .messages_to(chat_id)
+        .into_iter()
+        .filter(|m| m.text().contains("twitter.com/"))
+        .collect()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn forwards_tweet_with_unfollow_button() {
+    let bot = TestBot::start(|conn| {
+        seed_user(conn, USER);
+        seed_follow(conn, USER, 100);
+    })
+    .await;
+    bot.twitter.add_user(100, "alice");
+    bot.twitter.wait_for_stream_following(100).await;
+
+    let tweet = bot.twitter.tweet(100, "hello world");
+    assert_eq!(bot.twitter.push_tweet(&tweet), 1);
+
+    let messages = bot.telegram.wait_for_messages(USER, 1).await;
+    assert_eq!(messages.len(), 1);
+    assert_eq!(
+        messages[0].text(),
+        format!(
+            "*alice*: hello world [🔗](https://twitter.com/alice/status/{})",
+            tweet["id"]
+        )
+    );
+    assert_eq!(
+        messages[0].keyboard(),
+        vec![vec![(
+            "Unfollow".to_string(),
+            "/UnfollowTwitterID 100".to_string()
+        )]]
+    );
+
+    bot.stop().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn follow_command_subscribes_and_forwards() {
+    let bot = TestBot::start(|conn| {
+        seed_user(conn, USER);
+    })
+    .await;
+    bot.twitter.add_user(200, "bob");
+
+    bot.telegram.send_text(USER, "/FollowTwitterID 200 0");
+    let replies = bot.telegram.wait_for_messages(USER, 1).await;
+    assert_eq!(replies[0].text(), "Added successfully, affecting 1 records");
+    let follows = follow_model::get_follows_by_user_id(&bot.conn(), USER).unwrap();
+    assert_eq!(follows.len(), 1);
+    assert_eq!(follows[0].twitter_user_id, 200);
+    assert_eq!(follows[0].twitter_username, "bob");
+
+    bot.twitter.wait_for_stream_following(200).await;
+    bot.twitter.push_tweet(&bot.twitter.tweet(200, "first"));
+    bot.telegram.wait_for_messages(USER, 2).await;
+    let tweets = tweet_messages(&bot, USER);
+    assert_eq!(tweets.len(), 1);
+    assert!(tweets[0].text().starts_with("*bob*: first"));
+
+    bot.stop().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn blocked_retweeter_is_not_forwarded() {
+    let bot = TestBot::start(|conn| {
+        seed_user(conn, USER);
+        seed_follow(conn, USER, 100);
+    })
+    .await;
+    bot.twitter.add_user(100, "alice");
+    bot.twitter.add_user(300, "carol");
+    bot.twitter.wait_for_stream_following(100).await;
+
+    // alice 转推 carol
+    let original = bot.twitter.tweet(300, "from carol");
+    bot.twitter.push_tweet(&bot.twitter.retweet(100, &original));
+    let messages = bot.telegram.wait_for_messages(USER, 1).await;
+    let keyboard = messages[0].keyboard();
+    assert_eq!(
+        keyboard,
+        vec![vec![
+            (
+                "🚫RTer".to_string(),
+                "/BlockTwitterID 2 300 100".to_string()
+            ),
+            (
+                "👀RTer(0)".to_string(),
+                "/FollowTwitterID 300 100".to_string()
+            ),
+            ("🚫RT(0)".to_string(), "/BlockTwitterID 1 100 0".to_string()),
+            ("❌".to_string(), "/UnfollowTwitterID 100".to_string()),
+        ]]
+    );
+
+    // 点击屏蔽转推作者
+    bot.telegram
+        .press_button(USER, &messages[0], &keyboard[0][0].1);
+    let replies = bot.telegram.wait_for_messages(USER, 2).await;
+    assert_eq!(replies[1].text(), "Added successfully, affecting 1 records");
+    let blacklist = blacklist_model::get_blacklist_by_user_id(
+        &bot.conn(),
+        USER,
+        blacklist_model::BlacklistType::BlockTwitter.toi32(),
+    )
+    .unwrap();
+    assert_eq!(blacklist.len(), 1);
+    assert_eq!(blacklist[0].twitter_user_id, 300);
+
+    // 再次转推 carol 的推文不应推送，之后的原创推文照常推送
+    let original = bot.twitter.tweet(300, "more from carol");
+    bot.twitter.push_tweet(&bot.twitter.retweet(100, &original));
+    bot.twitter
+        .push_tweet(&bot.twitter.tweet(100, "alice again"));
+    wait_for_tweet_count(&bot, USER, 2).await;
+    let tweets = tweet_messages(&bot, USER);
+    assert_eq!(tweets.len(), 2);
+    assert!(tweets[1].text().starts_with("*alice*: alice again"));
+
+    bot.stop().await;
+}
+
+// 最近一次刷新 USER 消息按钮的键盘
+async fn wait_for_keyboard_edit(bot: &TestBot, count: usize) -> Vec<Vec<(String, String)>> {
+    let edits = || {
+        bot.telegram
+            .requests()
+            .into_iter()
+            .filter(|r| r.method == "editMessageReplyMarkup" && r.chat_id() == Some(USER))
+            .collect::<Vec<_>>()
+    };
+    common::wait_until("keyboard refreshed", || edits().len() >= count).await;
+    edits()[count - 1].keyboard()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn follow_and_block_refresh_past_buttons() {
+    let bot = TestBot::start(|conn| {
+        seed_user(conn, USER);
+        seed_follow(conn, USER, 100);
+    })
+    .await;
+    bot.twitter.add_user(100, "alice");
+    bot.twitter.add_user(300, "carol");
+    bot.twitter.wait_for_stream_following(100).await;
+
+    let original = bot.twitter.tweet(300, "from carol");
+    bot.twitter.push_tweet(&bot.twitter.retweet(100, &original));
+    let messages = bot.telegram.wait_for_messages(USER, 1).await;
+
+    // 从按钮关注转推作者后，原消息的关注按钮变为取消关注
+    bot.telegram
+        .press_button(USER, &messages[0], "/FollowTwitterID 300 100");
+    assert_eq!(
+        wait_for_keyboard_edit(&bot, 1).await,
+        vec![vec![
+            (
+                "🚫RTer".to_string(),
+                "/BlockTwitterID 2 300 100".to_string()
+            ),
+            ("❌RT".to_string(), "/UnfollowTwitterID 300".to_string()),
+            ("🚫RT(0)".to_string(), "/BlockTwitterID 1 100 0".to_string()),
+            ("❌".to_string(), "/UnfollowTwitterID 100".to_string()),
+        ]]
+    );
+
+    // 屏蔽转推作者后，转推者的屏蔽计数更新
+    bot.telegram
+        .press_button(USER, &messages[0], "/BlockTwitterID 2 300 100");
+    assert_eq!(
+        wait_for_keyboard_edit(&bot, 2).await[0][2],
+        ("🚫RT(1)".to_string(), "/BlockTwitterID 1 100 0".to_string())
+    );
+
+    bot.stop().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn duplicate_tweet_is_forwarded_once() {
+    let bot = TestBot::start(|conn| {
+        seed_user(conn, USER);
+        seed_follow(conn, USER, 100);
+    })
+    .await;
+    bot.twitter.add_user(100, "alice");
+    bot.twitter.wait_for_stream_following(100).await;
+
+    let tweet = bot.twitter.tweet(100, "once");
+    bot.twitter.push_tweet(&tweet);
+    bot.twitter.push_tweet(&tweet);
+    bot.twitter.push_tweet(&bot.twitter.tweet(100, "twice"));
+    wait_for_tweet_count(&bot, USER, 2).await;
+
+    let tweets = tweet_messages(&bot, USER);
+    assert_eq!(tweets.len(), 2);
+    assert!(tweets[0].text().contains("once"));
+    assert!(tweets[1].text().contains("twice"));
+
+    bot.stop().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn forward_history_is_flushed_on_shutdown() {
+    let bot = TestBot::start(|conn| {
+        seed_user(conn, USER);
+        seed_follow(conn, USER, 100);
+    })
+    .await;
+    bot.twitter.add_user(100, "alice");
+    bot.twitter.wait_for_stream_following(100).await;
+
+    let tweet = bot.twitter.tweet(100, "before restart");
+    bot.twitter.push_tweet(&tweet);
+    wait_for_tweet_count(&bot, USER, 1).await;
+    let conn = bot.conn();
+    bot.stop().await;
+
+    // 退出时把推送记录写入数据库
+    let history =
+        forward_history_model::get_forward_history(&conn, chrono::Utc::now().naive_utc()).unwrap();
+    assert_eq!(history.len(), 1);
+
+    // 重启后不再重复推送已经推送过的推文
+    let bot = TestBot::start(|conn| {
+        seed_user(conn, USER);
+        seed_follow(conn, USER, 100);
+        forward_history_model::replace_forward_history(conn, history).unwrap();
+    })
+    .await;
+    bot.twitter.add_user(100, "alice");
+    bot.twitter.wait_for_stream_following(100).await;
+    bot.twitter.push_tweet(&tweet);
+    // 新的模拟服务从头分配 id，跳过上次用过的
+    bot.twitter.new_tweet_id();
+    bot.twitter
+        .push_tweet(&bot.twitter.tweet(100, "after restart"));
+    wait_for_tweet_count(&bot, USER, 1).await;
+
+    let tweets = tweet_messages(&bot, USER);
+    assert_eq!(tweets.len(), 1);
+    assert!(tweets[0].text().contains("after restart"));
+
+    bot.stop().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn undecryptable_token_only_skips_its_user() {
+    let bot = TestBot::start(|conn| {
+        seed_user(conn, USER);
+        seed_user(conn, 2);
+        seed_follow(conn, USER, 100);
+        seed_follow(conn, 2, 100);
+        diesel::update(users::table.filter(users::id.eq(2)))
+            .set(users::twitter_access_token.eq("v1:broken"))
+            .execute(conn)
+            .unwrap();
+    })
+    .await;
+    bot.twitter.add_user(100, "alice");
+    bot.twitter.wait_for_stream_following(100).await;
+    bot.twitter
+        .push_tweet(&bot.twitter.tweet(100, "still here"));
+    wait_for_tweet_count(&bot, USER, 1).await;
+
+    // 列表不需要 token
+    bot.telegram.send_text(common::ADMIN_ID, "/ListUsers");
+    let messages = bot.telegram.wait_for_messages(common::ADMIN_ID, 1).await;
+    assert!(
+        messages[0].text().starts_with("There are 2 users"),
+        "{}",
+        messages[0].text()
+    );
+    assert!(tweet_messages(&bot, 2).is_empty());
+
+    bot.stop().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn deleted_status_only_affects_its_own_message() {
+    let bot = TestBot::start(|conn| {
+        seed_user(conn, USER);
+        seed_user(conn, 2);
+        seed_follow(conn, USER, 300);
+        seed_follow(conn, 2, 100);
+    })
+    .await;
+    bot.twitter.add_user(100, "alice");
+    bot.twitter.add_user(300, "carol");
+    bot.twitter.wait_for_stream_following(100).await;
+    bot.twitter.wait_for_stream_following(300).await;
+
+    // USER 收到 carol 的原推文，2 收到 alice 的转推
+    let original = bot.twitter.tweet(300, "from carol");
+    bot.twitter.push_tweet(&original);
+    wait_for_tweet_count(&bot, USER, 1).await;
+    let retweet = bot.twitter.retweet(100, &original);
+    bot.twitter.push_tweet(&retweet);
+    wait_for_tweet_count(&bot, 2, 1).await;
+    let edits_to = |chat_id: i64| {
+        bot.telegram
+            .requests()
+            .into_iter()
+            .filter(|r| r.method == "editMessageText" && r.chat_id() == Some(chat_id))
+            .count()
+    };
+    common::wait_until("sent messages", || {
+        sent_messages::table
+            .count()
+            .get_result::<i64>(&bot.conn())
+            .unwrap()
+            == 2
+    })
+    .await;
+
+    // 原推文被删除不影响转推的消息
+    bot.twitter.push_delete(&original);
+    common::wait_until("original marked", || edits_to(USER) == 1).await;
+    tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+    assert_eq!(edits_to(2), 0);
+
+    bot.twitter.push_delete(&retweet);
+    common::wait_until("retweet marked", || edits_to(2) == 1).await;
+    assert_eq!(edits_to(USER), 1);
+
+    bot.stop().await;
+}
+
+async fn wait_for_tweet_count(bot: &TestBot, chat_id: i64, count: usize) {
+    common::wait_until(&format!("{} tweets to {}", count, chat_id), || {
+        tweet_messages(bot, chat_id).len() >= count
+    })
+    .await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn shared_follow_is_forwarded_to_every_follower() {
+    let bot = TestBot::start(|conn| {
+        seed_user(conn, USER);
+        seed_user(conn, 2);
+        seed_follow(conn, USER, 100);
+        seed_follow(conn, 2, 100);
+    })
+    .await;
+    bot.twitter.add_user(100, "alice");
+    bot.twitter.wait_for_stream_following(100).await;
+
+    assert_eq!(bot.twitter.push_tweet(&bot.twitter.tweet(100, "hi all")), 1);
+    wait_for_tweet_count(&bot, USER, 1).await;
+    wait_for_tweet_count(&bot, 2, 1).await;
+
+    // 其中一人取消订阅后另一人照常收到
+    bot.telegram.send_text(2, "/UnfollowTwitterID 100");
+    bot.telegram.wait_for_messages(2, 2).await;
+    bot.twitter
+        .push_tweet(&bot.twitter.tweet(100, "still here"));
+    wait_for_tweet_count(&bot, USER, 2).await;
+    assert_eq!(tweet_messages(&bot, 2).len(), 1);
+
+    bot.stop().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn why_not_explains_delivery_decisions() {
+    let bot = TestBot::start(|conn| {
+        seed_user(conn, USER);
+        seed_follow(conn, USER, 100);
+        user_model::update_disable_retweet(conn, USER, true).unwrap();
+    })
+    .await;
+    bot.twitter.add_user(100, "alice");
+    bot.twitter.add_user(300, "carol");
+    bot.twitter.wait_for_stream_following(100).await;
+
+    let tweet = bot.twitter.tweet(100, "once");
+    bot.twitter.push_tweet(&tweet);
+    bot.twitter.push_tweet(&tweet);
+    wait_for_tweet_count(&bot, USER, 1).await;
+
+    let retweet = bot
+        .twitter
+        .retweet(100, &bot.twitter.tweet(300, "from carol"));
+    bot.twitter.push_tweet(&retweet);
+    let tweet_id = tweet["id"].as_i64().unwrap();
+    let retweet_id = retweet["id"].as_i64().unwrap();
+    common::wait_until("delivery logs", || {
+        delivery_log_model::get_logs_by_tweet(&bot.conn(), USER, retweet_id)
+            .unwrap()
+            .len()
+            == 1
+    })
+    .await;
+
+    bot.telegram.send_text(
+        USER,
+        &format!("/WhyNot https://twitter.com/alice/status/{}", tweet_id),
+    );
+    let messages = bot.telegram.wait_for_messages(USER, 2).await;
+    let text = messages[1].text();
+    assert!(
+        text.contains("via @alice: skipped, it was already forwarded to you"),
+        "{}",
+        text
+    );
+    assert!(text.contains("via @alice: delivered"), "{}", text);
+
+    // 用转推本身的链接也能查到
+    bot.telegram
+        .send_text(USER, &format!("/WhyNot {}", retweet_id));
+    let messages = bot.telegram.wait_for_messages(USER, 3).await;
+    assert!(messages[2]
+        .text()
+        .contains("via @alice: skipped, retweets are disabled by /SetDisableRetweet"));
+
+    bot.telegram.send_text(USER, "/WhyNot 999999");
+    let messages = bot.telegram.wait_for_messages(USER, 4).await;
+    assert!(messages[3].text().starts_with("No delivery record"));
+
+    // 每个用户只保留最近的记录
+    delivery_log_model::prune_logs(&bot.conn(), 1).unwrap();
+    assert!(
+        delivery_log_model::get_logs_by_tweet(&bot.conn(), USER, tweet_id)
+            .unwrap()
+            .is_empty()
+    );
+    assert_eq!(
+        delivery_log_model::get_logs_by_tweet(&bot.conn(), USER, retweet_id)
+            .unwrap()
+            .len(),
+        1
+    );
+
+    bot.stop().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn dry_run_sends_to_admin_instead_of_user() {
+    let bot = TestBot::start_with(
+        |conn| {
+            seed_user(conn, USER);
+            seed_follow(conn, USER, 100);
+        },
+        |config| {
+            config.forwarder.dry_run = true;
+            config.forwarder.dry_run_to_admin = true;
+        },
+    )
+    .await;
+    bot.twitter.add_user(100, "alice");
+    bot.twitter.wait_for_stream_following(100).await;
+
+    let tweet = bot.twitter.tweet(100, "rehearsal");
+    bot.twitter.push_tweet(&tweet);
+    wait_for_tweet_count(&bot, common::ADMIN_ID, 1).await;
+    let message = &tweet_messages(&bot, common::ADMIN_ID)[0];
+    assert!(
+        message
+            .text()
+            .starts_with("\\[dry\\-run for user 1\\]\n*alice*: rehearsal"),
+        "{}",
+        message.text()
+    );
+    // 不带按钮
+    assert!(message.keyboard().is_empty());
+
+    let tweet_id = tweet["id"].as_i64().unwrap();
+    common::wait_until("dry-run log", || {
+        delivery_log_model::get_logs_by_tweet(&bot.conn(), USER, tweet_id)
+            .unwrap()
+            .len()
+            == 1
+    })
+    .await;
+    let logs = delivery_log_model::get_logs_by_tweet(&bot.conn(), USER, tweet_id).unwrap();
+    assert_eq!(
+        logs[0].decision,
+        delivery_log_model::Decision::DryRun.toi32()
+    );
+    assert_eq!(logs[0].detail, "sent to admin");
+    assert!(bot.telegram.messages_to(USER).is_empty());
+
+    bot.stop().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn set_dry_run_only_affects_one_user() {
+    let bot = TestBot::start(|conn| {
+        seed_user(conn, USER);
+        seed_user(conn, 2);
+        seed_follow(conn, USER, 100);
+        seed_follow(conn, 2, 100);
+    })
+    .await;
+    bot.twitter.add_user(100, "alice");
+    bot.twitter.wait_for_stream_following(100).await;
+
+    bot.telegram
+        .send_text(common::ADMIN_ID, "/SetDryRun 2 true");
+    let messages = bot.telegram.wait_for_messages(common::ADMIN_ID, 1).await;
+    assert!(
+        messages[0].text().contains("Success"),
+        "{}",
+        messages[0].text()
+    );
+    assert!(user_model::get_user_by_id(&bot.conn(), 2).unwrap().dry_run);
+
+    let tweet = bot.twitter.tweet(100, "only one");
+    bot.twitter.push_tweet(&tweet);
+    wait_for_tweet_count(&bot, USER, 1).await;
+    let tweet_id = tweet["id"].as_i64().unwrap();
+    common::wait_until("dry-run log", || {
+        delivery_log_model::get_logs_by_tweet(&bot.conn(), 2, tweet_id)
+            .unwrap()
+            .len()
+            == 1
+    })
+    .await;
+    // 默认只写日志
+    assert!(tweet_messages(&bot, 2).is_empty());
+    assert!(tweet_messages(&bot, common::ADMIN_ID).is_empty());
+
+    bot.telegram.send_text(2, &format!("/WhyNot {}", tweet_id));
+    let messages = bot.telegram.wait_for_messages(2, 1).await;
+    assert!(
+        messages[0]
+            .text()
+            .contains("not sent, dry\\-run mode, logged"),
+        "{}",
+        messages[0].text()
+    );
+
+    // 关闭试运行后同一条推文仍会推送
+    bot.telegram
+        .send_text(common::ADMIN_ID, "/SetDryRun 2 false");
+    bot.telegram.wait_for_messages(common::ADMIN_ID, 2).await;
+    bot.twitter.push_tweet(&tweet);
+    wait_for_tweet_count(&bot, 2, 1).await;
+    assert_eq!(tweet_messages(&bot, USER).len(), 1);
+
+    bot.stop().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn deleted_tweet_is_marked_or_removed() {
+    let bot = TestBot::start(|conn| {
+        seed_user(conn, USER);
+        seed_user(conn, 2);
+        seed_follow(conn, USER, 100);
+        seed_follow(conn, 2, 100);
+    })
+    .await;
+    bot.twitter.add_user(100, "alice");
+    bot.twitter.wait_for_stream_following(100).await;
+
+    bot.telegram.send_text(2, "/SetRemoveDeleted true");
+    bot.telegram.wait_for_messages(2, 1).await;
+    assert!(
+        user_model::get_user_by_id(&bot.conn(), 2)
+            .unwrap()
+            .remove_deleted
+    );
+
+    let tweet = bot.twitter.tweet(100, "oops");
+    bot.twitter.push_tweet(&tweet);
+    let sent_message_id = |chat_id: i64| {
+        sent_messages::table
+            .filter(sent_messages::user_id.eq(chat_id))
+            .select(sent_messages::message_id)
+            .first::<i32>(&bot.conn())
+            .ok()
+    };
+    common::wait_until("sent messages", || {
+        sent_message_id(USER).is_some() && sent_message_id(2).is_some()
+    })
+    .await;
+    let (message_1, message_2) = (sent_message_id(USER).unwrap(), sent_message_id(2).unwrap());
+    let forwarded = tweet_messages(&bot, USER)[0].text().to_string();
+
+    bot.twitter.push_delete(&tweet);
+    let requests_to = |chat_id: i64, method: &str| {
+        bot.telegram
+            .requests()
+            .into_iter()
+            .filter(|r| r.method == method && r.chat_id() == Some(chat_id))
+            .collect::<Vec<_>>()
+    };
+    common::wait_until("deleted tweet handled", || {
+        !requests_to(USER, "editMessageText").is_empty()
+            && !requests_to(2, "deleteMessage").is_empty()
+    })
+    .await;
+
+    // 默认在原消息前标记，设置后删除消息
+    let edited = &requests_to(USER, "editMessageText")[0];
+    assert_eq!(edited.text(), format!("🗑 deleted\n{}", forwarded));
+    assert_eq!(edited.params["message_id"], message_1);
+    assert_eq!(
+        requests_to(2, "deleteMessage")[0].params["message_id"],
+        message_2
+    );
+    assert!(requests_to(2, "editMessageText").is_empty());
+    // 对应关系用过即删除
+    assert!(sent_message_id(USER).is_none() && sent_message_id(2).is_none());
+
+    bot.stop().await;
+}
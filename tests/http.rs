@@ -0,0 +1,179 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use hyper::{body, Body, Client, Method, Request, StatusCode};
+use serde_json::Value;
+
+use common::{seed_follow, seed_user, TestBot, ADMIN_ID};
+
+const USER: i64 = 1;
+
+async fn get(url: &str) -> (StatusCode, Value) {
+    let resp = Client::new().get(url.parse().unwrap()).await.unwrap();
+    let status = resp.status();
+    let bytes = body::to_bytes(resp.into_body()).await.unwrap();
+    (status, serde_json::from_slice(&bytes).unwrap())
+}
+
+// 读取 /metrics 中不带标签的指标值，指标第一次使用前不输出，视为 0
+async fn metric(listen: &std::net::SocketAddr, name: &str) -> f64 {
+    let resp = Client::new()
+        .get(format!("http://{}/metrics", listen).parse().unwrap())
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let bytes = body::to_bytes(resp.into_body()).await.unwrap();
+    String::from_utf8(bytes.to_vec())
+        .unwrap()
+        .lines()
+        .find_map(|l| l.strip_prefix(&format!("{} ", name)))
+        .map_or(0.0, |v| v.parse().unwrap())
+}
+
+fn free_addr() -> std::net::SocketAddr {
+    std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+}
+
+// 轮询直到返回期望的状态码
+async fn wait_for_status(url: &str, expected: StatusCode) -> Value {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    loop {
+        let (status, body) = get(url).await;
+        if status == expected {
+            return body;
+        }
+        assert!(
+            Instant::now() < deadline,
+            "{} is still {}: {}",
+            url,
+            status,
+            body
+        );
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn readyz_follows_stream_health() {
+    let listen = free_addr();
+    let bot = TestBot::start_with(
+        |conn| {
+            seed_user(conn, USER);
+            seed_follow(conn, USER, 100);
+        },
+        |config| {
+            config.http.listen = listen.to_string();
+            // 重连前留出足够的时间检查 503
+            config.twitter.stream_retry_secs = 3;
+        },
+    )
+    .await;
+    bot.twitter.add_user(100, "alice");
+    bot.twitter.wait_for_stream_following(100).await;
+    let readyz = format!("http://{}/readyz", listen);
+
+    // 连接后收到消息才算就绪
+    let tweet = bot.twitter.tweet(100, "hello");
+    bot.twitter.push_tweet(&tweet);
+    let body = wait_for_status(&readyz, StatusCode::OK).await;
+    assert_eq!(body["alive"], true);
+    assert_eq!(body["database"]["ok"], true);
+    let streams = body["streams"].as_object().unwrap();
+    assert_eq!(streams.len(), 1);
+    let stream = streams.values().next().unwrap();
+    assert_eq!(stream["status"], "connected");
+    assert_eq!(stream["follows"], 1);
+
+    // 重复的推文计入 deduped
+    bot.telegram.wait_for_messages(USER, 1).await;
+    let deduped = metric(&listen, "t2t_tweets_deduped_total").await;
+    bot.twitter.push_tweet(&tweet);
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while metric(&listen, "t2t_tweets_deduped_total").await < deduped + 1.0 {
+        assert!(Instant::now() < deadline, "deduped counter did not move");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    // stream 断开等待重连时不就绪，但仍然存活
+    bot.twitter.close_streams();
+    let body = wait_for_status(&readyz, StatusCode::SERVICE_UNAVAILABLE).await;
+    assert_eq!(body["ready"], false);
+    let stream = body["streams"]
+        .as_object()
+        .unwrap()
+        .values()
+        .next()
+        .unwrap();
+    assert_eq!(stream["status"], "retrying");
+    assert_eq!(stream["last_error"], "stream closed");
+    let (status, _) = get(&format!("http://{}/healthz", listen)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    bot.twitter.wait_for_stream_following(100).await;
+    bot.twitter
+        .push_tweet(&bot.twitter.tweet(100, "back again"));
+    wait_for_status(&readyz, StatusCode::OK).await;
+
+    bot.stop().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn webhook_rejects_wrong_secret() {
+    let listen = free_addr();
+    let bot = TestBot::start_with(
+        |conn| seed_user(conn, USER),
+        |config| {
+            config.telegram.webhook.url = "https://bot.example.com/hook".to_string();
+            config.telegram.webhook.listen = listen.to_string();
+            config.telegram.webhook.secret = "s3cret".to_string();
+        },
+    )
+    .await;
+    bot.telegram.wait_for_method("setWebhook").await;
+    let set_webhook = bot
+        .telegram
+        .requests()
+        .into_iter()
+        .find(|r| r.method == "setWebhook")
+        .unwrap();
+    assert_eq!(set_webhook.params["url"], "https://bot.example.com/hook");
+    assert_eq!(set_webhook.params["secret_token"], "s3cret");
+
+    let post = |secret: Option<&str>, text: &str| {
+        let mut update = bot.telegram.text_update(ADMIN_ID, text);
+        update["update_id"] = 1.into();
+        let mut req = Request::builder()
+            .method(Method::POST)
+            .uri(format!("http://{}/hook", listen))
+            .header("content-type", "application/json");
+        if let Some(secret) = secret {
+            req = req.header("x-telegram-bot-api-secret-token", secret);
+        }
+        let req = req.body(Body::from(update.to_string())).unwrap();
+        async move { Client::new().request(req).await.unwrap().status() }
+    };
+
+    assert_eq!(post(None, "/ListUsers").await, StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        post(Some("wrong"), "/ListUsers").await,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(post(Some("s3cret"), "/ListUsers").await, StatusCode::OK);
+    // 只处理带正确 secret 的更新
+    let messages = bot.telegram.wait_for_messages(ADMIN_ID, 1).await;
+    assert!(messages[0].text().starts_with("There are 1 users"));
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert_eq!(bot.telegram.messages_to(ADMIN_ID).len(), 1);
+
+    // 退出时删除 webhook
+    let telegram = bot.telegram.clone();
+    bot.stop().await;
+    assert!(telegram
+        .requests()
+        .iter()
+        .any(|r| r.method == "deleteWebhook"));
+}
@@ -0,0 +1,70 @@
+mod common;
+
+use std::fs;
+
+use twitter2telegram::recorder::Recorder;
+
+use common::{seed_follow, seed_user, TestBot};
+
+const USER: i64 = 1;
+
+// 录制用的是全局状态，这个文件里只启动一个 bot
+#[tokio::test(flavor = "multi_thread")]
+async fn stream_messages_are_recorded() {
+    let file = std::env::temp_dir().join(format!("t2t-record-{}.jsonl", std::process::id()));
+    let _ = fs::remove_file(&file);
+    let path = file.to_str().unwrap().to_string();
+    let bot = TestBot::start_with(
+        |conn| {
+            seed_user(conn, USER);
+            seed_follow(conn, USER, 100);
+        },
+        |config| config.recorder.file = path,
+    )
+    .await;
+    bot.twitter.add_user(100, "alice");
+    bot.twitter.wait_for_stream_following(100).await;
+
+    let tweets = [
+        bot.twitter.tweet(100, "first"),
+        bot.twitter.tweet(100, "second"),
+    ];
+    for t in &tweets {
+        bot.twitter.push_tweet(t);
+    }
+    bot.telegram.wait_for_messages(USER, 2).await;
+    bot.stop().await;
+
+    // 连接时的保活空行不录制
+    let recorded = fs::read_to_string(&file).unwrap();
+    let lines = recorded
+        .lines()
+        .map(|l| serde_json::from_str::<serde_json::Value>(l).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(lines, tweets);
+}
+
+#[test]
+fn recorder_rotates_files() {
+    let dir = std::env::temp_dir().join(format!("t2t-rotate-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let file = dir.join("stream.jsonl");
+    let line = format!("{{\"n\":\"{}\"}}", "x".repeat(20));
+
+    let mut recorder = Recorder::open(&file, 60, 2).unwrap();
+    for _ in 0..7 {
+        recorder.write(&line).unwrap();
+    }
+    drop(recorder);
+    // 每个文件放两行，最旧的一个被删除
+    let read = |name: &str| fs::read_to_string(dir.join(name)).unwrap().lines().count();
+    assert_eq!(read("stream.jsonl"), 1);
+    assert_eq!(read("stream.jsonl.1"), 2);
+    assert_eq!(read("stream.jsonl.2"), 2);
+    assert!(!dir.join("stream.jsonl.3").exists());
+
+    // 重新打开时接着写当前文件
+    let mut recorder = Recorder::open(&file, 60, 2).unwrap();
+    recorder.write(&line).unwrap();
+    assert_eq!(read("stream.jsonl"), 2);
+}
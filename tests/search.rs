@@ -0,0 +1,138 @@
+mod common;
+
+use common::{seed_follow, seed_user, wait_until, TestBot};
+use twitter2telegram::models::tweet_model::{self, SearchFilter};
+
+fn archived(bot: &TestBot, uid: i64, author: &str) -> i64 {
+    let filter = SearchFilter {
+        author: Some(author.to_string()),
+        ..SearchFilter::default()
+    };
+    tweet_model::search_tweets(&bot.conn(), uid, &filter, 0, 1)
+        .unwrap()
+        .0
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn search_archived_tweets() {
+    let bot = TestBot::start(|conn| {
+        seed_user(conn, 1);
+        seed_user(conn, 2);
+        seed_follow(conn, 1, 100);
+        seed_follow(conn, 2, 200);
+    })
+    .await;
+    bot.twitter.add_user(100, "alice");
+    bot.twitter.add_user(200, "bob");
+    bot.twitter.wait_for_stream_following(100).await;
+    bot.twitter.wait_for_stream_following(200).await;
+
+    let rust = bot.twitter.tweet(100, "rust is great");
+    bot.twitter.push_tweet(&rust);
+    for i in 0..11 {
+        bot.twitter
+            .push_tweet(&bot.twitter.tweet(100, &format!("news {}", i)));
+    }
+    bot.twitter
+        .push_tweet(&bot.twitter.tweet(200, "rust from bob"));
+    bot.telegram.wait_for_messages(1, 12).await;
+    bot.telegram.wait_for_messages(2, 1).await;
+    wait_until("tweets archived", || {
+        archived(&bot, 1, "alice") == 12 && archived(&bot, 2, "bob") == 1
+    })
+    .await;
+
+    // 只能搜到自己收到过的推文
+    let filter = SearchFilter {
+        query: "rust".to_string(),
+        ..SearchFilter::default()
+    };
+    let (_, found) = tweet_model::search_tweets(&bot.conn(), 1, &filter, 0, 10).unwrap();
+    assert_eq!(found.len(), 1);
+    bot.telegram.send_text(1, "/Search rust");
+    let messages = bot.telegram.wait_for_messages(1, 13).await;
+    assert_eq!(
+        messages[12].text(),
+        format!(
+            "Found 1 tweets, page 1/1\n\\* *alice* {} [rust is great](https://twitter.com/alice/status/{})\n",
+            found[0].created_at.format("%Y\\-%m\\-%d %H:%M"),
+            rust["id"]
+        )
+    );
+
+    // 分页
+    bot.telegram.send_text(1, "/Search news from:@Alice");
+    let messages = bot.telegram.wait_for_messages(1, 14).await;
+    assert!(messages[13].text().starts_with("Found 11 tweets, page 1/2"));
+    assert_eq!(
+        messages[13].keyboard(),
+        vec![vec![(
+            "Next▶️".to_string(),
+            "/Search news from:@Alice page:2".to_string()
+        )]]
+    );
+    bot.telegram
+        .press_button(1, &messages[13], "/Search news from:@Alice page:2");
+    let messages = bot.telegram.wait_for_messages(1, 15).await;
+    assert!(messages[14].text().starts_with("Found 11 tweets, page 2/2"));
+    assert_eq!(messages[14].text().lines().count(), 2);
+
+    // 日期过滤
+    bot.telegram.send_text(1, "/Search rust until:2020-01-01");
+    let messages = bot.telegram.wait_for_messages(1, 16).await;
+    assert_eq!(messages[15].text(), "No tweets found");
+
+    bot.telegram.send_text(2, "/Search rust");
+    let messages = bot.telegram.wait_for_messages(2, 2).await;
+    assert!(messages[1].text().contains("rust from bob"));
+    assert!(!messages[1].text().contains("alice"));
+
+    bot.stop().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn search_cjk_text() {
+    let bot = TestBot::start(|conn| {
+        seed_user(conn, 1);
+        seed_follow(conn, 1, 100);
+    })
+    .await;
+    bot.twitter.add_user(100, "alice");
+    bot.twitter.wait_for_stream_following(100).await;
+
+    let sunny = bot.twitter.tweet(100, "今天天气很好，去公园散步");
+    bot.twitter.push_tweet(&sunny);
+    bot.twitter
+        .push_tweet(&bot.twitter.tweet(100, "明天可能下雨 100%"));
+    bot.telegram.wait_for_messages(1, 2).await;
+    wait_until("tweets archived", || archived(&bot, 1, "alice") == 2).await;
+
+    let search = |query: &str| {
+        let filter = SearchFilter {
+            query: query.to_string(),
+            ..SearchFilter::default()
+        };
+        let (_, found) = tweet_model::search_tweets(&bot.conn(), 1, &filter, 0, 10).unwrap();
+        found.into_iter().map(|t| t.text).collect::<Vec<_>>()
+    };
+    // 三个字及以上走全文索引，更短的词逐字匹配
+    assert_eq!(search("天气很好"), vec!["今天天气很好，去公园散步"]);
+    assert_eq!(search("天气"), vec!["今天天气很好，去公园散步"]);
+    assert_eq!(search("下雨"), vec!["明天可能下雨 100%"]);
+    assert_eq!(search("天 散步"), vec!["今天天气很好，去公园散步"]);
+    assert_eq!(search("下雨 公园散步").len(), 0);
+    // 通配符按普通字符匹配
+    assert_eq!(search("%"), vec!["明天可能下雨 100%"]);
+    assert_eq!(search("_").len(), 0);
+
+    bot.telegram.send_text(1, "/Search 公园");
+    let messages = bot.telegram.wait_for_messages(1, 3).await;
+    assert!(
+        messages[2].text().starts_with("Found 1 tweets"),
+        "{}",
+        messages[2].text()
+    );
+    assert!(messages[2].text().contains(&sunny["id"].to_string()));
+
+    bot.stop().await;
+}
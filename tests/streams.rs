@@ -0,0 +1,177 @@
+mod common;
+
+use serde_json::json;
+use twitter2telegram::health;
+
+use common::{seed_follow, seed_user, wait_until, TestBot};
+
+const USER: i64 = 1;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn quick_follows_restart_stream_once() {
+    let bot = TestBot::start(|conn| {
+        seed_user(conn, USER);
+        seed_follow(conn, USER, 100);
+    })
+    .await;
+    bot.twitter.wait_for_stream_following(100).await;
+    assert_eq!(bot.twitter.connections(), 1);
+
+    for id in [200, 201, 202] {
+        bot.twitter.add_user(id, &format!("user{}", id));
+        bot.telegram
+            .send_text(USER, &format!("/FollowTwitterID {} 0", id));
+    }
+    bot.telegram.wait_for_messages(USER, 3).await;
+    bot.twitter.wait_for_stream_following(202).await;
+
+    // 旧连接关闭，只剩一个包含全部 follow 的连接
+    wait_until("old stream closed", || {
+        bot.twitter.open_streams().len() == 1
+    })
+    .await;
+    let mut follows = bot.twitter.open_streams().remove(0);
+    follows.sort_unstable();
+    assert_eq!(follows, vec![100, 200, 201, 202]);
+    assert_eq!(bot.twitter.connections(), 2);
+
+    bot.stop().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn stream_reconnects_after_disconnect() {
+    let bot = TestBot::start(|conn| {
+        seed_user(conn, USER);
+        seed_follow(conn, USER, 100);
+    })
+    .await;
+    bot.twitter.add_user(100, "alice");
+    bot.twitter.wait_for_stream_following(100).await;
+
+    bot.twitter.close_streams();
+    bot.twitter.wait_for_stream_following(100).await;
+    assert_eq!(bot.twitter.connections(), 2);
+
+    bot.twitter
+        .push_tweet(&bot.twitter.tweet(100, "back again"));
+    let messages = bot.telegram.wait_for_messages(USER, 1).await;
+    assert!(messages[0].text().starts_with("*alice*: back again"));
+
+    bot.stop().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn follows_respect_token_cap_and_rebalance() {
+    let bot = TestBot::start_with(
+        |conn| {
+            seed_user(conn, USER);
+            seed_user(conn, 2);
+            for id in [100, 101, 102] {
+                seed_follow(conn, USER, id);
+            }
+        },
+        |config| config.twitter.max_follows_per_token = 2,
+    )
+    .await;
+    wait_until("follows spread over two streams", || {
+        subscribed(&bot) == vec![100, 101, 102] && bot.twitter.open_streams().len() == 2
+    })
+    .await;
+
+    // 移除第二个用户后只剩一个 token，放不下的账号等待分配并提醒管理员
+    bot.telegram.send_text(common::ADMIN_ID, "/RemoveUser 2");
+    wait_until("capacity warning", || {
+        bot.telegram
+            .messages_to(common::ADMIN_ID)
+            .iter()
+            .any(|m| m.text().contains("capacity exhausted"))
+    })
+    .await;
+    wait_until("one stream left", || {
+        let streams = bot.twitter.open_streams();
+        streams.len() == 1 && streams[0].len() == 2
+    })
+    .await;
+
+    // 取消订阅空出位置后，等待中的账号补上
+    bot.telegram.send_text(USER, "/UnfollowTwitterID 100");
+    wait_until("pending account subscribed", || {
+        subscribed(&bot) == vec![101, 102]
+    })
+    .await;
+
+    bot.stop().await;
+}
+
+// 所有 stream 订阅的账号
+fn subscribed(bot: &TestBot) -> Vec<u64> {
+    let mut follows: Vec<u64> = bot.twitter.open_streams().into_iter().flatten().collect();
+    follows.sort_unstable();
+    follows.dedup();
+    follows
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn control_messages_are_counted_and_revoked_token_removed() {
+    // 单独的用户，health 里的统计不和其他测试共用同一个 token
+    const OWNER: i64 = 7;
+    let bot = TestBot::start(|conn| {
+        seed_user(conn, OWNER);
+        seed_follow(conn, OWNER, 100);
+    })
+    .await;
+    bot.twitter.wait_for_stream_following(100).await;
+    let admin_messages = |text: &str| {
+        bot.telegram
+            .messages_to(common::ADMIN_ID)
+            .iter()
+            .filter(|m| m.text().contains(text))
+            .count()
+    };
+
+    // 连接过多时提醒管理员，然后照常重连
+    bot.twitter.push_control(&json!({
+        "disconnect": {"code": 2, "stream_name": "test", "reason": "Duplicate stream"}
+    }));
+    wait_until("duplicate stream warning", || {
+        admin_messages("connected too many times") == 1
+    })
+    .await;
+    bot.twitter.wait_for_stream_following(100).await;
+    assert_eq!(bot.twitter.connections(), 2);
+
+    bot.twitter
+        .push_control(&json!({"limit": {"track": 42, "timestamp_ms": "1700000000000"}}));
+    bot.twitter.push_control(&json!({
+        "warning": {"code": "FALLING_BEHIND", "message": "queue is 60% full", "percent_full": 60}
+    }));
+    let counted = |kind: &str| {
+        health::streams()
+            .iter()
+            .filter_map(|(_, s)| s.control_messages.get(kind).copied())
+            .sum::<u64>()
+    };
+    wait_until("control messages counted", || {
+        counted("limit") == 1 && counted("warning") == 1
+    })
+    .await;
+    assert_eq!(counted("disconnect"), 1);
+
+    // token 被撤销时直接移除，不再重连
+    bot.twitter.push_control(&json!({
+        "disconnect": {"code": 6, "stream_name": "test", "reason": "Token revoked"}
+    }));
+    wait_until("token removed", || {
+        admin_messages("has been revoked") == 1
+            && bot
+                .telegram
+                .messages_to(OWNER)
+                .iter()
+                .any(|m| m.text().contains("authorization has expired"))
+    })
+    .await;
+    assert!(bot.twitter.open_streams().is_empty());
+    assert_eq!(bot.twitter.connections(), 2);
+
+    bot.stop().await;
+}
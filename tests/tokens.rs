@@ -0,0 +1,88 @@
+mod common;
+
+use diesel::prelude::*;
+use twitter2telegram::{
+    config::DatabaseConfig,
+    models::{schema::users, DbConnection},
+    token_cipher::{self, KeyState},
+};
+
+use common::{seed_follow, seed_user, token_json, TestBot, ADMIN_ID, TOKEN_KEY};
+
+const NEW_KEY: &str = "ZmVkY2JhOTg3NjU0MzIxMGZlZGNiYTk4NzY1NDMyMTA=";
+
+fn stored_token(conn: &DbConnection, uid: i64) -> String {
+    users::table
+        .select(users::twitter_access_token)
+        .filter(users::id.eq(uid))
+        .first::<Option<String>>(conn)
+        .unwrap()
+        .unwrap()
+}
+
+fn key_config(token_key: &str, old_token_keys: &[&str]) -> DatabaseConfig {
+    DatabaseConfig {
+        token_key: token_key.to_string(),
+        old_token_keys: old_token_keys.iter().map(|k| k.to_string()).collect(),
+        ..DatabaseConfig::default()
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn tokens_are_encrypted_and_rotated() {
+    let mut old_token = String::new();
+    let bot = TestBot::start_with(
+        |conn| {
+            // 用户 1 是加密上线前的明文 token，用户 2 用旧密钥加密
+            seed_user(conn, 1);
+            diesel::update(users::table.filter(users::id.eq(1)))
+                .set(users::twitter_access_token.eq(token_json(1)))
+                .execute(conn)
+                .unwrap();
+            token_cipher::init(&key_config(TOKEN_KEY, &[])).unwrap();
+            seed_user(conn, 2);
+            old_token = stored_token(conn, 2);
+            seed_follow(conn, 1, 100);
+            seed_follow(conn, 2, 100);
+        },
+        |config| {
+            config.database.token_key = NEW_KEY.to_string();
+            config.database.old_token_keys = vec![TOKEN_KEY.to_string()];
+        },
+    )
+    .await;
+
+    // 启动时明文被加密，旧密钥加密的保持不变
+    let stored = stored_token(&bot.conn(), 1);
+    assert!(stored.starts_with("v1:"), "{}", stored);
+    assert!(!stored.contains("access1"));
+    assert_eq!(stored_token(&bot.conn(), 2), old_token);
+
+    // 两个 token 都能正常使用
+    bot.twitter.add_user(100, "alice");
+    bot.twitter.wait_for_stream_following(100).await;
+    bot.twitter.push_tweet(&bot.twitter.tweet(100, "secret"));
+    bot.telegram.wait_for_messages(1, 1).await;
+    bot.telegram.wait_for_messages(2, 1).await;
+
+    bot.telegram.send_text(ADMIN_ID, "/RotateTokenKey");
+    let messages = bot.telegram.wait_for_messages(ADMIN_ID, 1).await;
+    assert!(
+        messages[0].text().starts_with("Re\\-encrypted 1 tokens"),
+        "{}",
+        messages[0].text()
+    );
+    assert_ne!(stored_token(&bot.conn(), 2), old_token);
+    let conn = bot.conn();
+    bot.stop().await;
+
+    // 轮换后只用新密钥就能解密
+    token_cipher::init(&key_config(NEW_KEY, &[])).unwrap();
+    for uid in [1, 2] {
+        let (plain, state) = token_cipher::decrypt(uid, &stored_token(&conn, uid)).unwrap();
+        assert_eq!(plain, token_json(uid));
+        assert_eq!(state, KeyState::Current);
+    }
+    // 密文绑定了用户 id
+    assert!(token_cipher::decrypt(2, &stored_token(&conn, 1)).is_err());
+}